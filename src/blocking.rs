@@ -0,0 +1,139 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::{sync::Notify, time::Instant};
+
+// 等待队列中的一个客户端: (client id, 唤醒通知)
+type Waiter = (u64, Arc<Notify>);
+
+/// 阻塞在key上的客户端(BLPOP等), 每个(db, key)一个按阻塞先后排列的队列
+#[derive(Debug, Default)]
+pub struct BlockedClients {
+    queues: Mutex<HashMap<(usize, Bytes), VecDeque<Waiter>>>,
+}
+
+impl BlockedClients {
+    /// 在db的keys上排队; 需要在持有这些key所在分片的锁时调用, 否则会错过在此之前写入的数据
+    pub fn block(&self, db: usize, keys: &[Bytes], id: u64) -> BlockGuard<'_> {
+        let notify = Arc::new(Notify::new());
+        let mut queues = self.queues.lock().unwrap();
+        for key in keys {
+            let queue = queues.entry((db, key.clone())).or_default();
+            // 同一个key出现多次时只排一次队
+            if !queue.iter().any(|(waiter, _)| *waiter == id) {
+                queue.push_back((id, notify.clone()));
+            }
+        }
+        BlockGuard {
+            blocked: self,
+            db,
+            keys: keys.to_vec(),
+            id,
+            notify,
+        }
+    }
+
+    /// key可能有了新数据, 唤醒排在最前面的客户端; 它没有取到数据时会继续排在队首
+    pub fn wake(&self, db: usize, key: &Bytes) {
+        let queues = self.queues.lock().unwrap();
+        if let Some((_, notify)) = queues.get(&(db, key.clone())).and_then(|q| q.front()) {
+            notify.notify_one();
+        }
+    }
+
+    /// db中有客户端在等待的key, SWAPDB之后需要逐个检查
+    pub fn blocked_keys(&self, db: usize) -> Vec<Bytes> {
+        let queues = self.queues.lock().unwrap();
+        queues
+            .keys()
+            .filter(|(i, _)| *i == db)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+}
+
+/// 阻塞命令的超时时刻, 超时为0表示一直阻塞
+pub fn deadline(timeout: Duration) -> Option<Instant> {
+    if timeout.is_zero() {
+        None
+    } else {
+        Instant::now().checked_add(timeout)
+    }
+}
+
+/// 排队中的客户端, drop时(包括连接断开)退出所有队列
+pub struct BlockGuard<'a> {
+    blocked: &'a BlockedClients,
+    db: usize,
+    keys: Vec<Bytes>,
+    id: u64,
+    notify: Arc<Notify>,
+}
+
+impl BlockGuard<'_> {
+    /// 等待被唤醒, 超时返回false
+    pub async fn wait(&self, deadline: Option<Instant>) -> bool {
+        match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, self.notify.notified())
+                .await
+                .is_ok(),
+            None => {
+                self.notify.notified().await;
+                true
+            }
+        }
+    }
+}
+
+impl Drop for BlockGuard<'_> {
+    fn drop(&mut self) {
+        let mut queues = self.blocked.queues.lock().unwrap();
+        for key in &self.keys {
+            let entry = (self.db, key.clone());
+            let Some(queue) = queues.get_mut(&entry) else {
+                continue;
+            };
+            let was_front = queue.front().is_some_and(|(id, _)| *id == self.id);
+            queue.retain(|(id, _)| *id != self.id);
+            // 排在队首时可能已经收到过唤醒, 转交给下一个客户端重新检查
+            match queue.front() {
+                Some((_, notify)) if was_front => notify.notify_one(),
+                Some(_) => {}
+                None => {
+                    queues.remove(&entry);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod blocking_test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fifo_wake() {
+        let blocked = BlockedClients::default();
+        let key = Bytes::from("q");
+        let first = blocked.block(0, std::slice::from_ref(&key), 1);
+        let second = blocked.block(0, &[key.clone(), key.clone()], 2);
+        let timeout = || deadline(Duration::from_millis(20));
+        // 只唤醒队首
+        blocked.wake(0, &key);
+        assert!(!second.wait(timeout()).await);
+        assert!(first.wait(timeout()).await);
+        // 队首退出时把唤醒转交给下一个
+        blocked.wake(0, &key);
+        drop(first);
+        assert!(second.wait(timeout()).await);
+        assert_eq!(blocked.blocked_keys(0), vec![key.clone()]);
+        assert!(blocked.blocked_keys(1).is_empty());
+        drop(second);
+        assert!(blocked.blocked_keys(0).is_empty());
+        assert_eq!(deadline(Duration::ZERO), None);
+    }
+}
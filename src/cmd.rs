@@ -1,139 +1,1364 @@
-use crate::frame::RESP;
+use std::{str::FromStr, time::Duration};
+
+use bytes::Bytes;
+
+use crate::{
+    cmd_table, db,
+    error::CmdError,
+    frame::{Protocol, RESP},
+    set::SetOp,
+    stream::{StreamId, Trim, TrimStrategy, XAddId},
+    zset::{Aggregate, LexRange, ScoreRange},
+};
 
 #[derive(Debug, PartialEq)]
 pub enum Cmd {
     Ping,
-    Echo(String),
-    Set(String, String, u128),
-    Get(String),
+    Echo(Bytes),
+    // SET/SETEX/PSETEX, 命令名用于过期时间的错误信息
+    Set(&'static str, Bytes, Bytes, SetOptions),
+    Get(Bytes),
+    // MSET/MSETNX, bool表示是否为MSETNX
+    MSet(Vec<(Bytes, Bytes)>, bool),
+    MGet(Vec<Bytes>),
+    SetNx(Bytes, Bytes),
+    Append(Bytes, Bytes),
+    Strlen(Bytes),
+    GetRange(Bytes, i64, i64),
+    SetRange(Bytes, usize, Bytes),
+    GetDel(Bytes),
+    GetEx(Bytes, Option<Expiry>),
+    // INCR/DECR/INCRBY/DECRBY统一为增量
+    IncrBy(Bytes, i64),
+    IncrByFloat(Bytes, f64),
+    // DEL/UNLINK
+    Del(Vec<Bytes>),
+    // EXISTS/TOUCH
+    Exists(Vec<Bytes>),
+    Type(Bytes),
+    // RENAME/RENAMENX, bool表示是否为RENAMENX
+    Rename(Bytes, Bytes, bool),
+    // COPY source destination [DB destination-db] [REPLACE]
+    Copy(Bytes, Bytes, Option<i64>, bool),
+    Select(i64),
+    Move(Bytes, i64),
+    SwapDb(i64, i64),
+    // FLUSHDB/FLUSHALL, 两个bool分别表示是否清空所有db, 是否ASYNC
+    Flush(bool, bool),
+    DbSize,
+    Keys(Bytes),
+    // EXPIRE/PEXPIRE/EXPIREAT/PEXPIREAT, 命令名用于过期时间的错误信息
+    Expire(&'static str, Bytes, ExpireTime, ExpireCond),
+    // TTL/PTTL/EXPIRETIME/PEXPIRETIME, 两个bool分别表示是否以毫秒为单位, 是否返回绝对时间
+    Ttl(Bytes, bool, bool),
+    Persist(Bytes),
+    Scan(u64, ScanOptions),
+    // LPUSH/RPUSH/LPUSHX/RPUSHX, 两个bool分别表示是否从左端插入, 是否只在key存在时插入
+    Push(Bytes, Vec<Bytes>, bool, bool),
+    // LPOP/RPOP key [count], bool表示是否从左端弹出
+    Pop(Bytes, Option<usize>, bool),
+    LLen(Bytes),
+    LRange(Bytes, i64, i64),
+    LIndex(Bytes, i64),
+    LSet(Bytes, i64, Bytes),
+    LRem(Bytes, i64, Bytes),
+    LTrim(Bytes, i64, i64),
+    // LINSERT key BEFORE | AFTER pivot element, bool表示是否为BEFORE
+    LInsert(Bytes, bool, Bytes, Bytes),
+    LPos(Bytes, Bytes, LPosOptions),
+    // LMOVE/RPOPLPUSH, 两个bool分别表示是否从源列表左端弹出, 是否插入目标列表左端
+    LMove(Bytes, Bytes, bool, bool),
+    // BLPOP/BRPOP key [key ...] timeout, bool表示是否从左端弹出
+    BPop(Vec<Bytes>, bool, Duration),
+    // BLMOVE/BRPOPLPUSH
+    BLMove(Bytes, Bytes, bool, bool, Duration),
+    // LMPOP/BLMPOP, 超时为None表示不阻塞
+    LMPop(Vec<Bytes>, bool, usize, Option<Duration>),
+    // HSET/HMSET, bool表示是否为HMSET(回复OK)
+    HSet(Bytes, Vec<(Bytes, Bytes)>, bool),
+    HSetNx(Bytes, Bytes, Bytes),
+    HGet(Bytes, Bytes),
+    HMGet(Bytes, Vec<Bytes>),
+    HGetAll(Bytes),
+    HDel(Bytes, Vec<Bytes>),
+    HIncrBy(Bytes, Bytes, i64),
+    HIncrByFloat(Bytes, Bytes, f64),
+    HKeys(Bytes),
+    HVals(Bytes),
+    HLen(Bytes),
+    HExists(Bytes, Bytes),
+    HStrlen(Bytes, Bytes),
+    HScan(Bytes, u64, ScanOptions),
+    // HRANDFIELD key [count [WITHVALUES]]
+    HRandField(Bytes, Option<(i64, bool)>),
+    SAdd(Bytes, Vec<Bytes>),
+    SRem(Bytes, Vec<Bytes>),
+    SMembers(Bytes),
+    SIsMember(Bytes, Bytes),
+    SMIsMember(Bytes, Vec<Bytes>),
+    SCard(Bytes),
+    SPop(Bytes, Option<usize>),
+    // SRANDMEMBER key [count], 负数表示允许重复
+    SRandMember(Bytes, Option<i64>),
+    // SMOVE source destination member
+    SMove(Bytes, Bytes, Bytes),
+    SScan(Bytes, u64, ScanOptions),
+    // SINTER/SUNION/SDIFF key [key ...]
+    SCombine(SetOp, Vec<Bytes>),
+    // SINTERSTORE/SUNIONSTORE/SDIFFSTORE destination key [key ...]
+    SCombineStore(SetOp, Bytes, Vec<Bytes>),
+    // SINTERCARD numkeys key [key ...] [LIMIT limit], limit为0表示不限制
+    SInterCard(Vec<Bytes>, usize),
+    // ZADD以及ZINCRBY(即ZADD key INCR increment member)
+    ZAdd(Bytes, Vec<(f64, Bytes)>, ZAddOptions),
+    ZRem(Bytes, Vec<Bytes>),
+    ZScore(Bytes, Bytes),
+    ZCard(Bytes),
+    // ZRANK/ZREVRANK key member [WITHSCORE], 两个bool分别表示是否从高到低, 是否返回分数
+    ZRank(Bytes, Bytes, bool, bool),
+    // ZRANGE以及ZREVRANGE, ZRANGEBYSCORE等旧命令
+    ZRange(Bytes, ZRange),
+    // ZREMRANGEBYRANK/ZREMRANGEBYSCORE/ZREMRANGEBYLEX
+    ZRemRange(Bytes, ZRangeBy),
+    // ZPOPMIN/ZPOPMAX key [count], bool表示是否弹出分数最高的
+    ZPop(Bytes, bool, Option<usize>),
+    // BZPOPMIN/BZPOPMAX key [key ...] timeout
+    BZPop(Vec<Bytes>, bool, Duration),
+    // ZMPOP/BZMPOP, 超时为None表示不阻塞
+    ZMPop(Vec<Bytes>, bool, usize, Option<Duration>),
+    // ZUNION/ZINTER/ZDIFF numkeys key [key ...] ...
+    ZCombine(SetOp, Vec<Bytes>, ZCombineOptions),
+    // ZUNIONSTORE/ZINTERSTORE/ZDIFFSTORE destination numkeys key [key ...] ...
+    ZCombineStore(SetOp, Bytes, Vec<Bytes>, ZCombineOptions),
+    XAdd(Bytes, XAddId, Vec<(Bytes, Bytes)>, XAddOptions),
+    XTrim(Bytes, Trim),
+    XDel(Bytes, Vec<StreamId>),
+    XLen(Bytes),
+    // XRANGE/XREVRANGE, 区间两端都包含在内, bool表示是否从新到旧; count为Some(0)时回复空
+    XRange(Bytes, StreamId, StreamId, bool, Option<usize>),
     Info(String),
     ReplConf(String, String),
     Psync(String, i64),
     FullReSync(String, usize),
     Wait(usize, u128),
+    // HELLO [protover [AUTH username password] [SETNAME clientname]]
+    Hello(Option<Protocol>, Option<(Bytes, Bytes)>, Option<Bytes>),
+    Command(Vec<Bytes>),
     Incomplete,
 }
 
+/// 过期时间参数, 均已换算为毫秒
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Expiry {
+    // KEEPTTL
+    Keep,
+    // GETEX的PERSIST, 清除过期时间
+    Persist,
+    // EXAT/PXAT, unix时间戳
+    At(u128),
+    // EX/PX, 相对于执行时刻
+    In(i64),
+}
+
+impl Expiry {
+    // 解析EX/PX/EXAT/PXAT之后的时间参数
+    fn parse(parser: &mut CmdParser, opt: &str) -> Result<Self, CmdError> {
+        let n: i64 = parser.next_int()?;
+        let millis = match opt {
+            "ex" | "exat" => n.checked_mul(1000),
+            _ => Some(n),
+        };
+        match millis {
+            Some(millis) if millis > 0 && opt.ends_with("at") => Ok(Expiry::At(millis as u128)),
+            Some(millis) if millis > 0 => Ok(Expiry::In(millis)),
+            _ => Err(CmdError::InvalidExpireTime(parser.name().to_string())),
+        }
+    }
+
+    /// 换算为绝对的毫秒时间戳, Keep返回None
+    pub fn deadline(&self, now_millis: u128, name: &str) -> Result<Option<u128>, CmdError> {
+        match self {
+            Expiry::Keep => Ok(None),
+            Expiry::Persist => Ok(Some(u128::MAX)),
+            Expiry::At(at) => Ok(Some(*at)),
+            Expiry::In(millis) => relative_deadline(*millis, now_millis, name).map(Some),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum SetCond {
+    #[default]
+    Always,
+    // 只在key不存在时写入
+    Nx,
+    // 只在key存在时写入
+    Xx,
+}
+
+/// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | KEEPTTL]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct SetOptions {
+    pub cond: SetCond,
+    pub get: bool,
+    // None表示清除原有的过期时间
+    pub expire: Option<Expiry>,
+}
+
+impl SetOptions {
+    // 选项可以以任意顺序出现, 互斥的选项同时出现时返回语法错误
+    fn parse(parser: &mut CmdParser) -> Result<Self, CmdError> {
+        let mut opts = SetOptions::default();
+        while parser.remaining() > 0 {
+            let opt = parser.next_string()?.to_lowercase();
+            match opt.as_str() {
+                "nx" if opts.cond == SetCond::Always => opts.cond = SetCond::Nx,
+                "xx" if opts.cond == SetCond::Always => opts.cond = SetCond::Xx,
+                "get" => opts.get = true,
+                "keepttl" if opts.expire.is_none() => opts.expire = Some(Expiry::Keep),
+                "ex" | "px" | "exat" | "pxat" if opts.expire.is_none() => {
+                    if parser.remaining() == 0 {
+                        return Err(CmdError::Syntax);
+                    }
+                    opts.expire = Some(Expiry::parse(parser, &opt)?);
+                }
+                _ => return Err(CmdError::Syntax),
+            }
+        }
+        Ok(opts)
+    }
+}
+
+/// EXPIRE系列命令的时间参数, 已换算为毫秒, 可以为负数(立即过期)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExpireTime {
+    At(i64),
+    In(i64),
+}
+
+impl ExpireTime {
+    /// 换算为绝对的毫秒时间戳, 已经过去的时间点为0
+    pub fn deadline(&self, now_millis: u128, name: &str) -> Result<u128, CmdError> {
+        match self {
+            ExpireTime::At(at) => Ok((*at).max(0) as u128),
+            ExpireTime::In(millis) => relative_deadline(*millis, now_millis, name),
+        }
+    }
+}
+
+// 相对时间加上执行时的当前时间(master连接上为0); 与redis一样结果不能超出i64,
+// 否则同步给replica的PXAT/PEXPIREAT会被拒绝
+fn relative_deadline(millis: i64, now_millis: u128, name: &str) -> Result<u128, CmdError> {
+    millis
+        .checked_add(now_millis as i64)
+        .map(|deadline| deadline.max(0) as u128)
+        .ok_or_else(|| CmdError::InvalidExpireTime(name.to_string()))
+}
+
+/// EXPIRE系列命令的NX | XX | GT | LT选项
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ExpireCond {
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+}
+
+impl ExpireCond {
+    fn parse(parser: &mut CmdParser) -> Result<Self, CmdError> {
+        let mut cond = ExpireCond::default();
+        while parser.remaining() > 0 {
+            let opt = parser.next_string()?;
+            match opt.to_lowercase().as_str() {
+                "nx" => cond.nx = true,
+                "xx" => cond.xx = true,
+                "gt" => cond.gt = true,
+                "lt" => cond.lt = true,
+                _ => return Err(CmdError::UnsupportedOption(opt)),
+            }
+        }
+        if cond.nx && (cond.xx || cond.gt || cond.lt) {
+            return Err(CmdError::ExpireNxConflict);
+        }
+        if cond.gt && cond.lt {
+            return Err(CmdError::ExpireGtLtConflict);
+        }
+        Ok(cond)
+    }
+
+    /// current为u128::MAX表示没有过期时间, 此时视为无穷大
+    pub fn allows(&self, current: u128, new: u128) -> bool {
+        !(self.nx && current != u128::MAX
+            || self.xx && current == u128::MAX
+            || self.gt && new <= current
+            || self.lt && new >= current)
+    }
+}
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type];
+/// HSCAN等集合内的遍历没有TYPE, HSCAN另外支持NOVALUES
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScanOptions {
+    pub pattern: Option<Bytes>,
+    pub count: usize,
+    // 小写的类型名
+    pub type_name: Option<String>,
+    // 只返回字段名
+    pub novalues: bool,
+}
+
+impl ScanOptions {
+    fn parse(parser: &mut CmdParser) -> Result<Self, CmdError> {
+        let mut opts = ScanOptions {
+            pattern: None,
+            count: 10,
+            type_name: None,
+            novalues: false,
+        };
+        while parser.remaining() > 0 {
+            let opt = parser.next_string()?.to_lowercase();
+            if opt == "novalues" && parser.name() == "hscan" {
+                opts.novalues = true;
+                continue;
+            }
+            if parser.remaining() == 0 {
+                return Err(CmdError::Syntax);
+            }
+            match opt.as_str() {
+                "match" => opts.pattern = Some(parser.next_bytes()?),
+                "count" => {
+                    opts.count = parser.next_int()?;
+                    if opts.count < 1 {
+                        return Err(CmdError::Syntax);
+                    }
+                }
+                "type" if parser.name() == "scan" => {
+                    opts.type_name = Some(parser.next_string()?.to_lowercase())
+                }
+                _ => return Err(CmdError::Syntax),
+            }
+        }
+        Ok(opts)
+    }
+}
+
+/// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ZAddOptions {
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+    // 回复新增和修改的成员数, 而不只是新增的
+    pub ch: bool,
+    // 与ZINCRBY相同, 回复新的分数
+    pub incr: bool,
+}
+
+impl ZAddOptions {
+    // 选项都在分数和成员之前
+    fn parse(parser: &mut CmdParser) -> Result<(Self, Vec<(f64, Bytes)>), CmdError> {
+        let args = parser.rest()?;
+        let mut opts = ZAddOptions::default();
+        let mut i = 0;
+        while i < args.len() {
+            match String::from_utf8_lossy(&args[i]).to_lowercase().as_str() {
+                "nx" => opts.nx = true,
+                "xx" => opts.xx = true,
+                "gt" => opts.gt = true,
+                "lt" => opts.lt = true,
+                "ch" => opts.ch = true,
+                "incr" => opts.incr = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        let args = &args[i..];
+        if args.is_empty() || args.len() % 2 != 0 {
+            return Err(CmdError::Syntax);
+        }
+        if opts.nx && opts.xx {
+            return Err(CmdError::ZAddXxNx);
+        }
+        if opts.gt && opts.lt || opts.nx && (opts.gt || opts.lt) {
+            return Err(CmdError::ZAddGtLtNx);
+        }
+        if opts.incr && args.len() > 2 {
+            return Err(CmdError::ZAddIncrPairs);
+        }
+        let pairs = args
+            .chunks(2)
+            .map(|pair| Ok((db::parse_float(&pair[0])?, pair[1].clone())))
+            .collect::<Result<_, CmdError>>()?;
+        Ok((opts, pairs))
+    }
+}
+
+/// ZRANGE的区间类型
+#[derive(Debug, PartialEq, Clone)]
+pub enum ZRangeBy {
+    Rank(i64, i64),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+/// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+#[derive(Debug, PartialEq, Clone)]
+pub struct ZRange {
+    pub by: ZRangeBy,
+    // 从高到低
+    pub rev: bool,
+    // LIMIT offset count, count为负数表示不限制个数
+    pub limit: Option<(i64, i64)>,
+    pub withscores: bool,
+}
+
+impl ZRange {
+    // ZREVRANGE, ZRANGEBYSCORE等旧命令的区间类型和方向由命令名决定, 不接受BYSCORE等选项
+    fn parse(parser: &mut CmdParser) -> Result<Self, CmdError> {
+        let name = parser.name().to_string();
+        let (start, stop) = (parser.next_bytes()?, parser.next_bytes()?);
+        let mut by_score = name.ends_with("byscore");
+        let mut by_lex = name.ends_with("bylex");
+        let mut rev = name.starts_with("zrev");
+        let (mut limit, mut withscores) = (None, false);
+        while parser.remaining() > 0 {
+            match parser.next_string()?.to_lowercase().as_str() {
+                "byscore" if name == "zrange" => by_score = true,
+                "bylex" if name == "zrange" => by_lex = true,
+                "rev" if name == "zrange" => rev = true,
+                "limit" if parser.remaining() >= 2 => {
+                    limit = Some((parser.next_int()?, parser.next_int()?))
+                }
+                "withscores" => withscores = true,
+                _ => return Err(CmdError::Syntax),
+            }
+        }
+        if by_score && by_lex {
+            return Err(CmdError::Syntax);
+        }
+        if limit.is_some() && !by_score && !by_lex {
+            return Err(CmdError::LimitWithoutBy);
+        }
+        if withscores && by_lex {
+            return Err(CmdError::WithScoresByLex);
+        }
+        // 按分数或字典序倒序时先给出的是上界
+        let (min, max) = if rev && (by_score || by_lex) {
+            (stop, start)
+        } else {
+            (start, stop)
+        };
+        let by = if by_score {
+            ZRangeBy::Score(ScoreRange::parse(&min, &max)?)
+        } else if by_lex {
+            ZRangeBy::Lex(LexRange::parse(&min, &max)?)
+        } else {
+            let rank = |src: &Bytes| parse_bytes(src).ok_or(CmdError::NotInteger);
+            ZRangeBy::Rank(rank(&min)?, rank(&max)?)
+        };
+        Ok(ZRange {
+            by,
+            rev,
+            limit,
+            withscores,
+        })
+    }
+}
+
+/// ZUNION等的[WEIGHTS weight ...] [AGGREGATE SUM | MIN | MAX] [WITHSCORES];
+/// ZDIFF没有WEIGHTS和AGGREGATE, STORE变体没有WITHSCORES
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ZCombineOptions {
+    // 为空时权重都为1
+    pub weights: Vec<f64>,
+    pub aggregate: Aggregate,
+    pub withscores: bool,
+}
+
+impl ZCombineOptions {
+    // 从numkeys开始解析, 返回输入的key
+    fn parse(parser: &mut CmdParser) -> Result<(Vec<Bytes>, Self), CmdError> {
+        let name = parser.name().to_string();
+        let numkeys: i64 = parser.next_int()?;
+        if numkeys <= 0 {
+            return Err(CmdError::NoInputKeys(name));
+        }
+        if numkeys as usize > parser.remaining() {
+            return Err(CmdError::Syntax);
+        }
+        let keys: Vec<Bytes> = (0..numkeys)
+            .map(|_| parser.next_bytes())
+            .collect::<Result<_, _>>()?;
+        let diff = name.starts_with("zdiff");
+        let mut opts = ZCombineOptions::default();
+        while parser.remaining() > 0 {
+            match parser.next_string()?.to_lowercase().as_str() {
+                "weights" if !diff && parser.remaining() >= keys.len() => {
+                    opts.weights = (0..keys.len())
+                        .map(|_| {
+                            db::parse_float(&parser.next_bytes()?)
+                                .map_err(|_| CmdError::WeightNotFloat)
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+                "aggregate" if !diff && parser.remaining() > 0 => {
+                    opts.aggregate = match parser.next_string()?.to_lowercase().as_str() {
+                        "sum" => Aggregate::Sum,
+                        "min" => Aggregate::Min,
+                        "max" => Aggregate::Max,
+                        _ => return Err(CmdError::Syntax),
+                    }
+                }
+                "withscores" if !name.ends_with("store") => opts.withscores = true,
+                _ => return Err(CmdError::Syntax),
+            }
+        }
+        Ok((keys, opts))
+    }
+}
+
+/// XADD key [NOMKSTREAM] [MAXLEN | MINID [= | ~] threshold [LIMIT count]] <* | id> field value
+/// [field value ...], XTRIM只有裁剪选项
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct XAddOptions {
+    // key不存在时不创建
+    pub nomkstream: bool,
+    pub trim: Option<Trim>,
+}
+
+impl XAddOptions {
+    // XADD的选项在ID之前, 第一个不是选项的参数为ID, 返回ID及之后的参数
+    fn parse(parser: &mut CmdParser) -> Result<(Self, Vec<Bytes>), CmdError> {
+        let xadd = parser.name() == "xadd";
+        let args = parser.rest()?;
+        let mut opts = XAddOptions::default();
+        let (mut strategy, mut approx, mut limit) = (None, false, None);
+        let mut i = 0;
+        while i < args.len() {
+            let more = args.len() - i - 1;
+            match String::from_utf8_lossy(&args[i]).to_lowercase().as_str() {
+                "*" if xadd => break,
+                opt @ ("maxlen" | "minid") if more > 0 => {
+                    if strategy.is_some() {
+                        return Err(CmdError::TrimMaxLenMinId);
+                    }
+                    if more >= 2 && (args[i + 1] == "~" || args[i + 1] == "=") {
+                        approx = args[i + 1] == "~";
+                        i += 1;
+                    }
+                    i += 1;
+                    strategy = Some(if opt == "maxlen" {
+                        let maxlen: i64 = parse_bytes(&args[i]).ok_or(CmdError::NotInteger)?;
+                        TrimStrategy::MaxLen(
+                            usize::try_from(maxlen)
+                                .map_err(|_| CmdError::StreamArgNegative("MAXLEN".to_string()))?,
+                        )
+                    } else {
+                        TrimStrategy::MinId(StreamId::parse(&args[i], 0)?)
+                    });
+                }
+                "limit" if more > 0 => {
+                    i += 1;
+                    let n: i64 = parse_bytes(&args[i]).ok_or(CmdError::NotInteger)?;
+                    limit = Some(
+                        usize::try_from(n)
+                            .map_err(|_| CmdError::StreamArgNegative("LIMIT".to_string()))?,
+                    );
+                }
+                "nomkstream" if xadd => opts.nomkstream = true,
+                _ if xadd => break,
+                _ => return Err(CmdError::Syntax),
+            }
+            i += 1;
+        }
+        match strategy {
+            Some(strategy) => {
+                if limit.is_some() && !approx {
+                    return Err(CmdError::TrimLimitWithoutApprox);
+                }
+                opts.trim = Some(Trim {
+                    strategy,
+                    approx,
+                    limit,
+                });
+            }
+            None if limit.is_some() => return Err(CmdError::TrimLimitWithoutStrategy),
+            None => {}
+        }
+        Ok((opts, args[i..].to_vec()))
+    }
+}
+
+/// LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LPosOptions {
+    // 负数表示从尾部开始查找
+    pub rank: i64,
+    // None表示只返回第一个匹配, Some(0)表示返回全部匹配
+    pub count: Option<usize>,
+    // 最多比较的元素个数, 0表示不限制
+    pub maxlen: usize,
+}
+
+impl LPosOptions {
+    fn parse(parser: &mut CmdParser) -> Result<Self, CmdError> {
+        let mut opts = LPosOptions {
+            rank: 1,
+            count: None,
+            maxlen: 0,
+        };
+        while parser.remaining() > 0 {
+            let opt = parser.next_string()?.to_lowercase();
+            if parser.remaining() == 0 {
+                return Err(CmdError::Syntax);
+            }
+            match opt.as_str() {
+                "rank" => {
+                    opts.rank = parser.next_int()?;
+                    if opts.rank == 0 {
+                        return Err(CmdError::RankZero);
+                    }
+                }
+                "count" => {
+                    let count: i64 = parser.next_int()?;
+                    let count = usize::try_from(count)
+                        .map_err(|_| CmdError::NegativeOption("COUNT".to_string()))?;
+                    opts.count = Some(count);
+                }
+                "maxlen" => {
+                    let maxlen: i64 = parser.next_int()?;
+                    opts.maxlen = usize::try_from(maxlen)
+                        .map_err(|_| CmdError::NegativeOption("MAXLEN".to_string()))?;
+                }
+                _ => return Err(CmdError::Syntax),
+            }
+        }
+        Ok(opts)
+    }
+}
+
+// LEFT | RIGHT, 返回是否为LEFT
+fn parse_side(parser: &mut CmdParser) -> Result<bool, CmdError> {
+    match parser.next_string()?.to_lowercase().as_str() {
+        "left" => Ok(true),
+        "right" => Ok(false),
+        _ => Err(CmdError::Syntax),
+    }
+}
+
+// MIN | MAX, 返回是否为MAX
+fn parse_min_max(parser: &mut CmdParser) -> Result<bool, CmdError> {
+    match parser.next_string()?.to_lowercase().as_str() {
+        "min" => Ok(false),
+        "max" => Ok(true),
+        _ => Err(CmdError::Syntax),
+    }
+}
+
+// 阻塞命令的超时参数, 单位为秒且可以是小数, 0表示一直阻塞
+fn parse_timeout(parser: &mut CmdParser) -> Result<Duration, CmdError> {
+    let timeout: f64 = parse_bytes(&parser.next_bytes()?).ok_or(CmdError::InvalidTimeout)?;
+    if timeout < 0.0 {
+        return Err(CmdError::NegativeTimeout);
+    }
+    Duration::try_from_secs_f64(timeout).map_err(|_| CmdError::InvalidTimeout)
+}
+
+// LMPOP/BLMPOP的numkeys key [key ...] LEFT | RIGHT [COUNT count],
+// ZMPOP/BZMPOP的方向为MIN | MAX, 由side解析
+fn parse_mpop(
+    parser: &mut CmdParser,
+    side: fn(&mut CmdParser) -> Result<bool, CmdError>,
+) -> Result<(Vec<Bytes>, bool, usize), CmdError> {
+    let numkeys: i64 = parser.next_int()?;
+    if numkeys <= 0 {
+        return Err(CmdError::NotGreaterThanZero("numkeys".to_string()));
+    }
+    if numkeys as usize >= parser.remaining() {
+        return Err(CmdError::Syntax);
+    }
+    let keys = (0..numkeys)
+        .map(|_| parser.next_bytes())
+        .collect::<Result<_, _>>()?;
+    let side = side(parser)?;
+    let mut count = 1;
+    if parser.remaining() > 0 {
+        if parser.remaining() != 2 || !parser.next_string()?.eq_ignore_ascii_case("count") {
+            return Err(CmdError::Syntax);
+        }
+        let n: i64 = parser.next_int()?;
+        if n <= 0 {
+            return Err(CmdError::NotGreaterThanZero("count".to_string()));
+        }
+        count = n as usize;
+    }
+    Ok((keys, side, count))
+}
+
+// 命令名中的运算, 如sinterstore对应Inter
+fn parse_set_op(name: &str) -> SetOp {
+    match &name[1..] {
+        n if n.starts_with("inter") => SetOp::Inter,
+        n if n.starts_with("union") => SetOp::Union,
+        _ => SetOp::Diff,
+    }
+}
+
+// 将二进制参数按utf8解析为数字等类型
+fn parse_bytes<T: FromStr>(src: &[u8]) -> Option<T> {
+    std::str::from_utf8(src).ok()?.parse().ok()
+}
+
+/// 按顺序读取命令参数, 参数缺失或格式错误时返回对应的`CmdError`
+pub struct CmdParser<'a> {
+    name: String,
+    args: std::slice::Iter<'a, RESP>,
+}
+
+impl<'a> CmdParser<'a> {
+    pub fn new(arr: &'a [RESP]) -> Result<Self, CmdError> {
+        let mut args = arr.iter();
+        match args.next() {
+            Some(RESP::Bulk(name)) => Ok(CmdParser {
+                name: String::from_utf8_lossy(name).to_lowercase(),
+                args,
+            }),
+            _ => Err(CmdError::Syntax),
+        }
+    }
+
+    /// 小写的命令名
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn next_bytes(&mut self) -> Result<Bytes, CmdError> {
+        match self.args.next() {
+            Some(RESP::Bulk(b)) => Ok(b.clone()),
+            Some(_) => Err(CmdError::Syntax),
+            None => Err(CmdError::WrongArity(self.name.clone())),
+        }
+    }
+
+    pub fn next_string(&mut self) -> Result<String, CmdError> {
+        self.next_bytes()
+            .map(|b| String::from_utf8_lossy(&b).to_string())
+    }
+
+    pub fn next_int<T: FromStr>(&mut self) -> Result<T, CmdError> {
+        parse_bytes(&self.next_bytes()?).ok_or(CmdError::NotInteger)
+    }
+
+    /// 读取剩余的全部参数
+    pub fn rest(&mut self) -> Result<Vec<Bytes>, CmdError> {
+        let mut rest = Vec::with_capacity(self.remaining());
+        while self.remaining() > 0 {
+            rest.push(self.next_bytes()?);
+        }
+        Ok(rest)
+    }
+
+    /// 所有参数都应已被读取
+    pub fn finish(&self) -> Result<(), CmdError> {
+        if self.args.len() == 0 {
+            Ok(())
+        } else {
+            Err(CmdError::WrongArity(self.name.clone()))
+        }
+    }
+
+    // redis的格式: 'arg1' 'arg2' ...
+    fn args_preview(&self) -> String {
+        self.args
+            .clone()
+            .filter_map(|resp| match resp {
+                RESP::Bulk(b) => Some(format!("'{}' ", String::from_utf8_lossy(b))),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 impl Cmd {
-    pub fn from(frame: &RESP) -> Option<Self> {
+    pub fn from(frame: &RESP) -> Result<Self, CmdError> {
         match frame {
             RESP::Array(arr) => {
-                if let RESP::Bulk(s) = &arr[0] {
-                    match s.as_str() {
-                        "ping" => Some(Cmd::Ping),
-                        "echo" => {
-                            if let RESP::Bulk(s) = &arr[1] {
-                                Some(Cmd::Echo(s.clone()))
-                            } else {
-                                None
+                let mut parser = CmdParser::new(arr)?;
+                let spec = match cmd_table::lookup(parser.name()) {
+                    Some(spec) => spec,
+                    None => {
+                        return Err(CmdError::UnknownCommand(
+                            parser.name().to_string(),
+                            parser.args_preview(),
+                        ))
+                    }
+                };
+                spec.check_arity(arr.len())?;
+                let cmd = match parser.name() {
+                    "ping" => Cmd::Ping,
+                    "echo" => Cmd::Echo(parser.next_bytes()?),
+                    "set" => {
+                        let key = parser.next_bytes()?;
+                        let value = parser.next_bytes()?;
+                        Cmd::Set(spec.name, key, value, SetOptions::parse(&mut parser)?)
+                    }
+                    "get" => Cmd::Get(parser.next_bytes()?),
+                    "mset" | "msetnx" => {
+                        // 命令名之外的参数须为偶数个
+                        if parser.remaining() % 2 != 0 {
+                            return Err(CmdError::WrongArity(parser.name().to_string()));
+                        }
+                        let mut pairs = Vec::with_capacity(parser.remaining() / 2);
+                        while parser.remaining() > 0 {
+                            pairs.push((parser.next_bytes()?, parser.next_bytes()?));
+                        }
+                        Cmd::MSet(pairs, parser.name() == "msetnx")
+                    }
+                    "mget" => Cmd::MGet(parser.rest()?),
+                    "setnx" => Cmd::SetNx(parser.next_bytes()?, parser.next_bytes()?),
+                    "setex" | "psetex" => {
+                        let key = parser.next_bytes()?;
+                        let unit = if parser.name() == "setex" { "ex" } else { "px" };
+                        let expire = Expiry::parse(&mut parser, unit)?;
+                        let opts = SetOptions {
+                            expire: Some(expire),
+                            ..Default::default()
+                        };
+                        Cmd::Set(spec.name, key, parser.next_bytes()?, opts)
+                    }
+                    "append" => Cmd::Append(parser.next_bytes()?, parser.next_bytes()?),
+                    "strlen" => Cmd::Strlen(parser.next_bytes()?),
+                    "getrange" => {
+                        Cmd::GetRange(parser.next_bytes()?, parser.next_int()?, parser.next_int()?)
+                    }
+                    "setrange" => {
+                        let key = parser.next_bytes()?;
+                        let offset: i64 = parser.next_int()?;
+                        if offset < 0 {
+                            return Err(CmdError::OffsetOutOfRange);
+                        }
+                        Cmd::SetRange(key, offset as usize, parser.next_bytes()?)
+                    }
+                    "getdel" => Cmd::GetDel(parser.next_bytes()?),
+                    "incr" => Cmd::IncrBy(parser.next_bytes()?, 1),
+                    "decr" => Cmd::IncrBy(parser.next_bytes()?, -1),
+                    "incrby" => Cmd::IncrBy(parser.next_bytes()?, parser.next_int()?),
+                    "decrby" => {
+                        let key = parser.next_bytes()?;
+                        let decrement: i64 = parser.next_int()?;
+                        let incr = decrement.checked_neg().ok_or(CmdError::DecrementOverflow)?;
+                        Cmd::IncrBy(key, incr)
+                    }
+                    "incrbyfloat" => Cmd::IncrByFloat(
+                        parser.next_bytes()?,
+                        db::parse_float(&parser.next_bytes()?)?,
+                    ),
+                    "del" | "unlink" => Cmd::Del(parser.rest()?),
+                    "exists" | "touch" => Cmd::Exists(parser.rest()?),
+                    "type" => Cmd::Type(parser.next_bytes()?),
+                    "rename" | "renamenx" => Cmd::Rename(
+                        parser.next_bytes()?,
+                        parser.next_bytes()?,
+                        parser.name() == "renamenx",
+                    ),
+                    "keys" => Cmd::Keys(parser.next_bytes()?),
+                    "scan" => {
+                        let cursor =
+                            parse_bytes(&parser.next_bytes()?).ok_or(CmdError::InvalidCursor)?;
+                        Cmd::Scan(cursor, ScanOptions::parse(&mut parser)?)
+                    }
+                    "expire" | "pexpire" | "expireat" | "pexpireat" => {
+                        let key = parser.next_bytes()?;
+                        let n: i64 = parser.next_int()?;
+                        let name = parser.name().to_string();
+                        let millis = if name.starts_with('p') {
+                            Some(n)
+                        } else {
+                            n.checked_mul(1000)
+                        };
+                        let time = match millis {
+                            Some(millis) if name.ends_with("at") => ExpireTime::At(millis),
+                            Some(millis) => ExpireTime::In(millis),
+                            None => return Err(CmdError::InvalidExpireTime(name)),
+                        };
+                        Cmd::Expire(spec.name, key, time, ExpireCond::parse(&mut parser)?)
+                    }
+                    "ttl" | "pttl" | "expiretime" | "pexpiretime" => {
+                        let millis = parser.name().starts_with('p');
+                        let absolute = parser.name().ends_with("expiretime");
+                        Cmd::Ttl(parser.next_bytes()?, millis, absolute)
+                    }
+                    "persist" => Cmd::Persist(parser.next_bytes()?),
+                    "copy" => {
+                        let (src, dst) = (parser.next_bytes()?, parser.next_bytes()?);
+                        let (mut db, mut replace) = (None, false);
+                        while parser.remaining() > 0 {
+                            match parser.next_string()?.to_lowercase().as_str() {
+                                "replace" => replace = true,
+                                "db" if parser.remaining() > 0 => db = Some(parser.next_int()?),
+                                _ => return Err(CmdError::Syntax),
                             }
                         }
-                        "set" => {
-                            if let (RESP::Bulk(key), RESP::Bulk(value)) = (&arr[1], &arr[2]) {
-                                if let (Some(RESP::Bulk(px)), Some(RESP::Bulk(millis_str))) =
-                                    (arr.get(3), arr.get(4))
+                        Cmd::Copy(src, dst, db, replace)
+                    }
+                    "select" => Cmd::Select(parser.next_int()?),
+                    "move" => Cmd::Move(parser.next_bytes()?, parser.next_int()?),
+                    "swapdb" => {
+                        let first = parser
+                            .next_int()
+                            .map_err(|_| CmdError::InvalidDbIndex("first".to_string()))?;
+                        let second = parser
+                            .next_int()
+                            .map_err(|_| CmdError::InvalidDbIndex("second".to_string()))?;
+                        Cmd::SwapDb(first, second)
+                    }
+                    "flushdb" | "flushall" => {
+                        let all = parser.name() == "flushall";
+                        let mut lazy = false;
+                        if parser.remaining() > 0 {
+                            lazy = match parser.next_string()?.to_lowercase().as_str() {
+                                "async" => true,
+                                "sync" => false,
+                                _ => return Err(CmdError::Syntax),
+                            };
+                        }
+                        Cmd::Flush(all, lazy)
+                    }
+                    "dbsize" => Cmd::DbSize,
+                    "getex" => {
+                        let key = parser.next_bytes()?;
+                        let mut expire = None;
+                        while parser.remaining() > 0 {
+                            let opt = parser.next_string()?.to_lowercase();
+                            match opt.as_str() {
+                                "persist" if expire.is_none() => expire = Some(Expiry::Persist),
+                                "ex" | "px" | "exat" | "pxat"
+                                    if expire.is_none() && parser.remaining() > 0 =>
                                 {
-                                    if px == "px" {
-                                        match millis_str.parse() {
-                                            Ok(millis) => {
-                                                Some(Cmd::Set(key.clone(), value.clone(), millis))
-                                            }
-                                            Err(_) => None,
-                                        }
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    Some(Cmd::Set(key.clone(), value.clone(), u128::MAX))
+                                    expire = Some(Expiry::parse(&mut parser, &opt)?)
                                 }
-                            } else {
-                                None
+                                _ => return Err(CmdError::Syntax),
                             }
                         }
-                        "get" => arr.get(1).and_then(|resp| {
-                            if let RESP::Bulk(key) = resp {
-                                Some(Cmd::Get(key.clone()))
-                            } else {
-                                None
-                            }
-                        }),
-                        "info" => arr.get(1).map_or(Some(Cmd::Info("".to_string())), |resp| {
-                            if let RESP::Bulk(rep) = resp {
-                                Some(Cmd::Info(rep.clone()))
-                            } else {
-                                Some(Cmd::Info("".to_string()))
-                            }
-                        }),
-                        "replconf" => {
-                            if let (Some(RESP::Bulk(a)), Some(RESP::Bulk(b))) =
-                                (arr.get(1), arr.get(2))
+                        Cmd::GetEx(key, expire)
+                    }
+                    "lpush" | "rpush" | "lpushx" | "rpushx" => {
+                        let name = parser.name();
+                        let (left, xx) = (name.starts_with('l'), name.ends_with('x'));
+                        Cmd::Push(parser.next_bytes()?, parser.rest()?, left, xx)
+                    }
+                    "lpop" | "rpop" => {
+                        let left = parser.name() == "lpop";
+                        let key = parser.next_bytes()?;
+                        let mut count = None;
+                        if parser.remaining() > 0 {
+                            let n: i64 = parser.next_int()?;
+                            count = Some(usize::try_from(n).map_err(|_| CmdError::NotPositive)?);
+                        }
+                        Cmd::Pop(key, count, left)
+                    }
+                    "llen" => Cmd::LLen(parser.next_bytes()?),
+                    "lrange" => {
+                        Cmd::LRange(parser.next_bytes()?, parser.next_int()?, parser.next_int()?)
+                    }
+                    "lindex" => Cmd::LIndex(parser.next_bytes()?, parser.next_int()?),
+                    "lset" => Cmd::LSet(
+                        parser.next_bytes()?,
+                        parser.next_int()?,
+                        parser.next_bytes()?,
+                    ),
+                    "lrem" => Cmd::LRem(
+                        parser.next_bytes()?,
+                        parser.next_int()?,
+                        parser.next_bytes()?,
+                    ),
+                    "ltrim" => {
+                        Cmd::LTrim(parser.next_bytes()?, parser.next_int()?, parser.next_int()?)
+                    }
+                    "linsert" => {
+                        let key = parser.next_bytes()?;
+                        let before = match parser.next_string()?.to_lowercase().as_str() {
+                            "before" => true,
+                            "after" => false,
+                            _ => return Err(CmdError::Syntax),
+                        };
+                        Cmd::LInsert(key, before, parser.next_bytes()?, parser.next_bytes()?)
+                    }
+                    "lpos" => {
+                        let (key, element) = (parser.next_bytes()?, parser.next_bytes()?);
+                        Cmd::LPos(key, element, LPosOptions::parse(&mut parser)?)
+                    }
+                    "lmove" => {
+                        let (src, dst) = (parser.next_bytes()?, parser.next_bytes()?);
+                        Cmd::LMove(src, dst, parse_side(&mut parser)?, parse_side(&mut parser)?)
+                    }
+                    "rpoplpush" => {
+                        Cmd::LMove(parser.next_bytes()?, parser.next_bytes()?, false, true)
+                    }
+                    "blpop" | "brpop" => {
+                        let left = parser.name() == "blpop";
+                        let mut keys = Vec::with_capacity(parser.remaining() - 1);
+                        while parser.remaining() > 1 {
+                            keys.push(parser.next_bytes()?);
+                        }
+                        Cmd::BPop(keys, left, parse_timeout(&mut parser)?)
+                    }
+                    "blmove" => {
+                        let (src, dst) = (parser.next_bytes()?, parser.next_bytes()?);
+                        let (from_left, to_left) =
+                            (parse_side(&mut parser)?, parse_side(&mut parser)?);
+                        Cmd::BLMove(src, dst, from_left, to_left, parse_timeout(&mut parser)?)
+                    }
+                    "brpoplpush" => {
+                        let (src, dst) = (parser.next_bytes()?, parser.next_bytes()?);
+                        Cmd::BLMove(src, dst, false, true, parse_timeout(&mut parser)?)
+                    }
+                    "lmpop" => {
+                        let (keys, left, count) = parse_mpop(&mut parser, parse_side)?;
+                        Cmd::LMPop(keys, left, count, None)
+                    }
+                    "blmpop" => {
+                        let timeout = parse_timeout(&mut parser)?;
+                        let (keys, left, count) = parse_mpop(&mut parser, parse_side)?;
+                        Cmd::LMPop(keys, left, count, Some(timeout))
+                    }
+                    "hset" | "hmset" => {
+                        let key = parser.next_bytes()?;
+                        if parser.remaining() % 2 != 0 {
+                            return Err(CmdError::WrongArity(parser.name().to_string()));
+                        }
+                        let mut pairs = Vec::with_capacity(parser.remaining() / 2);
+                        while parser.remaining() > 0 {
+                            pairs.push((parser.next_bytes()?, parser.next_bytes()?));
+                        }
+                        Cmd::HSet(key, pairs, parser.name() == "hmset")
+                    }
+                    "hsetnx" => Cmd::HSetNx(
+                        parser.next_bytes()?,
+                        parser.next_bytes()?,
+                        parser.next_bytes()?,
+                    ),
+                    "hget" => Cmd::HGet(parser.next_bytes()?, parser.next_bytes()?),
+                    "hmget" => Cmd::HMGet(parser.next_bytes()?, parser.rest()?),
+                    "hgetall" => Cmd::HGetAll(parser.next_bytes()?),
+                    "hdel" => Cmd::HDel(parser.next_bytes()?, parser.rest()?),
+                    "hincrby" => Cmd::HIncrBy(
+                        parser.next_bytes()?,
+                        parser.next_bytes()?,
+                        parser.next_int()?,
+                    ),
+                    "hincrbyfloat" => Cmd::HIncrByFloat(
+                        parser.next_bytes()?,
+                        parser.next_bytes()?,
+                        db::parse_float(&parser.next_bytes()?)?,
+                    ),
+                    "hkeys" => Cmd::HKeys(parser.next_bytes()?),
+                    "hvals" => Cmd::HVals(parser.next_bytes()?),
+                    "hlen" => Cmd::HLen(parser.next_bytes()?),
+                    "hexists" => Cmd::HExists(parser.next_bytes()?, parser.next_bytes()?),
+                    "hstrlen" => Cmd::HStrlen(parser.next_bytes()?, parser.next_bytes()?),
+                    "hscan" => {
+                        let key = parser.next_bytes()?;
+                        let cursor =
+                            parse_bytes(&parser.next_bytes()?).ok_or(CmdError::InvalidCursor)?;
+                        Cmd::HScan(key, cursor, ScanOptions::parse(&mut parser)?)
+                    }
+                    "hrandfield" => {
+                        let key = parser.next_bytes()?;
+                        let mut count = None;
+                        if parser.remaining() > 0 {
+                            let n = parser.next_int()?;
+                            let withvalues = match parser.remaining() {
+                                0 => false,
+                                1 if parser.next_string()?.eq_ignore_ascii_case("withvalues") => {
+                                    true
+                                }
+                                _ => return Err(CmdError::Syntax),
+                            };
+                            count = Some((n, withvalues));
+                        }
+                        Cmd::HRandField(key, count)
+                    }
+                    "sadd" => Cmd::SAdd(parser.next_bytes()?, parser.rest()?),
+                    "srem" => Cmd::SRem(parser.next_bytes()?, parser.rest()?),
+                    "smembers" => Cmd::SMembers(parser.next_bytes()?),
+                    "sismember" => Cmd::SIsMember(parser.next_bytes()?, parser.next_bytes()?),
+                    "smismember" => Cmd::SMIsMember(parser.next_bytes()?, parser.rest()?),
+                    "scard" => Cmd::SCard(parser.next_bytes()?),
+                    "spop" => {
+                        let key = parser.next_bytes()?;
+                        let mut count = None;
+                        if parser.remaining() > 0 {
+                            let n: i64 = parser.next_int()?;
+                            count = Some(usize::try_from(n).map_err(|_| CmdError::NotPositive)?);
+                        }
+                        Cmd::SPop(key, count)
+                    }
+                    "srandmember" => {
+                        let key = parser.next_bytes()?;
+                        let mut count = None;
+                        if parser.remaining() > 0 {
+                            count = Some(parser.next_int()?);
+                        }
+                        Cmd::SRandMember(key, count)
+                    }
+                    "smove" => Cmd::SMove(
+                        parser.next_bytes()?,
+                        parser.next_bytes()?,
+                        parser.next_bytes()?,
+                    ),
+                    "sscan" => {
+                        let key = parser.next_bytes()?;
+                        let cursor =
+                            parse_bytes(&parser.next_bytes()?).ok_or(CmdError::InvalidCursor)?;
+                        Cmd::SScan(key, cursor, ScanOptions::parse(&mut parser)?)
+                    }
+                    "sinter" | "sunion" | "sdiff" => {
+                        Cmd::SCombine(parse_set_op(parser.name()), parser.rest()?)
+                    }
+                    "sinterstore" | "sunionstore" | "sdiffstore" => {
+                        let op = parse_set_op(parser.name());
+                        Cmd::SCombineStore(op, parser.next_bytes()?, parser.rest()?)
+                    }
+                    "sintercard" => {
+                        let numkeys: i64 = parser.next_int()?;
+                        if numkeys <= 0 {
+                            return Err(CmdError::NotGreaterThanZero("numkeys".to_string()));
+                        }
+                        if numkeys as usize > parser.remaining() {
+                            return Err(CmdError::Syntax);
+                        }
+                        let keys = (0..numkeys)
+                            .map(|_| parser.next_bytes())
+                            .collect::<Result<_, _>>()?;
+                        let mut limit = 0;
+                        if parser.remaining() > 0 {
+                            if parser.remaining() != 2
+                                || !parser.next_string()?.eq_ignore_ascii_case("limit")
                             {
-                                Some(Cmd::ReplConf(a.clone(), b.clone()))
-                            } else {
-                                None
+                                return Err(CmdError::Syntax);
                             }
+                            let n: i64 = parser.next_int()?;
+                            limit = usize::try_from(n)
+                                .map_err(|_| CmdError::NegativeOption("LIMIT".to_string()))?;
                         }
-                        "psync" => {
-                            if let (
-                                Some(RESP::Bulk(master_replid)),
-                                Some(RESP::Bulk(master_repl_offset)),
-                            ) = (arr.get(1), arr.get(2))
-                            {
-                                match master_repl_offset.parse() {
-                                    Ok(offset) => Some(Cmd::Psync(master_replid.clone(), offset)),
-                                    Err(_) => None,
-                                }
-                            } else {
-                                None
+                        Cmd::SInterCard(keys, limit)
+                    }
+                    "zadd" => {
+                        let key = parser.next_bytes()?;
+                        let (opts, pairs) = ZAddOptions::parse(&mut parser)?;
+                        Cmd::ZAdd(key, pairs, opts)
+                    }
+                    "zincrby" => {
+                        let key = parser.next_bytes()?;
+                        let incr = db::parse_float(&parser.next_bytes()?)?;
+                        let opts = ZAddOptions {
+                            incr: true,
+                            ..Default::default()
+                        };
+                        Cmd::ZAdd(key, vec![(incr, parser.next_bytes()?)], opts)
+                    }
+                    "zrem" => Cmd::ZRem(parser.next_bytes()?, parser.rest()?),
+                    "zscore" => Cmd::ZScore(parser.next_bytes()?, parser.next_bytes()?),
+                    "zcard" => Cmd::ZCard(parser.next_bytes()?),
+                    "zrank" | "zrevrank" => {
+                        let rev = parser.name() == "zrevrank";
+                        let (key, member) = (parser.next_bytes()?, parser.next_bytes()?);
+                        let withscore = match parser.remaining() {
+                            0 => false,
+                            1 if parser.next_string()?.eq_ignore_ascii_case("withscore") => true,
+                            _ => return Err(CmdError::Syntax),
+                        };
+                        Cmd::ZRank(key, member, rev, withscore)
+                    }
+                    "zrange" | "zrevrange" | "zrangebyscore" | "zrevrangebyscore"
+                    | "zrangebylex" | "zrevrangebylex" => {
+                        Cmd::ZRange(parser.next_bytes()?, ZRange::parse(&mut parser)?)
+                    }
+                    "zremrangebyrank" | "zremrangebyscore" | "zremrangebylex" => {
+                        let key = parser.next_bytes()?;
+                        let (min, max) = (parser.next_bytes()?, parser.next_bytes()?);
+                        let by = match parser.name() {
+                            "zremrangebyscore" => ZRangeBy::Score(ScoreRange::parse(&min, &max)?),
+                            "zremrangebylex" => ZRangeBy::Lex(LexRange::parse(&min, &max)?),
+                            _ => {
+                                let rank =
+                                    |src: &Bytes| parse_bytes(src).ok_or(CmdError::NotInteger);
+                                ZRangeBy::Rank(rank(&min)?, rank(&max)?)
                             }
+                        };
+                        Cmd::ZRemRange(key, by)
+                    }
+                    "zpopmin" | "zpopmax" => {
+                        let max = parser.name() == "zpopmax";
+                        let key = parser.next_bytes()?;
+                        let mut count = None;
+                        if parser.remaining() > 0 {
+                            let n: i64 = parser.next_int()?;
+                            count = Some(usize::try_from(n).map_err(|_| CmdError::NotPositive)?);
                         }
-                        "wait" => {
-                            if let (
-                                Some(RESP::Bulk(numreplicas_str)),
-                                Some(RESP::Bulk(timeout_str)),
-                            ) = (arr.get(1), arr.get(2))
-                            {
-                                if let (Ok(numreplicas), Ok(timeout)) =
-                                    (numreplicas_str.parse(), timeout_str.parse())
-                                {
-                                    Some(Cmd::Wait(numreplicas, timeout))
-                                } else {
-                                    None
+                        Cmd::ZPop(key, max, count)
+                    }
+                    "bzpopmin" | "bzpopmax" => {
+                        let max = parser.name() == "bzpopmax";
+                        let mut keys = Vec::with_capacity(parser.remaining() - 1);
+                        while parser.remaining() > 1 {
+                            keys.push(parser.next_bytes()?);
+                        }
+                        Cmd::BZPop(keys, max, parse_timeout(&mut parser)?)
+                    }
+                    "zmpop" => {
+                        let (keys, max, count) = parse_mpop(&mut parser, parse_min_max)?;
+                        Cmd::ZMPop(keys, max, count, None)
+                    }
+                    "bzmpop" => {
+                        let timeout = parse_timeout(&mut parser)?;
+                        let (keys, max, count) = parse_mpop(&mut parser, parse_min_max)?;
+                        Cmd::ZMPop(keys, max, count, Some(timeout))
+                    }
+                    "zunion" | "zinter" | "zdiff" => {
+                        let op = parse_set_op(parser.name());
+                        let (keys, opts) = ZCombineOptions::parse(&mut parser)?;
+                        Cmd::ZCombine(op, keys, opts)
+                    }
+                    "zunionstore" | "zinterstore" | "zdiffstore" => {
+                        let op = parse_set_op(parser.name());
+                        let dst = parser.next_bytes()?;
+                        let (keys, opts) = ZCombineOptions::parse(&mut parser)?;
+                        Cmd::ZCombineStore(op, dst, keys, opts)
+                    }
+                    "xadd" => {
+                        let key = parser.next_bytes()?;
+                        let (opts, args) = XAddOptions::parse(&mut parser)?;
+                        // ID之后至少有一对字段和值
+                        if args.len() < 3 || args.len() % 2 == 0 {
+                            return Err(CmdError::WrongArity(parser.name().to_string()));
+                        }
+                        let id = XAddId::parse(&args[0])?;
+                        let fields = args[1..]
+                            .chunks(2)
+                            .map(|pair| (pair[0].clone(), pair[1].clone()))
+                            .collect();
+                        Cmd::XAdd(key, id, fields, opts)
+                    }
+                    "xtrim" => {
+                        let key = parser.next_bytes()?;
+                        match XAddOptions::parse(&mut parser)? {
+                            (
+                                XAddOptions {
+                                    trim: Some(trim), ..
+                                },
+                                _,
+                            ) => Cmd::XTrim(key, trim),
+                            _ => return Err(CmdError::XTrimNoStrategy),
+                        }
+                    }
+                    "xdel" => {
+                        let key = parser.next_bytes()?;
+                        let ids = parser
+                            .rest()?
+                            .iter()
+                            .map(|id| StreamId::parse(id, 0))
+                            .collect::<Result<_, _>>()?;
+                        Cmd::XDel(key, ids)
+                    }
+                    "xlen" => Cmd::XLen(parser.next_bytes()?),
+                    "xrange" | "xrevrange" => {
+                        let rev = parser.name() == "xrevrange";
+                        let key = parser.next_bytes()?;
+                        let (first, second) = (parser.next_bytes()?, parser.next_bytes()?);
+                        // XREVRANGE先给出终点
+                        let (start, end) = if rev {
+                            (second, first)
+                        } else {
+                            (first, second)
+                        };
+                        let start = StreamId::parse_bound(&start, true)?;
+                        let end = StreamId::parse_bound(&end, false)?;
+                        let mut count = None;
+                        while parser.remaining() > 0 {
+                            let opt = parser.next_string()?;
+                            if !opt.eq_ignore_ascii_case("count") || parser.remaining() == 0 {
+                                return Err(CmdError::Syntax);
+                            }
+                            // 负数视为0
+                            let n: i64 = parser.next_int()?;
+                            count = Some(n.max(0) as usize);
+                        }
+                        Cmd::XRange(key, start, end, rev, count)
+                    }
+                    "info" => {
+                        if parser.remaining() > 0 {
+                            Cmd::Info(parser.next_string()?.to_lowercase())
+                        } else {
+                            Cmd::Info("".to_string())
+                        }
+                    }
+                    "replconf" => Cmd::ReplConf(parser.next_string()?, parser.next_string()?),
+                    "psync" => Cmd::Psync(parser.next_string()?, parser.next_int()?),
+                    "wait" => Cmd::Wait(parser.next_int()?, parser.next_int()?),
+                    "hello" => {
+                        let (mut protocol, mut auth, mut setname) = (None, None, None);
+                        if parser.remaining() > 0 {
+                            protocol = match parser.next_int() {
+                                Ok(2) => Some(Protocol::Resp2),
+                                Ok(3) => Some(Protocol::Resp3),
+                                Ok(_) => return Err(CmdError::NoProto),
+                                Err(_) => return Err(CmdError::InvalidProtoVersion),
+                            };
+                        }
+                        while parser.remaining() > 0 {
+                            let opt = parser.next_string()?;
+                            match opt.to_lowercase().as_str() {
+                                "auth" if parser.remaining() >= 2 => {
+                                    auth = Some((parser.next_bytes()?, parser.next_bytes()?))
+                                }
+                                "setname" if parser.remaining() >= 1 => {
+                                    setname = Some(parser.next_bytes()?)
                                 }
-                            } else {
-                                None
+                                _ => return Err(CmdError::HelloOption(opt)),
                             }
                         }
-                        _ => None,
+                        Cmd::Hello(protocol, auth, setname)
                     }
-                } else {
-                    None
-                }
+                    "command" => Cmd::Command(parser.rest()?),
+                    name => {
+                        return Err(CmdError::UnknownCommand(
+                            name.to_string(),
+                            parser.args_preview(),
+                        ))
+                    }
+                };
+                parser.finish()?;
+                Ok(cmd)
             }
             RESP::Simple(s) => {
                 // FULLRESYNC
                 // println!("Cmd from RESP::Simple:{}", s);
-                if s.starts_with("fullresync") {
-                    let mut iter = s.split_whitespace();
-                    iter.next();
+                let mut iter = s.split_whitespace();
+                if iter
+                    .next()
+                    .is_some_and(|s| s.eq_ignore_ascii_case("fullresync"))
+                {
                     if let (Some(repl_id), Some(off_str)) = (iter.next(), iter.next()) {
                         // println!("repl_id:{}, off_str:{}", repl_id, off_str);
-                        match off_str.parse() {
-                            Ok(off) => Some(Cmd::FullReSync(repl_id.to_string(), off)),
-                            Err(_) => None,
-                        }
-                    } else {
-                        None
+                        let off = off_str.parse().map_err(|_| CmdError::NotInteger)?;
+                        return Ok(Cmd::FullReSync(repl_id.to_string(), off));
                     }
-                } else {
-                    None
                 }
+                Err(CmdError::Syntax)
             }
-            _ => None,
+            _ => Err(CmdError::Syntax),
         }
     }
     pub fn new_ping_resp() -> RESP {
-        RESP::Array(vec![RESP::Bulk("ping".to_string())])
+        RESP::Array(vec![RESP::new_bulk("PING")])
     }
 }
 
@@ -141,31 +1366,826 @@ impl Cmd {
 mod cmd_test {
     use super::*;
 
+    // 按bulk字符串数组解析命令
+    fn cmd(args: &[&'static str]) -> Result<Cmd, CmdError> {
+        Cmd::from(&RESP::Array(
+            args.iter().map(|s| RESP::new_bulk(*s)).collect(),
+        ))
+    }
+
+    fn b(s: &'static str) -> Bytes {
+        Bytes::from(s)
+    }
+
     #[test]
     fn test_ping() {
-        let frame = RESP::Array(vec![RESP::Bulk("ping".to_string())]);
-        assert_eq!(Cmd::from(&frame), Some(Cmd::Ping));
+        let frame = RESP::Array(vec![RESP::new_bulk("PING")]);
+        assert_eq!(Cmd::from(&frame), Ok(Cmd::Ping));
     }
 
     #[test]
     fn test_echo() {
-        let frame = RESP::Array(vec![
-            RESP::Bulk("echo".to_string()),
-            RESP::Bulk("hello".to_string()),
-        ]);
-        assert_eq!(Cmd::from(&frame), Some(Cmd::Echo("hello".to_string())));
+        let frame = RESP::Array(vec![RESP::new_bulk("echo"), RESP::new_bulk("Hello")]);
+        assert_eq!(Cmd::from(&frame), Ok(Cmd::Echo(Bytes::from("Hello"))));
     }
 
     #[test]
-    fn test_fullresync() {
-        let frame =
-            RESP::Simple("fullresync 75cd7bc10c49047e0d163660f3b90625b1af31dc 0".to_string());
+    fn test_set_binary_safe() {
+        let value = Bytes::from_static(b"\x00\xff\xfeBar");
+        let frame = RESP::Array(vec![
+            RESP::new_bulk("SeT"),
+            RESP::new_bulk("Foo"),
+            RESP::Bulk(value.clone()),
+            RESP::new_bulk("PX"),
+            RESP::new_bulk("100"),
+        ]);
         assert_eq!(
             Cmd::from(&frame),
-            Some(Cmd::FullReSync(
+            Ok(Cmd::Set(
+                "set",
+                Bytes::from("Foo"),
+                value,
+                SetOptions {
+                    expire: Some(Expiry::In(100)),
+                    ..Default::default()
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_set_options() {
+        let set = |args: &[&'static str]| {
+            let mut frame = vec![
+                RESP::new_bulk("set"),
+                RESP::new_bulk("k"),
+                RESP::new_bulk("v"),
+            ];
+            frame.extend(args.iter().map(|s| RESP::new_bulk(*s)));
+            match Cmd::from(&RESP::Array(frame)) {
+                Ok(Cmd::Set(_, _, _, opts)) => Ok(opts),
+                Ok(cmd) => panic!("unexpected cmd: {:?}", cmd),
+                Err(e) => Err(e),
+            }
+        };
+        assert_eq!(
+            set(&["px", "30000", "NX"]),
+            Ok(SetOptions {
+                cond: SetCond::Nx,
+                get: false,
+                expire: Some(Expiry::In(30000)),
+            })
+        );
+        assert_eq!(
+            set(&["GET", "EXAT", "100", "xx"]),
+            Ok(SetOptions {
+                cond: SetCond::Xx,
+                get: true,
+                expire: Some(Expiry::At(100000)),
+            })
+        );
+        assert_eq!(
+            set(&["keepttl"]).map(|opts| opts.expire),
+            Ok(Some(Expiry::Keep))
+        );
+        assert_eq!(set(&["nx", "xx"]), Err(CmdError::Syntax));
+        assert_eq!(set(&["ex", "10", "persist"]), Err(CmdError::Syntax));
+        assert_eq!(set(&["ex", "10", "px", "10"]), Err(CmdError::Syntax));
+        assert_eq!(set(&["keepttl", "ex", "10"]), Err(CmdError::Syntax));
+        assert_eq!(set(&["ex"]), Err(CmdError::Syntax));
+        assert_eq!(set(&["ex", "ten"]), Err(CmdError::NotInteger));
+        assert_eq!(
+            set(&["px", "0"]),
+            Err(CmdError::InvalidExpireTime("set".to_string()))
+        );
+        assert_eq!(
+            set(&["ex", "9223372036854775807"]),
+            Err(CmdError::InvalidExpireTime("set".to_string()))
+        );
+        // 相对时间加上当前时间是否溢出在执行时判断
+        let expire = set(&["px", "9223372036854775807"]).unwrap().expire.unwrap();
+        assert_eq!(expire, Expiry::In(i64::MAX));
+        assert_eq!(
+            expire.deadline(1, "set"),
+            Err(CmdError::InvalidExpireTime("set".to_string()))
+        );
+        // master连接上的当前时间为0
+        assert_eq!(expire.deadline(0, "set"), Ok(Some(i64::MAX as u128)));
+        // 绝对时间不需要加上当前时间
+        assert_eq!(
+            set(&["pxat", "9223372036854775807"]).map(|opts| opts.expire),
+            Ok(Some(Expiry::At(i64::MAX as u128)))
+        );
+    }
+
+    #[test]
+    fn test_fullresync() {
+        let frame =
+            RESP::Simple("FULLRESYNC 75cd7bc10c49047e0d163660f3b90625b1af31dc 0".to_string());
+        assert_eq!(
+            Cmd::from(&frame),
+            Ok(Cmd::FullReSync(
                 "75cd7bc10c49047e0d163660f3b90625b1af31dc".to_string(),
                 0
             ))
         );
     }
+
+    #[test]
+    fn test_string_family() {
+        assert_eq!(
+            cmd(&["MSETNX", "a", "1", "b", "2"]),
+            Ok(Cmd::MSet(vec![(b("a"), b("1")), (b("b"), b("2"))], true))
+        );
+        assert_eq!(
+            cmd(&["mset", "a", "1", "b"]),
+            Err(CmdError::WrongArity("mset".to_string()))
+        );
+        assert_eq!(
+            cmd(&["setex", "k", "10", "v"]),
+            Ok(Cmd::Set(
+                "setex",
+                b("k"),
+                b("v"),
+                SetOptions {
+                    expire: Some(Expiry::In(10000)),
+                    ..Default::default()
+                }
+            ))
+        );
+        assert_eq!(
+            cmd(&["psetex", "k", "0", "v"]),
+            Err(CmdError::InvalidExpireTime("psetex".to_string()))
+        );
+        assert_eq!(
+            cmd(&["getrange", "k", "0", "-1"]),
+            Ok(Cmd::GetRange(b("k"), 0, -1))
+        );
+        assert_eq!(
+            cmd(&["setrange", "k", "-1", "v"]),
+            Err(CmdError::OffsetOutOfRange)
+        );
+        assert_eq!(
+            cmd(&["getex", "k", "PERSIST"]),
+            Ok(Cmd::GetEx(b("k"), Some(Expiry::Persist)))
+        );
+        assert_eq!(
+            cmd(&["getex", "k", "pxat", "5"]),
+            Ok(Cmd::GetEx(b("k"), Some(Expiry::At(5))))
+        );
+        assert_eq!(cmd(&["getex", "k", "ex"]), Err(CmdError::Syntax));
+        assert_eq!(cmd(&["getex", "k"]), Ok(Cmd::GetEx(b("k"), None)));
+    }
+
+    #[test]
+    fn test_incr_family() {
+        let key = Bytes::from("k");
+        assert_eq!(cmd(&["incr", "k"]), Ok(Cmd::IncrBy(key.clone(), 1)));
+        assert_eq!(cmd(&["DECR", "k"]), Ok(Cmd::IncrBy(key.clone(), -1)));
+        assert_eq!(
+            cmd(&["decrby", "k", "-9223372036854775807"]),
+            Ok(Cmd::IncrBy(key.clone(), i64::MAX))
+        );
+        assert_eq!(
+            cmd(&["decrby", "k", "-9223372036854775808"]),
+            Err(CmdError::DecrementOverflow)
+        );
+        assert_eq!(cmd(&["incrby", "k", "1.5"]), Err(CmdError::NotInteger));
+        assert_eq!(
+            cmd(&["incrbyfloat", "k", "1e2"]),
+            Ok(Cmd::IncrByFloat(key, 100.0))
+        );
+        assert_eq!(cmd(&["incrbyfloat", "k", "nan"]), Err(CmdError::NotFloat));
+    }
+
+    #[test]
+    fn test_keyspace() {
+        assert_eq!(
+            cmd(&["unlink", "a", "b"]),
+            Ok(Cmd::Del(vec![b("a"), b("b")]))
+        );
+        assert_eq!(
+            cmd(&["renamenx", "a", "b"]),
+            Ok(Cmd::Rename(b("a"), b("b"), true))
+        );
+        assert_eq!(
+            cmd(&["copy", "a", "b", "REPLACE"]),
+            Ok(Cmd::Copy(b("a"), b("b"), None, true))
+        );
+        assert_eq!(
+            cmd(&["copy", "a", "b", "db", "3"]),
+            Ok(Cmd::Copy(b("a"), b("b"), Some(3), false))
+        );
+        assert_eq!(cmd(&["copy", "a", "b", "x"]), Err(CmdError::Syntax));
+        assert_eq!(
+            cmd(&["exists"]),
+            Err(CmdError::WrongArity("exists".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_scan() {
+        assert_eq!(
+            cmd(&["scan", "17", "count", "100", "MATCH", "a*", "type", "String"]),
+            Ok(Cmd::Scan(
+                17,
+                ScanOptions {
+                    pattern: Some(Bytes::from("a*")),
+                    count: 100,
+                    type_name: Some("string".to_string()),
+                    novalues: false,
+                }
+            ))
+        );
+        assert_eq!(cmd(&["scan", "-1"]), Err(CmdError::InvalidCursor));
+        assert_eq!(cmd(&["scan", "0", "count", "0"]), Err(CmdError::Syntax));
+        assert_eq!(cmd(&["scan", "0", "match"]), Err(CmdError::Syntax));
+    }
+
+    #[test]
+    fn test_expire() {
+        let key = Bytes::from("k");
+        assert_eq!(
+            cmd(&["expire", "k", "10", "xx", "GT"]),
+            Ok(Cmd::Expire(
+                "expire",
+                key.clone(),
+                ExpireTime::In(10000),
+                ExpireCond {
+                    xx: true,
+                    gt: true,
+                    ..Default::default()
+                }
+            ))
+        );
+        assert_eq!(
+            cmd(&["pexpireat", "k", "-5"]),
+            Ok(Cmd::Expire(
+                "pexpireat",
+                key.clone(),
+                ExpireTime::At(-5),
+                ExpireCond::default()
+            ))
+        );
+        assert_eq!(
+            cmd(&["expire", "k", "9223372036854775807"]),
+            Err(CmdError::InvalidExpireTime("expire".to_string()))
+        );
+        assert_eq!(ExpireTime::At(-5).deadline(100, "pexpireat"), Ok(0));
+        assert_eq!(ExpireTime::In(-5).deadline(100, "pexpire"), Ok(95));
+        assert_eq!(
+            ExpireTime::In(i64::MAX).deadline(1, "pexpire"),
+            Err(CmdError::InvalidExpireTime("pexpire".to_string()))
+        );
+        assert_eq!(
+            cmd(&["expire", "k", "1", "nx", "gt"]),
+            Err(CmdError::ExpireNxConflict)
+        );
+        assert_eq!(
+            cmd(&["expire", "k", "1", "gt", "lt"]),
+            Err(CmdError::ExpireGtLtConflict)
+        );
+        assert_eq!(
+            cmd(&["expire", "k", "1", "foo"]),
+            Err(CmdError::UnsupportedOption("foo".to_string()))
+        );
+        assert_eq!(cmd(&["pexpiretime", "k"]), Ok(Cmd::Ttl(key, true, true)));
+
+        let cond = |opt: &str| {
+            let frame = RESP::new_cmd_array(["expire", "k", "1", opt].map(String::from).to_vec());
+            match Cmd::from(&frame) {
+                Ok(Cmd::Expire(_, _, _, cond)) => cond,
+                other => panic!("unexpected: {:?}", other),
+            }
+        };
+        assert!(cond("nx").allows(u128::MAX, 10));
+        assert!(!cond("nx").allows(20, 10));
+        assert!(!cond("xx").allows(u128::MAX, 10));
+        assert!(!cond("gt").allows(u128::MAX, 10));
+        assert!(cond("gt").allows(5, 10));
+        assert!(cond("lt").allows(u128::MAX, 10));
+        assert!(!cond("lt").allows(5, 10));
+    }
+
+    #[test]
+    fn test_databases() {
+        assert_eq!(cmd(&["select", "3"]), Ok(Cmd::Select(3)));
+        assert_eq!(cmd(&["select", "x"]), Err(CmdError::NotInteger));
+        assert_eq!(cmd(&["move", "k", "1"]), Ok(Cmd::Move(Bytes::from("k"), 1)));
+        assert_eq!(cmd(&["swapdb", "0", "1"]), Ok(Cmd::SwapDb(0, 1)));
+        assert_eq!(
+            cmd(&["swapdb", "0", "x"]),
+            Err(CmdError::InvalidDbIndex("second".to_string()))
+        );
+        assert_eq!(cmd(&["flushall", "ASYNC"]), Ok(Cmd::Flush(true, true)));
+        assert_eq!(cmd(&["flushdb"]), Ok(Cmd::Flush(false, false)));
+        assert_eq!(cmd(&["flushdb", "now"]), Err(CmdError::Syntax));
+        assert_eq!(cmd(&["dbsize"]), Ok(Cmd::DbSize));
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(
+            cmd(&["rpushx", "k", "a", "b"]),
+            Ok(Cmd::Push(b("k"), vec![b("a"), b("b")], false, true))
+        );
+        assert_eq!(
+            cmd(&["lpush", "k", "a"]),
+            Ok(Cmd::Push(b("k"), vec![b("a")], true, false))
+        );
+        assert_eq!(cmd(&["rpop", "k"]), Ok(Cmd::Pop(b("k"), None, false)));
+        assert_eq!(
+            cmd(&["lpop", "k", "0"]),
+            Ok(Cmd::Pop(b("k"), Some(0), true))
+        );
+        assert_eq!(cmd(&["lpop", "k", "-1"]), Err(CmdError::NotPositive));
+        assert_eq!(
+            cmd(&["linsert", "k", "BEFORE", "p", "e"]),
+            Ok(Cmd::LInsert(b("k"), true, b("p"), b("e")))
+        );
+        assert_eq!(cmd(&["linsert", "k", "x", "p", "e"]), Err(CmdError::Syntax));
+        assert_eq!(
+            cmd(&["lpos", "k", "e", "rank", "-2", "COUNT", "0", "maxlen", "5"]),
+            Ok(Cmd::LPos(
+                b("k"),
+                b("e"),
+                LPosOptions {
+                    rank: -2,
+                    count: Some(0),
+                    maxlen: 5
+                }
+            ))
+        );
+        assert_eq!(
+            cmd(&["lpos", "k", "e", "rank", "0"]),
+            Err(CmdError::RankZero)
+        );
+        assert_eq!(
+            cmd(&["lpos", "k", "e", "count", "-1"]),
+            Err(CmdError::NegativeOption("COUNT".to_string()))
+        );
+        assert_eq!(cmd(&["lpos", "k", "e", "count"]), Err(CmdError::Syntax));
+        assert_eq!(
+            cmd(&["lmove", "a", "b", "RIGHT", "left"]),
+            Ok(Cmd::LMove(b("a"), b("b"), false, true))
+        );
+        assert_eq!(
+            cmd(&["rpoplpush", "a", "b"]),
+            Ok(Cmd::LMove(b("a"), b("b"), false, true))
+        );
+        assert_eq!(
+            cmd(&["lmove", "a", "b", "up", "left"]),
+            Err(CmdError::Syntax)
+        );
+    }
+
+    #[test]
+    fn test_blocking_list() {
+        assert_eq!(
+            cmd(&["blpop", "a", "b", "0.5"]),
+            Ok(Cmd::BPop(
+                vec![b("a"), b("b")],
+                true,
+                Duration::from_millis(500)
+            ))
+        );
+        assert_eq!(cmd(&["brpop", "a", "x"]), Err(CmdError::InvalidTimeout));
+        assert_eq!(cmd(&["brpop", "a", "-1"]), Err(CmdError::NegativeTimeout));
+        assert_eq!(
+            cmd(&["brpoplpush", "a", "b", "0"]),
+            Ok(Cmd::BLMove(b("a"), b("b"), false, true, Duration::ZERO))
+        );
+        assert_eq!(
+            cmd(&["blmove", "a", "b", "left", "left", "1"]),
+            Ok(Cmd::BLMove(
+                b("a"),
+                b("b"),
+                true,
+                true,
+                Duration::from_secs(1)
+            ))
+        );
+        assert_eq!(
+            cmd(&["lmpop", "2", "a", "b", "RIGHT", "count", "3"]),
+            Ok(Cmd::LMPop(vec![b("a"), b("b")], false, 3, None))
+        );
+        assert_eq!(
+            cmd(&["blmpop", "0", "1", "a", "left"]),
+            Ok(Cmd::LMPop(vec![b("a")], true, 1, Some(Duration::ZERO)))
+        );
+        assert_eq!(
+            cmd(&["lmpop", "0", "a", "left"]),
+            Err(CmdError::NotGreaterThanZero("numkeys".to_string()))
+        );
+        assert_eq!(cmd(&["lmpop", "2", "a", "left"]), Err(CmdError::Syntax));
+        assert_eq!(
+            cmd(&["lmpop", "1", "a", "left", "count", "0"]),
+            Err(CmdError::NotGreaterThanZero("count".to_string()))
+        );
+        assert_eq!(
+            cmd(&["lmpop", "1", "a", "left", "count"]),
+            Err(CmdError::Syntax)
+        );
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(
+            cmd(&["hset", "h", "f1", "v1", "f2", "v2"]),
+            Ok(Cmd::HSet(
+                b("h"),
+                vec![(b("f1"), b("v1")), (b("f2"), b("v2"))],
+                false
+            ))
+        );
+        assert_eq!(
+            cmd(&["hmset", "h", "f1", "v1", "f2"]),
+            Err(CmdError::WrongArity("hmset".to_string()))
+        );
+        assert_eq!(
+            cmd(&["hincrbyfloat", "h", "f", "0.5"]),
+            Ok(Cmd::HIncrByFloat(b("h"), b("f"), 0.5))
+        );
+        assert_eq!(cmd(&["hincrby", "h", "f", "x"]), Err(CmdError::NotInteger));
+        assert_eq!(
+            cmd(&["hscan", "h", "0", "match", "f*", "NOVALUES"]),
+            Ok(Cmd::HScan(
+                b("h"),
+                0,
+                ScanOptions {
+                    pattern: Some(b("f*")),
+                    count: 10,
+                    type_name: None,
+                    novalues: true,
+                }
+            ))
+        );
+        assert_eq!(
+            cmd(&["hscan", "h", "0", "type", "hash"]),
+            Err(CmdError::Syntax)
+        );
+        assert_eq!(cmd(&["scan", "0", "novalues"]), Err(CmdError::Syntax));
+        assert_eq!(cmd(&["hrandfield", "h"]), Ok(Cmd::HRandField(b("h"), None)));
+        assert_eq!(
+            cmd(&["hrandfield", "h", "-5", "withvalues"]),
+            Ok(Cmd::HRandField(b("h"), Some((-5, true))))
+        );
+        assert_eq!(cmd(&["hrandfield", "h", "1", "x"]), Err(CmdError::Syntax));
+    }
+
+    #[test]
+    fn test_set() {
+        assert_eq!(
+            cmd(&["sadd", "s", "a", "1"]),
+            Ok(Cmd::SAdd(b("s"), vec![b("a"), b("1")]))
+        );
+        assert_eq!(
+            cmd(&["sadd", "s"]),
+            Err(CmdError::WrongArity("sadd".to_string()))
+        );
+        assert_eq!(cmd(&["spop", "s"]), Ok(Cmd::SPop(b("s"), None)));
+        assert_eq!(cmd(&["spop", "s", "-1"]), Err(CmdError::NotPositive));
+        assert_eq!(
+            cmd(&["srandmember", "s", "-3"]),
+            Ok(Cmd::SRandMember(b("s"), Some(-3)))
+        );
+        assert_eq!(
+            cmd(&["srandmember", "s", "1", "2"]),
+            Err(CmdError::WrongArity("srandmember".to_string()))
+        );
+        assert_eq!(
+            cmd(&["sscan", "s", "0", "count", "5"]),
+            Ok(Cmd::SScan(
+                b("s"),
+                0,
+                ScanOptions {
+                    pattern: None,
+                    count: 5,
+                    type_name: None,
+                    novalues: false,
+                }
+            ))
+        );
+        assert_eq!(cmd(&["sscan", "s", "0", "novalues"]), Err(CmdError::Syntax));
+        assert_eq!(
+            cmd(&["sunion", "a", "b"]),
+            Ok(Cmd::SCombine(SetOp::Union, vec![b("a"), b("b")]))
+        );
+        assert_eq!(
+            cmd(&["sdiffstore", "d", "a"]),
+            Ok(Cmd::SCombineStore(SetOp::Diff, b("d"), vec![b("a")]))
+        );
+        assert_eq!(
+            cmd(&["sintercard", "2", "a", "b", "LIMIT", "3"]),
+            Ok(Cmd::SInterCard(vec![b("a"), b("b")], 3))
+        );
+        assert_eq!(cmd(&["sintercard", "3", "a", "b"]), Err(CmdError::Syntax));
+        assert_eq!(
+            cmd(&["sintercard", "0", "a"]),
+            Err(CmdError::NotGreaterThanZero("numkeys".to_string()))
+        );
+        assert_eq!(
+            cmd(&["sintercard", "1", "a", "limit", "-1"]),
+            Err(CmdError::NegativeOption("LIMIT".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_zset() {
+        assert_eq!(
+            cmd(&["zadd", "z", "XX", "ch", "1", "a", "-inf", "b"]),
+            Ok(Cmd::ZAdd(
+                b("z"),
+                vec![(1.0, b("a")), (f64::NEG_INFINITY, b("b"))],
+                ZAddOptions {
+                    xx: true,
+                    ch: true,
+                    ..Default::default()
+                }
+            ))
+        );
+        assert_eq!(cmd(&["zadd", "z", "nx", "1"]), Err(CmdError::Syntax));
+        assert_eq!(
+            cmd(&["zadd", "z", "nx", "xx", "1", "a"]),
+            Err(CmdError::ZAddXxNx)
+        );
+        assert_eq!(
+            cmd(&["zadd", "z", "nx", "gt", "1", "a"]),
+            Err(CmdError::ZAddGtLtNx)
+        );
+        assert_eq!(
+            cmd(&["zadd", "z", "incr", "1", "a", "2", "b"]),
+            Err(CmdError::ZAddIncrPairs)
+        );
+        assert_eq!(cmd(&["zadd", "z", "nan", "a"]), Err(CmdError::NotFloat));
+        assert_eq!(
+            cmd(&["zrevrank", "z", "a", "withscore"]),
+            Ok(Cmd::ZRank(b("z"), b("a"), true, true))
+        );
+        assert_eq!(
+            cmd(&["zrange", "z", "(5", "1", "byscore", "rev", "limit", "1", "-1"]),
+            Ok(Cmd::ZRange(
+                b("z"),
+                ZRange {
+                    by: ZRangeBy::Score(ScoreRange {
+                        min: 1.0,
+                        minex: false,
+                        max: 5.0,
+                        maxex: true,
+                    }),
+                    rev: true,
+                    limit: Some((1, -1)),
+                    withscores: false,
+                }
+            ))
+        );
+        assert_eq!(
+            cmd(&["zrevrange", "z", "0", "-1", "withscores"]),
+            Ok(Cmd::ZRange(
+                b("z"),
+                ZRange {
+                    by: ZRangeBy::Rank(0, -1),
+                    rev: true,
+                    limit: None,
+                    withscores: true,
+                }
+            ))
+        );
+        assert_eq!(
+            cmd(&["zrange", "z", "0", "1", "limit", "0", "1"]),
+            Err(CmdError::LimitWithoutBy)
+        );
+        assert_eq!(
+            cmd(&["zrange", "z", "-", "+", "bylex", "withscores"]),
+            Err(CmdError::WithScoresByLex)
+        );
+        assert_eq!(
+            cmd(&["zrangebyscore", "z", "0", "1", "bylex"]),
+            Err(CmdError::Syntax)
+        );
+        assert_eq!(
+            cmd(&["zrangebylex", "z", "a", "+"]),
+            Err(CmdError::MinMaxNotString)
+        );
+    }
+
+    #[test]
+    fn test_zset_pop_and_combine() {
+        assert_eq!(
+            cmd(&["zremrangebyscore", "z", "-inf", "(1"]),
+            Ok(Cmd::ZRemRange(
+                b("z"),
+                ZRangeBy::Score(ScoreRange {
+                    min: f64::NEG_INFINITY,
+                    minex: false,
+                    max: 1.0,
+                    maxex: true,
+                })
+            ))
+        );
+        assert_eq!(cmd(&["zpopmax", "z", "-1"]), Err(CmdError::NotPositive));
+        assert_eq!(
+            cmd(&["bzpopmin", "a", "b", "0.5"]),
+            Ok(Cmd::BZPop(
+                vec![b("a"), b("b")],
+                false,
+                Duration::from_millis(500)
+            ))
+        );
+        assert_eq!(
+            cmd(&["bzmpop", "1", "2", "a", "b", "MAX", "count", "3"]),
+            Ok(Cmd::ZMPop(
+                vec![b("a"), b("b")],
+                true,
+                3,
+                Some(Duration::from_secs(1))
+            ))
+        );
+        assert_eq!(cmd(&["zmpop", "1", "a", "left"]), Err(CmdError::Syntax));
+        assert_eq!(
+            cmd(&[
+                "zunionstore",
+                "d",
+                "2",
+                "a",
+                "b",
+                "weights",
+                "2",
+                "3",
+                "aggregate",
+                "MAX"
+            ]),
+            Ok(Cmd::ZCombineStore(
+                SetOp::Union,
+                b("d"),
+                vec![b("a"), b("b")],
+                ZCombineOptions {
+                    weights: vec![2.0, 3.0],
+                    aggregate: Aggregate::Max,
+                    withscores: false,
+                }
+            ))
+        );
+        assert_eq!(
+            cmd(&["zinterstore", "d", "0", "a"]),
+            Err(CmdError::NoInputKeys("zinterstore".to_string()))
+        );
+        assert_eq!(
+            cmd(&["zinter", "2", "a", "b", "weights", "1", "x"]),
+            Err(CmdError::WeightNotFloat)
+        );
+        assert_eq!(
+            cmd(&["zinterstore", "d", "1", "a", "withscores"]),
+            Err(CmdError::Syntax)
+        );
+        assert_eq!(
+            cmd(&["zdiff", "2", "a", "b", "weights", "1", "1"]),
+            Err(CmdError::Syntax)
+        );
+        assert_eq!(
+            cmd(&["zdiff", "2", "a", "b", "withscores"]),
+            Ok(Cmd::ZCombine(
+                SetOp::Diff,
+                vec![b("a"), b("b")],
+                ZCombineOptions {
+                    withscores: true,
+                    ..Default::default()
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_stream() {
+        let id = |ms, seq| StreamId { ms, seq };
+        assert_eq!(
+            cmd(&[
+                "xadd",
+                "s",
+                "NOMKSTREAM",
+                "maxlen",
+                "~",
+                "10",
+                "LIMIT",
+                "5",
+                "*",
+                "f",
+                "v"
+            ]),
+            Ok(Cmd::XAdd(
+                b("s"),
+                XAddId::Auto,
+                vec![(b("f"), b("v"))],
+                XAddOptions {
+                    nomkstream: true,
+                    trim: Some(Trim {
+                        strategy: TrimStrategy::MaxLen(10),
+                        approx: true,
+                        limit: Some(5),
+                    }),
+                }
+            ))
+        );
+        // 不是选项的参数即为ID
+        assert_eq!(
+            cmd(&["xadd", "s", "5-*", "maxlen", "1"]),
+            Ok(Cmd::XAdd(
+                b("s"),
+                XAddId::AutoSeq(5),
+                vec![(b("maxlen"), b("1"))],
+                XAddOptions::default()
+            ))
+        );
+        assert_eq!(
+            cmd(&["xadd", "s", "*", "f", "v", "g"]),
+            Err(CmdError::WrongArity("xadd".to_string()))
+        );
+        assert_eq!(
+            cmd(&["xadd", "s", "0-0", "f", "v"]),
+            Err(CmdError::XAddIdZero)
+        );
+        assert_eq!(
+            cmd(&["xadd", "s", "maxlen", "1", "minid", "1", "*", "f", "v"]),
+            Err(CmdError::TrimMaxLenMinId)
+        );
+        assert_eq!(
+            cmd(&["xtrim", "s", "maxlen", "=", "1", "limit", "1"]),
+            Err(CmdError::TrimLimitWithoutApprox)
+        );
+        assert_eq!(
+            cmd(&["xtrim", "s", "maxlen", "-1"]),
+            Err(CmdError::StreamArgNegative("MAXLEN".to_string()))
+        );
+        assert_eq!(
+            cmd(&["xtrim", "s", "minid", "5"]),
+            Ok(Cmd::XTrim(
+                b("s"),
+                Trim {
+                    strategy: TrimStrategy::MinId(id(5, 0)),
+                    approx: false,
+                    limit: None,
+                }
+            ))
+        );
+        assert_eq!(
+            cmd(&["xtrim", "s", "limit", "1", "x"]),
+            Err(CmdError::Syntax)
+        );
+        assert_eq!(
+            cmd(&["xdel", "s", "1-1", "2"]),
+            Ok(Cmd::XDel(b("s"), vec![id(1, 1), id(2, 0)]))
+        );
+        assert_eq!(
+            cmd(&["xrevrange", "s", "+", "(5", "count", "-3"]),
+            Ok(Cmd::XRange(b("s"), id(5, 1), StreamId::MAX, true, Some(0)))
+        );
+        assert_eq!(
+            cmd(&["xrange", "s", "-", "+", "count"]),
+            Err(CmdError::Syntax)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            cmd(&["foo", "a", "b"]),
+            Err(CmdError::UnknownCommand(
+                "foo".to_string(),
+                "'a' 'b' ".to_string()
+            ))
+        );
+        assert_eq!(cmd(&["get"]), Err(CmdError::WrongArity("get".to_string())));
+        assert_eq!(
+            cmd(&["get", "a", "b"]),
+            Err(CmdError::WrongArity("get".to_string()))
+        );
+        assert_eq!(cmd(&["set", "a", "b", "ttl", "1"]), Err(CmdError::Syntax));
+        assert_eq!(
+            cmd(&["set", "a", "b", "px", "x"]),
+            Err(CmdError::NotInteger)
+        );
+        assert_eq!(
+            CmdError::UnknownCommand("foo".to_string(), "'a' ".to_string()).to_string(),
+            "ERR unknown command 'foo', with args beginning with: 'a' "
+        );
+    }
+
+    #[test]
+    fn test_hello() {
+        assert_eq!(cmd(&["hello"]), Ok(Cmd::Hello(None, None, None)));
+        assert_eq!(
+            cmd(&["HELLO", "3", "AUTH", "default", "pw", "SETNAME", "worker"]),
+            Ok(Cmd::Hello(
+                Some(Protocol::Resp3),
+                Some((Bytes::from("default"), Bytes::from("pw"))),
+                Some(Bytes::from("worker"))
+            ))
+        );
+        assert_eq!(cmd(&["hello", "4"]), Err(CmdError::NoProto));
+        assert_eq!(cmd(&["hello", "x"]), Err(CmdError::InvalidProtoVersion));
+        assert_eq!(
+            cmd(&["hello", "3", "auth", "default"]),
+            Err(CmdError::HelloOption("auth".to_string()))
+        );
+    }
 }
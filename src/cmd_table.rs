@@ -0,0 +1,1875 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use bytes::Bytes;
+
+use crate::{db::parse_i64_strict, error::CmdError, frame::RESP};
+
+// 命令标志, 与redis中的CMD_*对应
+pub const CMD_WRITE: u32 = 1 << 0;
+pub const CMD_READONLY: u32 = 1 << 1;
+pub const CMD_DENYOOM: u32 = 1 << 2;
+pub const CMD_ADMIN: u32 = 1 << 3;
+pub const CMD_PUBSUB: u32 = 1 << 4;
+pub const CMD_NOSCRIPT: u32 = 1 << 5;
+pub const CMD_BLOCKING: u32 = 1 << 6;
+pub const CMD_LOADING: u32 = 1 << 7;
+pub const CMD_STALE: u32 = 1 << 8;
+pub const CMD_FAST: u32 = 1 << 9;
+pub const CMD_NO_AUTH: u32 = 1 << 10;
+pub const CMD_MOVABLE_KEYS: u32 = 1 << 11;
+
+const FLAG_NAMES: &[(u32, &str)] = &[
+    (CMD_WRITE, "write"),
+    (CMD_READONLY, "readonly"),
+    (CMD_DENYOOM, "denyoom"),
+    (CMD_ADMIN, "admin"),
+    (CMD_PUBSUB, "pubsub"),
+    (CMD_NOSCRIPT, "noscript"),
+    (CMD_BLOCKING, "blocking"),
+    (CMD_LOADING, "loading"),
+    (CMD_STALE, "stale"),
+    (CMD_FAST, "fast"),
+    (CMD_NO_AUTH, "no_auth"),
+    (CMD_MOVABLE_KEYS, "movablekeys"),
+];
+
+/// 命令的元信息: 名称, 参数个数, 标志以及key所在的位置
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// 包括命令名在内的参数个数, 负数表示至少-arity个
+    pub arity: i32,
+    pub flags: u32,
+    /// 第一个key的下标, 0表示没有key
+    pub first_key: i32,
+    /// 最后一个key的下标, 负数表示从末尾倒数
+    pub last_key: i32,
+    pub key_step: i32,
+    /// numkeys参数的下标, 其后的numkeys个参数都是key; 0表示没有
+    pub numkeys_index: i32,
+    pub group: &'static str,
+    pub since: &'static str,
+    pub summary: &'static str,
+}
+
+const fn spec(
+    name: &'static str,
+    arity: i32,
+    flags: u32,
+    keys: (i32, i32, i32),
+    group: &'static str,
+    since: &'static str,
+    summary: &'static str,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        first_key: keys.0,
+        last_key: keys.1,
+        key_step: keys.2,
+        numkeys_index: 0,
+        group,
+        since,
+        summary,
+    }
+}
+
+const NO_KEYS: (i32, i32, i32) = (0, 0, 0);
+
+// 不在命令表中的帧(例如FULLRESYNC回复)使用的占位
+pub static NO_COMMAND: &CommandSpec = &spec("", 0, 0, NO_KEYS, "", "", "");
+
+static COMMAND_TABLE: &[CommandSpec] = &[
+    spec(
+        "ping",
+        -1,
+        CMD_FAST | CMD_STALE,
+        NO_KEYS,
+        "connection",
+        "1.0.0",
+        "Returns the server's liveliness response.",
+    ),
+    spec(
+        "echo",
+        2,
+        CMD_FAST,
+        NO_KEYS,
+        "connection",
+        "1.0.0",
+        "Returns the given string.",
+    ),
+    spec(
+        "hello",
+        -1,
+        CMD_NOSCRIPT | CMD_LOADING | CMD_STALE | CMD_FAST | CMD_NO_AUTH,
+        NO_KEYS,
+        "connection",
+        "6.0.0",
+        "Handshakes with the Redis server.",
+    ),
+    spec(
+        "select",
+        2,
+        CMD_LOADING | CMD_STALE | CMD_FAST,
+        NO_KEYS,
+        "connection",
+        "1.0.0",
+        "Changes the selected database.",
+    ),
+    spec(
+        "set",
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, 1, 1),
+        "string",
+        "1.0.0",
+        "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+    ),
+    spec(
+        "get",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "1.0.0",
+        "Returns the string value of a key.",
+    ),
+    spec(
+        "mget",
+        -2,
+        CMD_READONLY | CMD_FAST,
+        (1, -1, 1),
+        "string",
+        "1.0.0",
+        "Atomically returns the string values of one or more keys.",
+    ),
+    spec(
+        "mset",
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, -1, 2),
+        "string",
+        "1.0.1",
+        "Atomically creates or modifies the string values of one or more keys.",
+    ),
+    spec(
+        "msetnx",
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, -1, 2),
+        "string",
+        "1.0.1",
+        "Atomically modifies the string values of one or more keys only when all keys don't exist.",
+    ),
+    spec(
+        "setnx",
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "1.0.0",
+        "Set the string value of a key only when the key doesn't exist.",
+    ),
+    spec(
+        "setex",
+        4,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, 1, 1),
+        "string",
+        "2.0.0",
+        "Sets the string value and expiration time of a key. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "psetex",
+        4,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, 1, 1),
+        "string",
+        "2.6.0",
+        "Sets both string value and expiration time in milliseconds of a key. The key is created if it doesn't exist.",
+    ),
+    spec(
+        "append",
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "2.0.0",
+        "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "strlen",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "2.2.0",
+        "Returns the length of a string value.",
+    ),
+    spec(
+        "getrange",
+        4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "string",
+        "2.4.0",
+        "Returns a substring of the string stored at a key.",
+    ),
+    spec(
+        "setrange",
+        4,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, 1, 1),
+        "string",
+        "2.2.0",
+        "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "getdel",
+        2,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "6.2.0",
+        "Returns the string value of a key after deleting the key.",
+    ),
+    spec(
+        "getex",
+        -2,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "6.2.0",
+        "Returns the string value of a key after setting its expiration time.",
+    ),
+    spec(
+        "incr",
+        2,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "1.0.0",
+        "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+    ),
+    spec(
+        "decr",
+        2,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "1.0.0",
+        "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+    ),
+    spec(
+        "incrby",
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "1.0.0",
+        "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+    ),
+    spec(
+        "decrby",
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "1.0.0",
+        "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+    ),
+    spec(
+        "incrbyfloat",
+        3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "string",
+        "2.6.0",
+        "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+    ),
+    spec(
+        "del",
+        -2,
+        CMD_WRITE,
+        (1, -1, 1),
+        "generic",
+        "1.0.0",
+        "Deletes one or more keys.",
+    ),
+    spec(
+        "unlink",
+        -2,
+        CMD_WRITE | CMD_FAST,
+        (1, -1, 1),
+        "generic",
+        "4.0.0",
+        "Asynchronously deletes one or more keys.",
+    ),
+    spec(
+        "exists",
+        -2,
+        CMD_READONLY | CMD_FAST,
+        (1, -1, 1),
+        "generic",
+        "1.0.0",
+        "Determines whether one or more keys exist.",
+    ),
+    spec(
+        "touch",
+        -2,
+        CMD_READONLY | CMD_FAST,
+        (1, -1, 1),
+        "generic",
+        "3.2.1",
+        "Returns the number of existing keys out of those specified after updating the time they were last accessed.",
+    ),
+    spec(
+        "type",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "1.0.0",
+        "Determines the type of value stored at a key.",
+    ),
+    spec(
+        "rename",
+        3,
+        CMD_WRITE,
+        (1, 2, 1),
+        "generic",
+        "1.0.0",
+        "Renames a key and overwrites the destination.",
+    ),
+    spec(
+        "renamenx",
+        3,
+        CMD_WRITE | CMD_FAST,
+        (1, 2, 1),
+        "generic",
+        "1.0.0",
+        "Renames a key only when the target key name doesn't exist.",
+    ),
+    spec(
+        "copy",
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, 2, 1),
+        "generic",
+        "6.2.0",
+        "Copies the value of a key to a new key.",
+    ),
+    spec(
+        "keys",
+        2,
+        CMD_READONLY,
+        NO_KEYS,
+        "generic",
+        "1.0.0",
+        "Returns all key names that match a pattern.",
+    ),
+    spec(
+        "scan",
+        -2,
+        CMD_READONLY,
+        NO_KEYS,
+        "generic",
+        "2.8.0",
+        "Iterates over the key names in the database.",
+    ),
+    spec(
+        "expire",
+        -3,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "1.0.0",
+        "Sets the expiration time of a key in seconds.",
+    ),
+    spec(
+        "pexpire",
+        -3,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "2.6.0",
+        "Sets the expiration time of a key in milliseconds.",
+    ),
+    spec(
+        "expireat",
+        -3,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "1.2.0",
+        "Sets the expiration time of a key to a Unix timestamp.",
+    ),
+    spec(
+        "pexpireat",
+        -3,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "2.6.0",
+        "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+    ),
+    spec(
+        "ttl",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "1.0.0",
+        "Returns the expiration time in seconds of a key.",
+    ),
+    spec(
+        "pttl",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "2.6.0",
+        "Returns the expiration time in milliseconds of a key.",
+    ),
+    spec(
+        "expiretime",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "7.0.0",
+        "Returns the expiration time of a key as a Unix timestamp.",
+    ),
+    spec(
+        "pexpiretime",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "7.0.0",
+        "Returns the expiration time of a key as a Unix milliseconds timestamp.",
+    ),
+    spec(
+        "persist",
+        2,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "2.2.0",
+        "Removes the expiration time of a key.",
+    ),
+    spec(
+        "move",
+        3,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "generic",
+        "1.0.0",
+        "Moves a key to another database.",
+    ),
+    spec(
+        "lpush",
+        -3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Prepends one or more elements to a list. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "rpush",
+        -3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Appends one or more elements to a list. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "lpushx",
+        -3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "list",
+        "2.2.0",
+        "Prepends one or more elements to a list only when the list exists.",
+    ),
+    spec(
+        "rpushx",
+        -3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "list",
+        "2.2.0",
+        "Appends an element to a list only when the list exists.",
+    ),
+    spec(
+        "lpop",
+        -2,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Returns the first elements in a list after removing it. Deletes the list if the last element was popped.",
+    ),
+    spec(
+        "rpop",
+        -2,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Returns and removes the last elements of a list. Deletes the list if the last element was popped.",
+    ),
+    spec(
+        "llen",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Returns the length of a list.",
+    ),
+    spec(
+        "lrange",
+        4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Returns a range of elements from a list.",
+    ),
+    spec(
+        "lindex",
+        3,
+        CMD_READONLY,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Returns an element from a list by its index.",
+    ),
+    spec(
+        "lset",
+        4,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Sets the value of an element in a list by its index.",
+    ),
+    spec(
+        "lrem",
+        4,
+        CMD_WRITE,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Removes elements from a list. Deletes the list if the last element was removed.",
+    ),
+    spec(
+        "ltrim",
+        4,
+        CMD_WRITE,
+        (1, 1, 1),
+        "list",
+        "1.0.0",
+        "Removes elements from both ends a list. Deletes the list if all elements were trimmed.",
+    ),
+    spec(
+        "linsert",
+        5,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, 1, 1),
+        "list",
+        "2.2.0",
+        "Inserts an element before or after another element in a list.",
+    ),
+    spec(
+        "lpos",
+        -3,
+        CMD_READONLY,
+        (1, 1, 1),
+        "list",
+        "6.0.6",
+        "Returns the index of matching elements in a list.",
+    ),
+    spec(
+        "lmove",
+        5,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, 2, 1),
+        "list",
+        "6.2.0",
+        "Returns an element after popping it from one list and pushing it to another. Deletes the list if the last element was moved.",
+    ),
+    spec(
+        "rpoplpush",
+        3,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, 2, 1),
+        "list",
+        "1.2.0",
+        "Returns the last element of a list after removing and pushing it to another list. Deletes the list if the last element was popped.",
+    ),
+    spec(
+        "blpop",
+        -3,
+        CMD_WRITE | CMD_BLOCKING,
+        (1, -2, 1),
+        "list",
+        "2.0.0",
+        "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+    ),
+    spec(
+        "brpop",
+        -3,
+        CMD_WRITE | CMD_BLOCKING,
+        (1, -2, 1),
+        "list",
+        "2.0.0",
+        "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+    ),
+    spec(
+        "blmove",
+        6,
+        CMD_WRITE | CMD_DENYOOM | CMD_BLOCKING,
+        (1, 2, 1),
+        "list",
+        "6.2.0",
+        "Pops an element from a list, pushes it to another list and returns it. Blocks until an element is available otherwise. Deletes the list if the last element was moved.",
+    ),
+    spec(
+        "brpoplpush",
+        4,
+        CMD_WRITE | CMD_DENYOOM | CMD_BLOCKING,
+        (1, 2, 1),
+        "list",
+        "2.2.0",
+        "Pops an element from a list, pushes it to another list and returns it. Block until an element is available otherwise. Deletes the list if the last element was popped.",
+    ),
+    spec(
+        "lmpop",
+        -4,
+        CMD_WRITE | CMD_MOVABLE_KEYS,
+        NO_KEYS,
+        "list",
+        "7.0.0",
+        "Returns multiple elements from a list after removing them. Deletes the list if the last element was popped.",
+    ).with_numkeys(1),
+    spec(
+        "blmpop",
+        -5,
+        CMD_WRITE | CMD_BLOCKING | CMD_MOVABLE_KEYS,
+        NO_KEYS,
+        "list",
+        "7.0.0",
+        "Pops the first element from one of multiple lists. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+    ).with_numkeys(2),
+    spec(
+        "hset",
+        -4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Creates or modifies the value of a field in a hash.",
+    ),
+    spec(
+        "hsetnx",
+        4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Sets the value of a field in a hash only when the field doesn't exist.",
+    ),
+    spec(
+        "hmset",
+        -4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Sets the values of multiple fields.",
+    ),
+    spec(
+        "hget",
+        3,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Returns the value of a field in a hash.",
+    ),
+    spec(
+        "hmget",
+        -3,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Returns the values of all fields in a hash.",
+    ),
+    spec(
+        "hgetall",
+        2,
+        CMD_READONLY,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Returns all fields and values in a hash.",
+    ),
+    spec(
+        "hdel",
+        -3,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain.",
+    ),
+    spec(
+        "hincrby",
+        4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field doesn't exist.",
+    ),
+    spec(
+        "hincrbyfloat",
+        4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.6.0",
+        "Increments the floating point value of a field by a number. Uses 0 as initial value if the field doesn't exist.",
+    ),
+    spec(
+        "hkeys",
+        2,
+        CMD_READONLY,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Returns all fields in a hash.",
+    ),
+    spec(
+        "hvals",
+        2,
+        CMD_READONLY,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Returns all values in a hash.",
+    ),
+    spec(
+        "hlen",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Returns the number of fields in a hash.",
+    ),
+    spec(
+        "hexists",
+        3,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "2.0.0",
+        "Determines whether a field exists in a hash.",
+    ),
+    spec(
+        "hstrlen",
+        3,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "hash",
+        "3.2.0",
+        "Returns the length of the value of a field.",
+    ),
+    spec(
+        "hscan",
+        -3,
+        CMD_READONLY,
+        (1, 1, 1),
+        "hash",
+        "2.8.0",
+        "Iterates over fields and values of a hash.",
+    ),
+    spec(
+        "hrandfield",
+        -2,
+        CMD_READONLY,
+        (1, 1, 1),
+        "hash",
+        "6.2.0",
+        "Returns one or more random fields from a hash.",
+    ),
+    spec(
+        "sadd",
+        -3,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "set",
+        "1.0.0",
+        "Adds one or more members to a set. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "srem",
+        -3,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "set",
+        "1.0.0",
+        "Removes one or more members from a set. Deletes the set if the last member was removed.",
+    ),
+    spec(
+        "smembers",
+        2,
+        CMD_READONLY,
+        (1, 1, 1),
+        "set",
+        "1.0.0",
+        "Returns all members of a set.",
+    ),
+    spec(
+        "sismember",
+        3,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "set",
+        "1.0.0",
+        "Determines whether a member belongs to a set.",
+    ),
+    spec(
+        "smismember",
+        -3,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "set",
+        "6.2.0",
+        "Determines whether multiple members belong to a set.",
+    ),
+    spec(
+        "scard",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "set",
+        "1.0.0",
+        "Returns the number of members in a set.",
+    ),
+    spec(
+        "spop",
+        -2,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "set",
+        "1.0.0",
+        "Returns one or more random members from a set after removing them. Deletes the set if the last member was popped.",
+    ),
+    spec(
+        "srandmember",
+        -2,
+        CMD_READONLY,
+        (1, 1, 1),
+        "set",
+        "1.0.0",
+        "Get one or multiple random members from a set",
+    ),
+    spec(
+        "smove",
+        4,
+        CMD_WRITE | CMD_FAST,
+        (1, 2, 1),
+        "set",
+        "1.0.0",
+        "Moves a member from one set to another.",
+    ),
+    spec(
+        "sscan",
+        -3,
+        CMD_READONLY,
+        (1, 1, 1),
+        "set",
+        "2.8.0",
+        "Iterates over members of a set.",
+    ),
+    spec(
+        "sinter",
+        -2,
+        CMD_READONLY,
+        (1, -1, 1),
+        "set",
+        "1.0.0",
+        "Returns the intersect of multiple sets.",
+    ),
+    spec(
+        "sinterstore",
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, -1, 1),
+        "set",
+        "1.0.0",
+        "Stores the intersect of multiple sets in a key.",
+    ),
+    spec(
+        "sintercard",
+        -3,
+        CMD_READONLY | CMD_MOVABLE_KEYS,
+        NO_KEYS,
+        "set",
+        "7.0.0",
+        "Returns the number of members of the intersect of multiple sets.",
+    ).with_numkeys(1),
+    spec(
+        "sunion",
+        -2,
+        CMD_READONLY,
+        (1, -1, 1),
+        "set",
+        "1.0.0",
+        "Returns the union of multiple sets.",
+    ),
+    spec(
+        "sunionstore",
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, -1, 1),
+        "set",
+        "1.0.0",
+        "Stores the union of multiple sets in a key.",
+    ),
+    spec(
+        "sdiff",
+        -2,
+        CMD_READONLY,
+        (1, -1, 1),
+        "set",
+        "1.0.0",
+        "Returns the difference of multiple sets.",
+    ),
+    spec(
+        "sdiffstore",
+        -3,
+        CMD_WRITE | CMD_DENYOOM,
+        (1, -1, 1),
+        "set",
+        "1.0.0",
+        "Stores the difference of multiple sets in a key.",
+    ),
+    spec(
+        "zadd",
+        -4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "sorted-set",
+        "1.2.0",
+        "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "zincrby",
+        4,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "sorted-set",
+        "1.2.0",
+        "Increments the score of a member in a sorted set.",
+    ),
+    spec(
+        "zrem",
+        -3,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "sorted-set",
+        "1.2.0",
+        "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed.",
+    ),
+    spec(
+        "zscore",
+        3,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "sorted-set",
+        "1.2.0",
+        "Returns the score of a member in a sorted set.",
+    ),
+    spec(
+        "zcard",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "sorted-set",
+        "1.2.0",
+        "Returns the number of members in a sorted set.",
+    ),
+    spec(
+        "zrank",
+        -3,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "sorted-set",
+        "2.0.0",
+        "Returns the index of a member in a sorted set ordered by ascending scores.",
+    ),
+    spec(
+        "zrevrank",
+        -3,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "sorted-set",
+        "2.0.0",
+        "Returns the index of a member in a sorted set ordered by descending scores.",
+    ),
+    spec(
+        "zrange",
+        -4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "sorted-set",
+        "1.2.0",
+        "Returns members in a sorted set within a range of indexes.",
+    ),
+    spec(
+        "zrevrange",
+        -4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "sorted-set",
+        "1.2.0",
+        "Returns members in a sorted set within a range of indexes in reverse order.",
+    ),
+    spec(
+        "zrangebyscore",
+        -4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "sorted-set",
+        "1.0.5",
+        "Returns members in a sorted set within a range of scores.",
+    ),
+    spec(
+        "zrevrangebyscore",
+        -4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "sorted-set",
+        "2.2.0",
+        "Returns members in a sorted set within a range of scores in reverse order.",
+    ),
+    spec(
+        "zrangebylex",
+        -4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "sorted-set",
+        "2.8.9",
+        "Returns members in a sorted set within a lexicographical range.",
+    ),
+    spec(
+        "zrevrangebylex",
+        -4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "sorted-set",
+        "2.8.9",
+        "Returns members in a sorted set within a lexicographical range in reverse order.",
+    ),
+    spec(
+        "zremrangebyrank",
+        4,
+        CMD_WRITE,
+        (1, 1, 1),
+        "sorted-set",
+        "2.0.0",
+        "Removes members in a sorted set within a range of indexes. Deletes the sorted set if all members were removed.",
+    ),
+    spec(
+        "zremrangebyscore",
+        4,
+        CMD_WRITE,
+        (1, 1, 1),
+        "sorted-set",
+        "1.2.0",
+        "Removes members in a sorted set within a range of scores. Deletes the sorted set if all members were removed.",
+    ),
+    spec(
+        "zremrangebylex",
+        4,
+        CMD_WRITE,
+        (1, 1, 1),
+        "sorted-set",
+        "2.8.9",
+        "Removes members in a sorted set within a lexicographical range. Deletes the sorted set if all members were removed.",
+    ),
+    spec(
+        "zpopmin",
+        -2,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "sorted-set",
+        "5.0.0",
+        "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+    ),
+    spec(
+        "zpopmax",
+        -2,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "sorted-set",
+        "5.0.0",
+        "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+    ),
+    spec(
+        "bzpopmin",
+        -3,
+        CMD_WRITE | CMD_FAST | CMD_BLOCKING,
+        (1, -2, 1),
+        "sorted-set",
+        "5.0.0",
+        "Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+    ),
+    spec(
+        "bzpopmax",
+        -3,
+        CMD_WRITE | CMD_FAST | CMD_BLOCKING,
+        (1, -2, 1),
+        "sorted-set",
+        "5.0.0",
+        "Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member available otherwise. Deletes the sorted set if the last element was popped.",
+    ),
+    spec(
+        "zmpop",
+        -4,
+        CMD_WRITE | CMD_MOVABLE_KEYS,
+        NO_KEYS,
+        "sorted-set",
+        "7.0.0",
+        "Returns the highest- or lowest-scoring members from one or more sorted sets after removing them. Deletes the sorted set if the last member was popped.",
+    ).with_numkeys(1),
+    spec(
+        "bzmpop",
+        -5,
+        CMD_WRITE | CMD_BLOCKING | CMD_MOVABLE_KEYS,
+        NO_KEYS,
+        "sorted-set",
+        "7.0.0",
+        "Removes and returns a member by score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+    ).with_numkeys(2),
+    spec(
+        "zunion",
+        -3,
+        CMD_READONLY | CMD_MOVABLE_KEYS,
+        NO_KEYS,
+        "sorted-set",
+        "6.2.0",
+        "Returns the union of multiple sorted sets.",
+    ).with_numkeys(1),
+    spec(
+        "zinter",
+        -3,
+        CMD_READONLY | CMD_MOVABLE_KEYS,
+        NO_KEYS,
+        "sorted-set",
+        "6.2.0",
+        "Returns the intersect of multiple sorted sets.",
+    ).with_numkeys(1),
+    spec(
+        "zdiff",
+        -3,
+        CMD_READONLY | CMD_MOVABLE_KEYS,
+        NO_KEYS,
+        "sorted-set",
+        "6.2.0",
+        "Returns the difference between multiple sorted sets.",
+    ).with_numkeys(1),
+    spec(
+        "zunionstore",
+        -4,
+        CMD_WRITE | CMD_DENYOOM | CMD_MOVABLE_KEYS,
+        (1, 1, 1),
+        "sorted-set",
+        "2.0.0",
+        "Stores the union of multiple sorted sets in a key.",
+    ).with_numkeys(2),
+    spec(
+        "zinterstore",
+        -4,
+        CMD_WRITE | CMD_DENYOOM | CMD_MOVABLE_KEYS,
+        (1, 1, 1),
+        "sorted-set",
+        "2.0.0",
+        "Stores the intersect of multiple sorted sets in a key.",
+    ).with_numkeys(2),
+    spec(
+        "zdiffstore",
+        -4,
+        CMD_WRITE | CMD_DENYOOM | CMD_MOVABLE_KEYS,
+        (1, 1, 1),
+        "sorted-set",
+        "6.2.0",
+        "Stores the difference of multiple sorted sets in a key.",
+    ).with_numkeys(2),
+    spec(
+        "xadd",
+        -5,
+        CMD_WRITE | CMD_DENYOOM | CMD_FAST,
+        (1, 1, 1),
+        "stream",
+        "5.0.0",
+        "Appends a new message to a stream. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "xtrim",
+        -4,
+        CMD_WRITE,
+        (1, 1, 1),
+        "stream",
+        "5.0.0",
+        "Deletes messages from the beginning of a stream.",
+    ),
+    spec(
+        "xdel",
+        -3,
+        CMD_WRITE | CMD_FAST,
+        (1, 1, 1),
+        "stream",
+        "5.0.0",
+        "Returns the number of messages after removing them from a stream.",
+    ),
+    spec(
+        "xlen",
+        2,
+        CMD_READONLY | CMD_FAST,
+        (1, 1, 1),
+        "stream",
+        "5.0.0",
+        "Return the number of messages in a stream.",
+    ),
+    spec(
+        "xrange",
+        -4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "stream",
+        "5.0.0",
+        "Returns the messages from a stream within a range of IDs.",
+    ),
+    spec(
+        "xrevrange",
+        -4,
+        CMD_READONLY,
+        (1, 1, 1),
+        "stream",
+        "5.0.0",
+        "Returns the messages from a stream within a range of IDs in reverse order.",
+    ),
+    spec(
+        "info",
+        -1,
+        CMD_LOADING | CMD_STALE,
+        NO_KEYS,
+        "server",
+        "1.0.0",
+        "Returns information and statistics about the server.",
+    ),
+    spec(
+        "dbsize",
+        1,
+        CMD_READONLY | CMD_FAST,
+        NO_KEYS,
+        "server",
+        "1.0.0",
+        "Returns the number of keys in the database.",
+    ),
+    spec(
+        "flushdb",
+        -1,
+        CMD_WRITE,
+        NO_KEYS,
+        "server",
+        "1.0.0",
+        "Remove all keys from the current database.",
+    ),
+    spec(
+        "flushall",
+        -1,
+        CMD_WRITE,
+        NO_KEYS,
+        "server",
+        "1.0.0",
+        "Removes all keys from all databases.",
+    ),
+    spec(
+        "swapdb",
+        3,
+        CMD_WRITE | CMD_FAST,
+        NO_KEYS,
+        "server",
+        "4.0.0",
+        "Swaps two Redis databases.",
+    ),
+    spec(
+        "replconf",
+        -1,
+        CMD_ADMIN | CMD_NOSCRIPT | CMD_LOADING | CMD_STALE,
+        NO_KEYS,
+        "server",
+        "3.0.0",
+        "An internal command for configuring the replication stream.",
+    ),
+    spec(
+        "psync",
+        -3,
+        CMD_ADMIN | CMD_NOSCRIPT,
+        NO_KEYS,
+        "server",
+        "2.8.0",
+        "An internal command used in replication.",
+    ),
+    spec(
+        "wait",
+        3,
+        CMD_NOSCRIPT,
+        NO_KEYS,
+        "generic",
+        "3.0.0",
+        "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.",
+    ),
+    spec(
+        "command",
+        -1,
+        CMD_LOADING | CMD_STALE,
+        NO_KEYS,
+        "server",
+        "2.8.13",
+        "Returns detailed information about all commands.",
+    ),
+];
+
+fn table() -> &'static HashMap<&'static str, &'static CommandSpec> {
+    static TABLE: OnceLock<HashMap<&'static str, &'static CommandSpec>> = OnceLock::new();
+    TABLE.get_or_init(|| COMMAND_TABLE.iter().map(|spec| (spec.name, spec)).collect())
+}
+
+/// 按小写的命令名查找
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    table().get(name).copied()
+}
+
+/// 查找命令数组对应的命令, 命令名不区分大小写
+pub fn lookup_frame(frame: &RESP) -> Option<&'static CommandSpec> {
+    match frame {
+        RESP::Array(arr) => match arr.first() {
+            Some(RESP::Bulk(name)) => lookup(&lowercase(name)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// acl分类由标志和命令所属的分组推导, 规则与redis一致
+fn group_category(group: &str) -> Option<&'static str> {
+    match group {
+        "string" => Some("@string"),
+        "list" => Some("@list"),
+        "hash" => Some("@hash"),
+        "set" => Some("@set"),
+        "sorted-set" => Some("@sortedset"),
+        "stream" => Some("@stream"),
+        "generic" => Some("@keyspace"),
+        "connection" => Some("@connection"),
+        _ => None,
+    }
+}
+
+// 单个key spec: 从begin_search给出的下标开始, 按find_keys的方式查找key
+fn key_spec(
+    flags: [&str; 2],
+    index: i32,
+    find_type: &'static str,
+    find_spec: &[(&'static str, i32)],
+) -> RESP {
+    RESP::Map(vec![
+        (
+            RESP::new_bulk("flags"),
+            RESP::Set(
+                flags
+                    .iter()
+                    .map(|f| RESP::new_simple(f.to_string()))
+                    .collect(),
+            ),
+        ),
+        (
+            RESP::new_bulk("begin_search"),
+            RESP::Map(vec![
+                (RESP::new_bulk("type"), RESP::new_bulk("index")),
+                (
+                    RESP::new_bulk("spec"),
+                    RESP::Map(vec![(RESP::new_bulk("index"), RESP::Integer(index as i64))]),
+                ),
+            ]),
+        ),
+        (
+            RESP::new_bulk("find_keys"),
+            RESP::Map(vec![
+                (RESP::new_bulk("type"), RESP::new_bulk(find_type)),
+                (
+                    RESP::new_bulk("spec"),
+                    RESP::Map(
+                        find_spec
+                            .iter()
+                            .map(|(name, n)| (RESP::new_bulk(*name), RESP::Integer(*n as i64)))
+                            .collect(),
+                    ),
+                ),
+            ]),
+        ),
+    ])
+}
+
+impl CommandSpec {
+    /// 自定义命令使用的构造方法, keys为(first_key, last_key, key_step)
+    pub const fn new(name: &'static str, arity: i32, flags: u32, keys: (i32, i32, i32)) -> Self {
+        spec(name, arity, flags, keys, "module", "", "")
+    }
+
+    /// key的个数由numkeys参数给出, index为numkeys的下标; 与keys给出的key(例如destination)可以同时存在
+    pub const fn with_numkeys(mut self, index: i32) -> Self {
+        self.numkeys_index = index;
+        self
+    }
+
+    pub fn has(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.has(CMD_WRITE)
+    }
+
+    /// argc包括命令名本身
+    pub fn check_arity(&self, argc: usize) -> Result<(), CmdError> {
+        let argc = argc as i32;
+        if (self.arity > 0 && argc != self.arity) || argc < -self.arity {
+            Err(CmdError::WrongArity(self.name.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn acl_categories(&self) -> Vec<&'static str> {
+        let mut categories = vec![];
+        if self.has(CMD_WRITE) {
+            categories.push("@write");
+        }
+        if self.has(CMD_READONLY) {
+            categories.push("@read");
+        }
+        if self.has(CMD_ADMIN) {
+            categories.push("@admin");
+            categories.push("@dangerous");
+        }
+        if self.has(CMD_PUBSUB) {
+            categories.push("@pubsub");
+        }
+        if self.has(CMD_BLOCKING) {
+            categories.push("@blocking");
+        }
+        categories.extend(group_category(self.group));
+        categories.push(if self.has(CMD_FAST) { "@fast" } else { "@slow" });
+        categories
+    }
+
+    /// 返回args(包括命令名)中key所在的下标, numkeys不合法时返回None
+    pub fn key_positions(&self, args: &[Bytes]) -> Option<Vec<usize>> {
+        let argc = args.len();
+        let mut keys: Vec<usize> = if self.first_key <= 0 {
+            vec![]
+        } else {
+            let last = if self.last_key < 0 {
+                argc as i32 + self.last_key
+            } else {
+                self.last_key.min(argc as i32 - 1)
+            };
+            (self.first_key..=last)
+                .step_by(self.key_step.max(1) as usize)
+                .map(|i| i as usize)
+                .collect()
+        };
+        // key紧跟在numkeys之后
+        if self.numkeys_index > 0 {
+            let index = self.numkeys_index as usize;
+            let numkeys = args
+                .get(index)
+                .and_then(|n| parse_i64_strict(n))
+                .filter(|n| *n > 0 && *n as usize + index < argc)?;
+            keys.extend(index + 1..=index + numkeys as usize);
+        }
+        Some(keys)
+    }
+
+    // COMMAND INFO中的key specs: (first_key, last_key, key_step)对应range, numkeys对应keynum
+    fn key_specs(&self) -> RESP {
+        let write = ["RW", "update"];
+        let read = ["RO", "access"];
+        let mut specs = Vec::new();
+        if self.first_key > 0 {
+            let last_key = if self.last_key < 0 {
+                self.last_key
+            } else {
+                self.last_key - self.first_key
+            };
+            let flags = if self.has(CMD_WRITE) { write } else { read };
+            specs.push(key_spec(
+                flags,
+                self.first_key,
+                "range",
+                &[
+                    ("lastkey", last_key),
+                    ("keystep", self.key_step),
+                    ("limit", 0),
+                ],
+            ));
+        }
+        if self.numkeys_index > 0 {
+            // 有destination时numkeys给出的是只读的源key
+            let flags = if self.has(CMD_WRITE) && self.first_key <= 0 {
+                write
+            } else {
+                read
+            };
+            specs.push(key_spec(
+                flags,
+                self.numkeys_index,
+                "keynum",
+                &[("keynumidx", 0), ("firstkey", 1), ("keystep", 1)],
+            ));
+        }
+        RESP::Array(specs)
+    }
+
+    /// COMMAND INFO的回复格式
+    pub fn info(&self) -> RESP {
+        RESP::Array(vec![
+            RESP::new_bulk(self.name),
+            RESP::Integer(self.arity as i64),
+            RESP::Set(
+                FLAG_NAMES
+                    .iter()
+                    .filter(|(flag, _)| self.has(*flag))
+                    .map(|(_, name)| RESP::new_simple(name.to_string()))
+                    .collect(),
+            ),
+            RESP::Integer(self.first_key as i64),
+            RESP::Integer(self.last_key as i64),
+            RESP::Integer(self.key_step as i64),
+            RESP::Set(
+                self.acl_categories()
+                    .into_iter()
+                    .map(|c| RESP::new_simple(c.to_string()))
+                    .collect(),
+            ),
+            // tips
+            RESP::Array(vec![]),
+            self.key_specs(),
+            // subcommands
+            RESP::Array(vec![]),
+        ])
+    }
+
+    /// COMMAND DOCS的回复格式
+    pub fn docs(&self) -> RESP {
+        RESP::Map(vec![
+            (RESP::new_bulk("summary"), RESP::new_bulk(self.summary)),
+            (RESP::new_bulk("since"), RESP::new_bulk(self.since)),
+            (RESP::new_bulk("group"), RESP::new_bulk(self.group)),
+        ])
+    }
+}
+
+fn lowercase(name: &Bytes) -> String {
+    String::from_utf8_lossy(name).to_lowercase()
+}
+
+/// 执行COMMAND [COUNT | INFO name... | DOCS name... | GETKEYS cmd arg...],
+/// extra为注册的自定义命令
+pub fn command(args: &[Bytes], extra: &[&CommandSpec]) -> RESP {
+    let all = || COMMAND_TABLE.iter().chain(extra.iter().copied());
+    let find = |name: &Bytes| {
+        let name = lowercase(name);
+        lookup(&name).or_else(|| extra.iter().copied().find(|spec| spec.name == name))
+    };
+    let sub = match args.first() {
+        None => return RESP::Array(all().map(|spec| spec.info()).collect()),
+        Some(sub) => lowercase(sub),
+    };
+    match sub.as_str() {
+        "count" if args.len() == 1 => RESP::Integer(all().count() as i64),
+        "info" if args.len() == 1 => RESP::Array(all().map(|spec| spec.info()).collect()),
+        "info" => RESP::Array(
+            args[1..]
+                .iter()
+                .map(|name| find(name).map_or(RESP::Null, |spec| spec.info()))
+                .collect(),
+        ),
+        "docs" => {
+            let specs: Vec<_> = if args.len() == 1 {
+                all().collect()
+            } else {
+                args[1..].iter().filter_map(find).collect()
+            };
+            RESP::Map(
+                specs
+                    .into_iter()
+                    .map(|spec| (RESP::new_bulk(spec.name), spec.docs()))
+                    .collect(),
+            )
+        }
+        "getkeys" if args.len() >= 2 => {
+            let spec = match find(&args[1]) {
+                Some(spec) => spec,
+                None => return RESP::Error("ERR Invalid command specified".to_string()),
+            };
+            let argc = args.len() - 1;
+            if spec.check_arity(argc).is_err() {
+                return RESP::Error(
+                    "ERR Invalid number of arguments specified for command".to_string(),
+                );
+            }
+            let keys = match spec.key_positions(&args[1..]) {
+                Some(keys) => keys,
+                None => {
+                    return RESP::Error("ERR Invalid arguments specified for command".to_string())
+                }
+            };
+            if keys.is_empty() {
+                return RESP::Error("ERR The command has no key arguments".to_string());
+            }
+            RESP::Array(
+                keys.into_iter()
+                    .map(|i| RESP::Bulk(args[i + 1].clone()))
+                    .collect(),
+            )
+        }
+        _ => RESP::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try COMMAND HELP.",
+            sub
+        )),
+    }
+}
+
+#[cfg(test)]
+mod cmd_table_test {
+    use super::*;
+
+    fn args(strs: &[&'static str]) -> Vec<Bytes> {
+        strs.iter().map(|s| Bytes::from(*s)).collect()
+    }
+
+    #[test]
+    fn test_arity() {
+        let set = lookup("set").unwrap();
+        assert!(set.check_arity(2).is_err());
+        assert!(set.check_arity(3).is_ok());
+        assert!(set.check_arity(5).is_ok());
+        let get = lookup("get").unwrap();
+        assert_eq!(
+            get.check_arity(3),
+            Err(CmdError::WrongArity("get".to_string()))
+        );
+        assert!(!get.is_write() && set.is_write());
+    }
+
+    #[test]
+    fn test_command_info() {
+        assert_eq!(
+            command(&args(&["COUNT"]), &[]),
+            RESP::Integer(COMMAND_TABLE.len() as i64)
+        );
+        let info = command(&args(&["info", "get", "nosuch"]), &[]);
+        let RESP::Array(infos) = info else {
+            panic!("expected array")
+        };
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[1], RESP::Null);
+        let RESP::Array(get) = &infos[0] else {
+            panic!("expected array")
+        };
+        assert_eq!(get[0], RESP::new_bulk("get"));
+        assert_eq!(get[1], RESP::Integer(2));
+        assert_eq!(
+            get[2],
+            RESP::Set(vec![
+                RESP::new_simple("readonly".to_string()),
+                RESP::new_simple("fast".to_string())
+            ])
+        );
+        assert_eq!(
+            get[6],
+            RESP::Set(vec![
+                RESP::new_simple("@read".to_string()),
+                RESP::new_simple("@string".to_string()),
+                RESP::new_simple("@fast".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_command_getkeys() {
+        assert_eq!(
+            command(&args(&["getkeys", "set", "k", "v", "px", "10"]), &[]),
+            RESP::Array(vec![RESP::new_bulk("k")])
+        );
+        assert_eq!(
+            command(&args(&["getkeys", "ping"]), &[]),
+            RESP::Error("ERR The command has no key arguments".to_string())
+        );
+        assert_eq!(
+            command(&args(&["getkeys", "get"]), &[]),
+            RESP::Error("ERR Invalid number of arguments specified for command".to_string())
+        );
+    }
+
+    #[test]
+    fn test_command_getkeys_numkeys() {
+        assert_eq!(
+            command(&args(&["getkeys", "lmpop", "2", "a", "b", "left"]), &[]),
+            RESP::Array(vec![RESP::new_bulk("a"), RESP::new_bulk("b")])
+        );
+        assert_eq!(
+            command(
+                &args(&["getkeys", "blmpop", "0", "1", "a", "right", "count", "2"]),
+                &[]
+            ),
+            RESP::Array(vec![RESP::new_bulk("a")])
+        );
+        assert_eq!(
+            command(&args(&["getkeys", "sintercard", "2", "a", "b"]), &[]),
+            RESP::Array(vec![RESP::new_bulk("a"), RESP::new_bulk("b")])
+        );
+        assert_eq!(
+            command(
+                &args(&["getkeys", "sintercard", "1", "a", "limit", "5"]),
+                &[]
+            ),
+            RESP::Array(vec![RESP::new_bulk("a")])
+        );
+        assert_eq!(
+            command(
+                &args(&["getkeys", "zunion", "2", "a", "b", "withscores"]),
+                &[]
+            ),
+            RESP::Array(vec![RESP::new_bulk("a"), RESP::new_bulk("b")])
+        );
+        assert_eq!(
+            command(&args(&["getkeys", "bzmpop", "1", "1", "a", "min"]), &[]),
+            RESP::Array(vec![RESP::new_bulk("a")])
+        );
+        assert_eq!(
+            command(
+                &args(&[
+                    "getkeys",
+                    "zinterstore",
+                    "dst",
+                    "2",
+                    "a",
+                    "b",
+                    "weights",
+                    "1",
+                    "2"
+                ]),
+                &[]
+            ),
+            RESP::Array(vec![
+                RESP::new_bulk("dst"),
+                RESP::new_bulk("a"),
+                RESP::new_bulk("b")
+            ])
+        );
+        assert_eq!(
+            command(&args(&["getkeys", "zdiffstore", "dst", "0", "a"]), &[]),
+            RESP::Error("ERR Invalid arguments specified for command".to_string())
+        );
+        for numkeys in ["0", "3", "x"] {
+            assert_eq!(
+                command(&args(&["getkeys", "lmpop", numkeys, "a", "left"]), &[]),
+                RESP::Error("ERR Invalid arguments specified for command".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_specs() {
+        let range = |flags, last_key| {
+            key_spec(
+                flags,
+                1,
+                "range",
+                &[("lastkey", last_key), ("keystep", 1), ("limit", 0)],
+            )
+        };
+        let keynum = |flags, index| {
+            key_spec(
+                flags,
+                index,
+                "keynum",
+                &[("keynumidx", 0), ("firstkey", 1), ("keystep", 1)],
+            )
+        };
+        assert_eq!(lookup("ping").unwrap().key_specs(), RESP::Array(vec![]));
+        assert_eq!(
+            lookup("set").unwrap().key_specs(),
+            RESP::Array(vec![range(["RW", "update"], 0)])
+        );
+        let union = spec("union", -3, CMD_READONLY, NO_KEYS, "set", "", "").with_numkeys(1);
+        assert_eq!(
+            union.key_specs(),
+            RESP::Array(vec![keynum(["RO", "access"], 1)])
+        );
+        let pop = spec("pop", -3, CMD_WRITE, NO_KEYS, "list", "", "").with_numkeys(1);
+        assert_eq!(
+            pop.key_specs(),
+            RESP::Array(vec![keynum(["RW", "update"], 1)])
+        );
+        // destination和源key分别给出
+        let store = spec("store", -4, CMD_WRITE, (1, 1, 1), "set", "", "").with_numkeys(2);
+        assert_eq!(
+            store.key_specs(),
+            RESP::Array(vec![
+                range(["RW", "update"], 0),
+                keynum(["RO", "access"], 2)
+            ])
+        );
+        assert_eq!(
+            lookup("sintercard").unwrap().key_specs(),
+            RESP::Array(vec![keynum(["RO", "access"], 1)])
+        );
+        assert_eq!(
+            lookup("blmpop").unwrap().key_specs(),
+            RESP::Array(vec![keynum(["RW", "update"], 2)])
+        );
+        assert_eq!(
+            lookup("zunionstore").unwrap().key_specs(),
+            RESP::Array(vec![
+                range(["RW", "update"], 0),
+                keynum(["RO", "access"], 2)
+            ])
+        );
+        assert_eq!(
+            store.key_positions(&args(&["store", "dst", "2", "a", "b", "weights"])),
+            Some(vec![1, 3, 4])
+        );
+        for numkeys in ["0", "3", "x"] {
+            assert_eq!(
+                store.key_positions(&args(&["store", "dst", numkeys, "a"])),
+                None
+            );
+        }
+    }
+}
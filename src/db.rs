@@ -0,0 +1,876 @@
+use std::{
+    collections::{
+        hash_map::{DefaultHasher, RandomState},
+        BTreeSet, HashMap, VecDeque,
+    },
+    hash::{BuildHasher, Hash, Hasher},
+    ops::{Deref, DerefMut},
+};
+
+use bytes::Bytes;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{
+    error::CmdError,
+    frame::RESP,
+    hash,
+    server::{shard_index, CmdSender, ShardedDb},
+    set, stream, zset,
+};
+
+/// 已过期的key如何处理
+#[derive(Debug, Default, Clone)]
+pub enum ExpirePolicy {
+    // 直接删除
+    #[default]
+    Delete,
+    // master: 删除并向replica同步DEL, 过期完全由master决定; usize为分片所属的db
+    Propagate(CmdSender, usize),
+    // replica: 读取时隐藏但不删除, 等待master同步的DEL
+    Hide,
+}
+
+/// 单个分片: key -> (value, 过期时间的毫秒时间戳), u128::MAX表示永不过期
+#[derive(Debug, Default)]
+pub struct Shard {
+    entries: HashMap<Bytes, (Value, u128)>,
+    // 有过期时间的key按过期时间排序, 供主动过期使用; 修改过期时间必须经过insert/remove/set_expire
+    expires: BTreeSet<(u128, Bytes)>,
+    // 所有key按scan_hash排序, SCAN从游标位置开始直接取出下一批; 增删key必须经过insert/remove
+    scan_index: BTreeSet<(u64, Bytes)>,
+    // 已过期并被删除的key数
+    expired: u64,
+    policy: ExpirePolicy,
+}
+
+impl Shard {
+    pub fn new(policy: ExpirePolicy) -> Self {
+        Shard {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&(Value, u128)> {
+        self.entries.get(key)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &(Value, u128))> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn insert(&mut self, key: Bytes, entry: (Value, u128)) -> Option<(Value, u128)> {
+        if entry.1 != u128::MAX {
+            self.expires.insert((entry.1, key.clone()));
+        }
+        let old = self.entries.insert(key.clone(), entry);
+        if old.is_none() {
+            self.scan_index.insert((scan_hash(&key), key.clone()));
+        }
+        self.unindex(&key, old.as_ref());
+        old
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<(Value, u128)> {
+        let old = self.entries.remove(key);
+        if old.is_some() {
+            self.scan_index
+                .remove(&(scan_hash(key), Bytes::copy_from_slice(key)));
+        }
+        self.unindex(key, old.as_ref());
+        old
+    }
+
+    /// 删除未过期的key并返回其条目; 已过期的key经过expire_key处理(计数并向replica同步DEL),
+    /// 视为不存在
+    pub fn remove_live(&mut self, key: &[u8], now_millis: u128) -> Option<(Value, u128)> {
+        if self.expire_if_needed(key, now_millis) {
+            return None;
+        }
+        self.remove(key)
+    }
+
+    /// key已过期时经过expire_key删除, 返回是否已过期; 写命令在判断key是否存在之前调用,
+    /// 使master向replica同步的DEL先于该命令到达, 两边的判断结果一致
+    pub fn expire_if_needed(&mut self, key: &[u8], now_millis: u128) -> bool {
+        let expired = self
+            .get(key)
+            .is_some_and(|(_, expire_time)| now_millis >= *expire_time);
+        if expired {
+            self.expire_key(key);
+        }
+        expired
+    }
+
+    // 从过期索引中去掉被替换或删除的条目
+    fn unindex(&mut self, key: &[u8], old: Option<&(Value, u128)>) {
+        if let Some((_, expire_time)) = old {
+            if *expire_time != u128::MAX {
+                self.expires
+                    .remove(&(*expire_time, Bytes::copy_from_slice(key)));
+            }
+        }
+    }
+
+    /// 只修改value, 过期时间不变
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+        self.entries.get_mut(key).map(|(value, _)| value)
+    }
+
+    /// 修改已存在的key的过期时间, u128::MAX表示清除
+    pub fn set_expire(&mut self, key: &[u8], expire_time: u128) {
+        if let Some((value, _)) = self.remove(key) {
+            self.insert(Bytes::copy_from_slice(key), (value, expire_time));
+        }
+    }
+
+    // 删除一个已过期的key并计数; DEL在持有分片锁时发出, 保证先于触发过期的命令到达replica
+    fn expire_key(&mut self, key: &[u8]) {
+        if matches!(self.policy, ExpirePolicy::Hide) || self.remove(key).is_none() {
+            return;
+        }
+        self.expired += 1;
+        if let ExpirePolicy::Propagate(tx, db) = &self.policy {
+            let del = vec![
+                RESP::new_bulk("DEL"),
+                RESP::Bulk(Bytes::copy_from_slice(key)),
+            ];
+            let _ = tx.send((*db, RESP::Array(del)));
+        }
+    }
+
+    /// 按过期时间顺序删除最多limit个已过期的key, 返回被删除的key
+    pub fn remove_expired(&mut self, now_millis: u128, limit: usize) -> Vec<Bytes> {
+        if matches!(self.policy, ExpirePolicy::Hide) {
+            return vec![];
+        }
+        let keys: Vec<_> = self
+            .expires
+            .iter()
+            .take_while(|(expire_time, _)| *expire_time <= now_millis)
+            .take(limit)
+            .map(|(_, key)| key.clone())
+            .collect();
+        for key in &keys {
+            self.expire_key(key);
+        }
+        keys
+    }
+
+    /// 清空分片, 返回原有的数据, 可以交给其他线程释放
+    pub fn clear(&mut self) -> Shard {
+        Shard {
+            entries: std::mem::take(&mut self.entries),
+            expires: std::mem::take(&mut self.expires),
+            scan_index: std::mem::take(&mut self.scan_index),
+            ..Default::default()
+        }
+    }
+
+    /// 交换两个分片的数据, 所属的db和过期策略不变
+    pub fn swap_data(&mut self, other: &mut Shard) {
+        std::mem::swap(&mut self.entries, &mut other.entries);
+        std::mem::swap(&mut self.expires, &mut other.expires);
+        std::mem::swap(&mut self.scan_index, &mut other.scan_index);
+    }
+
+    /// 按scan_hash顺序从pos开始检查大约count个key, 哈希值相同的key在同一批检查;
+    /// 返回其中未过期的key及其类型, 以及下一个位置, None表示已经检查完
+    pub fn scan(
+        &self,
+        pos: u64,
+        count: usize,
+        now_millis: u128,
+    ) -> (Vec<(Bytes, &'static str)>, Option<u64>) {
+        let mut keys = Vec::new();
+        let mut last = None;
+        for (n, (h, key)) in self.scan_index.range((pos, Bytes::new())..).enumerate() {
+            if n >= count && last != Some(*h) {
+                return (keys, Some(*h));
+            }
+            last = Some(*h);
+            if let Some((value, _)) = get_live(self, key, now_millis) {
+                keys.push((key.clone(), value.type_name()));
+            }
+        }
+        (keys, None)
+    }
+
+    pub fn expired_keys(&self) -> u64 {
+        self.expired
+    }
+}
+
+/// 键空间中的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(Bytes),
+    // 可以表示为i64的字符串直接存为整数, 计数器无需每次重新解析
+    Int(i64),
+    // 列表, 两端的push/pop都是O(1); 元素为空时key会被删除
+    List(VecDeque<Bytes>),
+    Hash(hash::Hash),
+    Set(set::Set),
+    ZSet(zset::ZSet),
+    // 消息流, 消息被删光后key也不会被删除
+    Stream(stream::Stream),
+}
+
+// 与redis的string2ll一致: 不允许空格, '+'和前导0, 保证与原字符串一一对应
+pub fn parse_i64_strict(src: &[u8]) -> Option<i64> {
+    if src.len() > 20 {
+        return None;
+    }
+    let n: i64 = std::str::from_utf8(src).ok()?.parse().ok()?;
+    (n.to_string().as_bytes() == src).then_some(n)
+}
+
+impl Value {
+    /// 能无损表示为整数的字符串使用整数编码
+    pub fn from_bytes(src: Bytes) -> Self {
+        match parse_i64_strict(&src) {
+            Some(n) => Value::Int(n),
+            None => Value::Str(src),
+        }
+    }
+
+    /// 字符串的内容, 其他类型返回WRONGTYPE
+    pub fn to_bytes(&self) -> Result<Bytes, CmdError> {
+        match self {
+            Value::Str(b) => Ok(b.clone()),
+            Value::Int(n) => Ok(Bytes::from(n.to_string())),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    /// TYPE命令返回的类型名
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) | Value::Int(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// 字符串形式的长度
+    pub fn strlen(&self) -> Result<usize, CmdError> {
+        match self {
+            Value::Str(b) => Ok(b.len()),
+            Value::Int(n) => Ok(n.to_string().len()),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i64, CmdError> {
+        match self {
+            Value::Int(n) => Ok(*n),
+            Value::Str(b) => parse_i64_strict(b).ok_or(CmdError::NotInteger),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_float(&self) -> Result<f64, CmdError> {
+        match self {
+            Value::Int(n) => Ok(*n as f64),
+            Value::Str(b) => parse_float(b),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&VecDeque<Bytes>, CmdError> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Result<&mut VecDeque<Bytes>, CmdError> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_hash(&self) -> Result<&hash::Hash, CmdError> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Result<&mut hash::Hash, CmdError> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_set(&self) -> Result<&set::Set, CmdError> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Result<&mut set::Set, CmdError> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_zset(&self) -> Result<&zset::ZSet, CmdError> {
+        match self {
+            Value::ZSet(zset) => Ok(zset),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_zset_mut(&mut self) -> Result<&mut zset::ZSet, CmdError> {
+        match self {
+            Value::ZSet(zset) => Ok(zset),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_stream(&self) -> Result<&stream::Stream, CmdError> {
+        match self {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+
+    pub fn as_stream_mut(&mut self) -> Result<&mut stream::Stream, CmdError> {
+        match self {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(CmdError::WrongType),
+        }
+    }
+}
+
+/// 解析浮点数参数, 不允许前后空格和NaN
+pub fn parse_float(src: &[u8]) -> Result<f64, CmdError> {
+    std::str::from_utf8(src)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|f| !f.is_nan())
+        .ok_or(CmdError::NotFloat)
+}
+
+/// INCRBYFLOAT结果的格式, 与redis一样不使用科学计数法并去掉末尾的0;
+/// 只保留15位有效数字, 避免0.1 + 0.2显示为0.30000000000000004
+pub fn format_float(f: f64) -> String {
+    if f == 0.0 {
+        return "0".to_string();
+    }
+    let magnitude = f.abs().log10().floor() as i32 + 1;
+    let prec = (15 - magnitude).clamp(0, 17) as usize;
+    let s = format!("{:.*}", prec, f);
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+/// 未过期的条目, 已过期的key视为不存在
+pub fn get_live<'a>(shard: &'a Shard, key: &[u8], now_millis: u128) -> Option<&'a (Value, u128)> {
+    shard
+        .get(key)
+        .filter(|(_, expire_time)| now_millis < *expire_time)
+}
+
+/// 未过期的value的可变引用, 不存在时写入default()生成的永不过期的value
+pub fn get_live_or_insert_with<'a>(
+    shard: &'a mut Shard,
+    key: &Bytes,
+    now_millis: u128,
+    default: impl FnOnce() -> Value,
+) -> &'a mut Value {
+    if get_live_mut(shard, key, now_millis).is_none() {
+        shard.insert(key.clone(), (default(), u128::MAX));
+    }
+    shard.get_mut(key).unwrap()
+}
+
+/// 未过期的value的可变引用, 顺便删除已过期的key(replica上只隐藏)
+pub fn get_live_mut<'a>(
+    shard: &'a mut Shard,
+    key: &[u8],
+    now_millis: u128,
+) -> Option<&'a mut Value> {
+    shard.expire_if_needed(key, now_millis);
+    shard
+        .entries
+        .get_mut(key)
+        .filter(|(_, expire_time)| now_millis < *expire_time)
+        .map(|(value, _)| value)
+}
+
+/// redis风格的glob匹配: 支持*, ?, [abc], [^a-z]以及\\转义
+pub fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // 最近一个*之后的模式位置, 以及它当前匹配到的字符串位置
+    let mut star: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if pattern.get(p) == Some(&b'*') {
+            while pattern.get(p) == Some(&b'*') {
+                p += 1;
+            }
+            star = Some((p, i));
+            continue;
+        }
+        if p < pattern.len() {
+            let (matched, next) = match_one(pattern, p, s[i]);
+            if matched {
+                p = next;
+                i += 1;
+                continue;
+            }
+        }
+        // 匹配失败时让上一个*多吞一个字符
+        match star {
+            Some((star_p, star_i)) => {
+                p = star_p;
+                i = star_i + 1;
+                star = Some((star_p, i));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+// 用p处的单个token匹配字符c, 返回是否匹配以及下一个token的位置
+fn match_one(pattern: &[u8], p: usize, c: u8) -> (bool, usize) {
+    match pattern[p] {
+        b'?' => (true, p + 1),
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c, p + 2),
+        b'[' => {
+            let mut j = p + 1;
+            let negate = pattern.get(j) == Some(&b'^');
+            if negate {
+                j += 1;
+            }
+            let mut matched = false;
+            while j < pattern.len() && pattern[j] != b']' {
+                if pattern[j] == b'\\' && j + 1 < pattern.len() {
+                    matched |= pattern[j + 1] == c;
+                    j += 2;
+                } else if j + 2 < pattern.len() && pattern[j + 1] == b'-' {
+                    let (lo, hi) = (
+                        pattern[j].min(pattern[j + 2]),
+                        pattern[j].max(pattern[j + 2]),
+                    );
+                    matched |= (lo..=hi).contains(&c);
+                    j += 3;
+                } else {
+                    matched |= pattern[j] == c;
+                    j += 1;
+                }
+            }
+            // 缺少']'时视为在模式末尾闭合
+            (matched != negate, (j + 1).min(pattern.len()))
+        }
+        literal => (literal == c, p + 1),
+    }
+}
+
+// SCAN游标的低48位是分片内的位置, 其余高位是分片下标
+const SCAN_HASH_BITS: u32 = 48;
+
+// 分片内按固定的哈希值排序遍历, 与HashMap内部顺序无关, 扩容或写入都不会打乱
+fn scan_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() >> (64 - SCAN_HASH_BITS)
+}
+
+// 按scan_hash排序后从pos开始取出大约count个元素, 哈希值相同的元素必须在同一批返回,
+// 否则下一个游标会跳过它们; 返回取出的元素和下一个位置, None表示已经取完
+fn scan_batch<T>(mut batch: Vec<(u64, T)>, pos: u64, count: usize) -> (Vec<T>, Option<u64>) {
+    batch.retain(|(h, _)| *h >= pos);
+    batch.sort_unstable_by_key(|(h, _)| *h);
+    if batch.len() <= count {
+        return (batch.into_iter().map(|(_, item)| item).collect(), None);
+    }
+    let last = batch[count - 1].0;
+    let items = batch
+        .into_iter()
+        .take_while(|(h, _)| *h <= last)
+        .map(|(_, item)| item)
+        .collect();
+    (items, (last + 1 < 1 << SCAN_HASH_BITS).then_some(last + 1))
+}
+
+/// HSCAN等在单个集合内的遍历, 游标是下一个元素的哈希位置, 0表示遍历结束
+pub fn scan_collection<'a, T>(
+    items: impl Iterator<Item = (&'a Bytes, T)>,
+    cursor: u64,
+    count: usize,
+) -> (u64, Vec<(&'a Bytes, T)>) {
+    let batch = items.map(|item| (scan_hash(item.0), item)).collect();
+    let (items, next) = scan_batch(batch, cursor, count);
+    (next.unwrap_or(0), items)
+}
+
+/// 从cursor开始取出大约count个未过期的key及其类型, 返回下一个游标, 0表示遍历结束;
+/// 整个遍历期间一直存在的key至少会被返回一次
+pub async fn scan(
+    db: &ShardedDb,
+    cursor: u64,
+    count: usize,
+    now_millis: u128,
+) -> (u64, Vec<(Bytes, &'static str)>) {
+    let mut shard = (cursor >> SCAN_HASH_BITS) as usize;
+    let mut pos = cursor & ((1 << SCAN_HASH_BITS) - 1);
+    let mut keys = Vec::new();
+    while shard < db.len() && keys.len() < count {
+        let (batch, next) = db[shard]
+            .read()
+            .await
+            .scan(pos, count - keys.len(), now_millis);
+        keys.extend(batch);
+        match next {
+            Some(next) => pos = next,
+            None => {
+                shard += 1;
+                pos = 0;
+            }
+        }
+    }
+    let next = if shard >= db.len() {
+        0
+    } else {
+        ((shard as u64) << SCAN_HASH_BITS) | pos
+    };
+    (next, keys)
+}
+
+/// HRANDFIELD等使用的随机数, 每次调用使用不同的哈希种子
+pub fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// 随机打乱后取出前count个, 即不重复的随机抽样
+pub fn sample<T>(mut items: Vec<T>, count: usize) -> Vec<T> {
+    let count = count.min(items.len());
+    for i in 0..count {
+        let j = i + (random_u64() % (items.len() - i) as u64) as usize;
+        items.swap(i, j);
+    }
+    items.truncate(count);
+    items
+}
+
+/// 多key命令持有的一组分片锁
+pub struct ShardGuards<'a, G> {
+    db: &'a ShardedDb,
+    // 按分片下标升序排列
+    guards: Vec<(usize, G)>,
+}
+
+// 涉及的分片下标, 升序且去重
+fn shard_indexes(db: &ShardedDb, keys: &[Bytes]) -> Vec<usize> {
+    let mut indexes: Vec<_> = keys.iter().map(|key| shard_index(db, key)).collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    indexes
+}
+
+/// 按分片下标升序获取读锁, 所有多key命令都以相同顺序加锁, 因此不会死锁
+pub async fn read_shards<'a>(
+    db: &'a ShardedDb,
+    keys: &[Bytes],
+) -> ShardGuards<'a, RwLockReadGuard<'a, Shard>> {
+    let mut guards = Vec::new();
+    for i in shard_indexes(db, keys) {
+        guards.push((i, db[i].read().await));
+    }
+    ShardGuards { db, guards }
+}
+
+/// 按分片下标升序获取写锁
+pub async fn write_shards<'a>(
+    db: &'a ShardedDb,
+    keys: &[Bytes],
+) -> ShardGuards<'a, RwLockWriteGuard<'a, Shard>> {
+    let mut guards = Vec::new();
+    for i in shard_indexes(db, keys) {
+        guards.push((i, db[i].write().await));
+    }
+    ShardGuards { db, guards }
+}
+
+/// 给两个不同db中的分片加写锁; 跨db时统一按(db下标, 分片下标)的顺序加锁
+pub async fn write_pair<'a>(
+    (first, first_db): (&'a RwLock<Shard>, usize),
+    (second, second_db): (&'a RwLock<Shard>, usize),
+) -> (RwLockWriteGuard<'a, Shard>, RwLockWriteGuard<'a, Shard>) {
+    if first_db < second_db {
+        let first = first.write().await;
+        (first, second.write().await)
+    } else {
+        let second = second.write().await;
+        (first.write().await, second)
+    }
+}
+
+impl<'a, G: Deref<Target = Shard>> ShardGuards<'a, G> {
+    fn position(&self, key: &[u8]) -> usize {
+        let i = shard_index(self.db, key);
+        self.guards
+            .binary_search_by_key(&i, |(i, _)| *i)
+            .expect("key's shard is not locked")
+    }
+
+    /// key所在的分片, key必须在加锁时给出
+    pub fn shard(&self, key: &[u8]) -> &Shard {
+        &self.guards[self.position(key)].1
+    }
+}
+
+impl<'a, G: DerefMut<Target = Shard>> ShardGuards<'a, G> {
+    pub fn shard_mut(&mut self, key: &[u8]) -> &mut Shard {
+        let pos = self.position(key);
+        &mut self.guards[pos].1
+    }
+}
+
+#[cfg(test)]
+mod db_test {
+    use super::*;
+    use crate::server::new_sharded_db;
+
+    #[tokio::test]
+    async fn test_shard_guards() {
+        let db = new_sharded_db(4, ExpirePolicy::Delete);
+        let keys: Vec<Bytes> = ["a", "b", "c", "d", "e", "a"]
+            .into_iter()
+            .map(Bytes::from)
+            .collect();
+        {
+            let mut guards = write_shards(&db, &keys).await;
+            for key in &keys {
+                guards
+                    .shard_mut(key)
+                    .insert(key.clone(), (Value::Str(key.clone()), u128::MAX));
+            }
+        }
+        // 读锁之间不冲突
+        let g1 = read_shards(&db, &keys).await;
+        let g2 = read_shards(&db, &keys[1..3]).await;
+        assert_eq!(g1.shard(b"e").get(&b"e"[..]).map(|v| v.1), Some(u128::MAX));
+        assert!(get_live(g2.shard(b"b"), b"b", 0).is_some());
+        assert!(get_live(g2.shard(b"b"), b"b", u128::MAX).is_none());
+    }
+
+    #[test]
+    fn test_expire_index() {
+        let mut shard = Shard::default();
+        for (key, expire_time) in [("a", 10), ("b", 30), ("c", 20), ("d", u128::MAX)] {
+            shard.insert(Bytes::from(key), (Value::Int(0), expire_time));
+        }
+        // 覆盖, 修改和删除后索引保持一致
+        shard.insert(Bytes::from("a"), (Value::Int(1), 35));
+        shard.set_expire(b"a", 40);
+        shard.set_expire(b"d", 5);
+        shard.remove(b"b");
+        assert_eq!(shard.expires.len(), 3);
+        assert_eq!(
+            shard.remove_expired(25, 20),
+            vec![Bytes::from("d"), Bytes::from("c")]
+        );
+        assert_eq!(shard.expired_keys(), 2);
+        assert!(get_live_mut(&mut shard, b"a", 40).is_none());
+        assert_eq!(shard.expired_keys(), 3);
+        assert!(shard.is_empty() && shard.expires.is_empty());
+    }
+
+    #[test]
+    fn test_expire_policy() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut master = Shard::new(ExpirePolicy::Propagate(tx, 3));
+        let mut replica = Shard::new(ExpirePolicy::Hide);
+        for shard in [&mut master, &mut replica] {
+            shard.insert(Bytes::from("a"), (Value::Int(0), 10));
+            shard.insert(Bytes::from("b"), (Value::Int(0), 10));
+            assert!(get_live_mut(shard, b"a", 20).is_none());
+            shard.remove_expired(20, 20);
+        }
+        assert!(master.is_empty());
+        assert_eq!(
+            rx.try_recv().ok(),
+            Some((
+                3,
+                RESP::new_cmd_array(vec!["DEL".to_string(), "a".to_string()])
+            ))
+        );
+        assert!(rx.try_recv().is_ok() && rx.try_recv().is_err());
+        // replica上过期的key只是不可见
+        assert_eq!(replica.len(), 2);
+        assert_eq!(replica.expired_keys(), 0);
+        assert!(get_live_mut(&mut replica, b"a", 0).is_some());
+    }
+
+    #[test]
+    fn test_remove_live() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut master = Shard::new(ExpirePolicy::Propagate(tx, 0));
+        let mut replica = Shard::new(ExpirePolicy::Hide);
+        for shard in [&mut master, &mut replica] {
+            shard.insert(Bytes::from("a"), (Value::Int(0), 10));
+            shard.insert(Bytes::from("b"), (Value::Int(1), u128::MAX));
+        }
+        // 已过期的key按过期处理: 计数并同步DEL, 而不是当作普通删除
+        assert!(master.remove_live(b"a", 20).is_none());
+        assert_eq!(master.expired_keys(), 1);
+        assert_eq!(
+            rx.try_recv().ok(),
+            Some((
+                0,
+                RESP::new_cmd_array(vec!["DEL".to_string(), "a".to_string()])
+            ))
+        );
+        assert_eq!(
+            master.remove_live(b"b", 20).map(|e| e.0),
+            Some(Value::Int(1))
+        );
+        assert!(master.is_empty() && rx.try_recv().is_err());
+        // replica上只隐藏, 等待master的DEL; master连接上的now为0, 仍能删除
+        assert!(replica.remove_live(b"a", 20).is_none());
+        assert_eq!(replica.len(), 2);
+        assert!(replica.remove_live(b"a", 0).is_some());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        let m = |p: &str, s: &str| glob_match(p.as_bytes(), s.as_bytes());
+        assert!(m("*", ""));
+        assert!(m("h?llo", "hello"));
+        assert!(m("h*llo", "heeeello"));
+        assert!(m("h*llo", "hllo"));
+        assert!(m("h[ae]llo", "hallo"));
+        assert!(!m("h[ae]llo", "hillo"));
+        assert!(m("h[^e]llo", "hallo"));
+        assert!(!m("h[^e]llo", "hello"));
+        assert!(m("h[a-b]llo", "hbllo"));
+        assert!(m("h[b-a]llo", "hallo"));
+        assert!(m("h\\*llo", "h*llo"));
+        assert!(!m("h\\*llo", "hello"));
+        assert!(m("user:*:name", "user:1:2:name"));
+        assert!(!m("user:*:name", "user:1:names"));
+        assert!(m("a*b*c", "aXbYbZc"));
+        assert!(!m("a?", "a"));
+    }
+
+    #[tokio::test]
+    async fn test_scan() {
+        let db = new_sharded_db(4, ExpirePolicy::Delete);
+        for i in 0..100 {
+            let key = Bytes::from(format!("key:{}", i));
+            db[shard_index(&db, &key)]
+                .write()
+                .await
+                .insert(key.clone(), (Value::Int(i), u128::MAX));
+        }
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            let (next, keys) = scan(&db, cursor, 7, 0).await;
+            calls += 1;
+            // 遍历过程中的写入不影响一直存在的key
+            let key = Bytes::from(format!("new:{}", calls));
+            db[shard_index(&db, &key)]
+                .write()
+                .await
+                .insert(key.clone(), (Value::Int(0), u128::MAX));
+            seen.extend(keys.into_iter().map(|(key, _)| key));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert!((0..100).all(|i| seen.contains(format!("key:{}", i).as_bytes())));
+        assert!(calls >= 100 / 7);
+    }
+
+    #[test]
+    fn test_shard_scan() {
+        let mut shard = Shard::default();
+        for i in 0..10 {
+            let expire_time = if i < 2 { 10 } else { u128::MAX };
+            shard.insert(format!("k{}", i).into(), (Value::Int(i), expire_time));
+        }
+        // 覆盖和修改过期时间不会重复索引, 删除的key从索引中去掉
+        shard.insert(Bytes::from("k5"), (Value::Int(0), u128::MAX));
+        shard.set_expire(b"k6", 100);
+        shard.remove(b"k9");
+        assert_eq!(shard.scan_index.len(), 9);
+        let (mut seen, mut pos) = (Vec::new(), 0);
+        loop {
+            let (keys, next) = shard.scan(pos, 2, 20);
+            assert!(keys.len() <= 2);
+            seen.extend(keys.into_iter().map(|(key, _)| key));
+            match next {
+                Some(next) => pos = next,
+                None => break,
+            }
+        }
+        seen.sort();
+        let expected: Vec<Bytes> = (2..9).map(|i| format!("k{}", i).into()).collect();
+        assert_eq!(seen, expected);
+        let mut other = Shard::default();
+        shard.swap_data(&mut other);
+        assert_eq!(shard.scan(0, 100, 0), (vec![], None));
+        assert_eq!(other.scan(0, 100, 0).0.len(), 9);
+        other.clear();
+        assert!(other.scan_index.is_empty());
+    }
+
+    #[test]
+    fn test_value_encoding() {
+        let value = |s: &'static str| Value::from_bytes(Bytes::from(s));
+        assert_eq!(value("-42"), Value::Int(-42));
+        assert_eq!(value("9223372036854775807"), Value::Int(i64::MAX));
+        for s in ["042", "+1", " 1", "-0", "1.5", "9223372036854775808", ""] {
+            assert_eq!(value(s), Value::Str(Bytes::from(s)));
+            assert_eq!(value(s).as_int(), Err(CmdError::NotInteger));
+        }
+        assert_eq!(value("-42").strlen(), Ok(3));
+        assert_eq!(value("-42").to_bytes(), Ok(Bytes::from("-42")));
+        assert_eq!(value("1.5").as_float(), Ok(1.5));
+        assert_eq!(value("abc").as_float(), Err(CmdError::NotFloat));
+        let list = Value::List(VecDeque::from([Bytes::from("1")]));
+        assert_eq!(list.type_name(), "list");
+        assert_eq!(list.to_bytes(), Err(CmdError::WrongType));
+        assert_eq!(list.as_int(), Err(CmdError::WrongType));
+        assert_eq!(value("1").as_list(), Err(CmdError::WrongType));
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(10.5 + 0.1), "10.6");
+        assert_eq!(format_float(0.1 + 0.2), "0.3");
+        assert_eq!(format_float(5.0e3), "5000");
+        assert_eq!(format_float(-1.5), "-1.5");
+        assert_eq!(format_float(1e20), "100000000000000000000");
+        assert_eq!(format_float(1.2345678901234568e-4), "0.00012345678901235");
+    }
+}
@@ -0,0 +1,128 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FrameError {
+    // 数据还不完整, 需要继续从socket读取
+    #[error("incomplete frame")]
+    Incomplete,
+    #[error("Protocol error: {0}")]
+    Invalid(String),
+}
+
+// 错误信息与redis保持一致, 以便客户端库能正确识别
+#[derive(Debug, Error, PartialEq)]
+pub enum CmdError {
+    #[error("ERR unknown command '{0}', with args beginning with: {1}")]
+    UnknownCommand(String, String),
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("ERR syntax error")]
+    Syntax,
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+    #[error("ERR invalid expire time in '{0}' command")]
+    InvalidExpireTime(String),
+    #[error("ERR value is not a valid float")]
+    NotFloat,
+    #[error("ERR increment or decrement would overflow")]
+    Overflow,
+    #[error("ERR decrement would overflow")]
+    DecrementOverflow,
+    #[error("ERR increment would produce NaN or Infinity")]
+    NanOrInfinity,
+    #[error("ERR no such key")]
+    NoSuchKey,
+    #[error("ERR source and destination objects are the same")]
+    SameObject,
+    #[error("ERR Unsupported option {0}")]
+    UnsupportedOption(String),
+    #[error("ERR NX and XX, GT or LT options at the same time are not compatible")]
+    ExpireNxConflict,
+    #[error("ERR GT and LT options at the same time are not compatible")]
+    ExpireGtLtConflict,
+    #[error("ERR DB index is out of range")]
+    DbIndexOutOfRange,
+    #[error("ERR invalid {0} DB index")]
+    InvalidDbIndex(String),
+    #[error("ERR invalid cursor")]
+    InvalidCursor,
+    #[error("ERR index out of range")]
+    IndexOutOfRange,
+    #[error("ERR value is out of range, must be positive")]
+    NotPositive,
+    #[error("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list")]
+    RankZero,
+    #[error("ERR {0} can't be negative")]
+    NegativeOption(String),
+    #[error("ERR hash value is not an integer")]
+    HashNotInteger,
+    #[error("ERR hash value is not a float")]
+    HashNotFloat,
+    #[error("ERR timeout is not a float or out of range")]
+    InvalidTimeout,
+    #[error("ERR timeout is negative")]
+    NegativeTimeout,
+    #[error("ERR {0} should be greater than 0")]
+    NotGreaterThanZero(String),
+    #[error("ERR XX and NX options at the same time are not compatible")]
+    ZAddXxNx,
+    #[error("ERR GT, LT, and/or NX options at the same time are not compatible")]
+    ZAddGtLtNx,
+    #[error("ERR INCR option supports a single increment-element pair")]
+    ZAddIncrPairs,
+    #[error("ERR resulting score is not a number (NaN)")]
+    ScoreNaN,
+    #[error("ERR min or max is not a float")]
+    MinMaxNotFloat,
+    #[error("ERR min or max not valid string range item")]
+    MinMaxNotString,
+    #[error(
+        "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+    )]
+    LimitWithoutBy,
+    #[error("ERR syntax error, WITHSCORES not supported in combination with BYLEX")]
+    WithScoresByLex,
+    #[error("ERR at least 1 input key is needed for '{0}' command")]
+    NoInputKeys(String),
+    #[error("ERR weight value is not a float")]
+    WeightNotFloat,
+    #[error("ERR Invalid stream ID specified as stream command argument")]
+    InvalidStreamId,
+    #[error("ERR invalid {0} ID for the interval")]
+    InvalidStreamInterval(String),
+    #[error("ERR The ID specified in XADD must be greater than 0-0")]
+    XAddIdZero,
+    #[error("ERR The ID specified in XADD is equal or smaller than the target stream top item")]
+    XAddIdTooSmall,
+    #[error("ERR The stream has exhausted the last possible ID, unable to add more items")]
+    StreamExhausted,
+    #[error("ERR The {0} argument must be >= 0.")]
+    StreamArgNegative(String),
+    #[error("ERR syntax error, MAXLEN and MINID options at the same time are not compatible")]
+    TrimMaxLenMinId,
+    #[error("ERR syntax error, LIMIT cannot be used without specifying a trimming strategy")]
+    TrimLimitWithoutStrategy,
+    #[error("ERR syntax error, LIMIT cannot be used without the special ~ option")]
+    TrimLimitWithoutApprox,
+    #[error("ERR syntax error, XTRIM must be called with a trimming strategy")]
+    XTrimNoStrategy,
+    #[error("ERR offset is out of range")]
+    OffsetOutOfRange,
+    #[error("ERR string exceeds maximum allowed size (proto-max-bulk-len)")]
+    StringTooLong,
+    #[error("NOPROTO unsupported protocol version")]
+    NoProto,
+    #[error("ERR Protocol version is not an integer or out of range")]
+    InvalidProtoVersion,
+    #[error("ERR Syntax error in HELLO option '{0}'")]
+    HelloOption(String),
+    #[error("WRONGPASS invalid username-password pair or user is disabled.")]
+    WrongPass,
+    #[error("ERR Client names cannot contain spaces, newlines or special characters.")]
+    InvalidClientName,
+    // 自定义命令的错误, 内容需自带"ERR "之类的前缀
+    #[error("{0}")]
+    Custom(String),
+}
@@ -1,132 +1,256 @@
-use std::fmt::Display;
+use std::fmt::{Display, Write};
 
-use bytes::Bytes;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::error::{CmdError, FrameError};
 
 #[derive(Debug, PartialEq)]
 pub enum RESP {
     Integer(i64),
     Simple(String),
     Error(String),
-    Bulk(String),
+    Bulk(Bytes),
     Array(Vec<RESP>),
     Null,
+    // RESP2中的*-1, 例如LPOP带count时key不存在
+    NullArray,
     Boolean(bool),
     Double(f64),
     BigNumber(i128),
     Verbatim(String),
     RDBFile(Bytes),
+    Map(Vec<(RESP, RESP)>),
+    Set(Vec<RESP>),
+    Push(Vec<RESP>),
+    // 属性以及紧随其后的实际回复
+    Attribute(Vec<(RESP, RESP)>, Box<RESP>),
+    BlobError(Bytes),
+}
+
+/// 连接使用的协议版本, 由HELLO命令协商
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+pub(crate) const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+const MAX_INLINE_LEN: usize = 64 * 1024;
+// 回复中聚合类型的最大嵌套层数, 防止恶意的深层嵌套耗尽栈空间
+const MAX_NESTING: usize = 128;
+// Display中RDB文件最多显示的字节数
+const RDB_DISPLAY_LEN: usize = 32;
+
+// 在src[start..]中找到下一个"\r\n", 返回这一行的内容以及下一行的起始位置
+fn read_line(src: &[u8], start: usize) -> Result<(&[u8], usize), FrameError> {
+    match src[start..].windows(2).position(|w| w == b"\r\n") {
+        Some(pos) => Ok((&src[start..start + pos], start + pos + 2)),
+        None => Err(FrameError::Incomplete),
+    }
+}
+
+fn parse_decimal(line: &[u8]) -> Option<i64> {
+    std::str::from_utf8(line).ok()?.parse().ok()
+}
+
+// 将"需要更多数据"转换为Ok(None), 其余错误原样返回
+fn incomplete_to_none<T>(res: Result<T, FrameError>) -> Result<Option<T>, FrameError> {
+    match res {
+        Ok(v) => Ok(Some(v)),
+        Err(FrameError::Incomplete) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// 解析"$", "!", "="这类带长度的字符串, 长度为-1时返回None
+fn parse_blob(src: &[u8]) -> Result<(usize, Option<&[u8]>), FrameError> {
+    let (line, next) = read_line(src, 1)?;
+    let len = match parse_decimal(line) {
+        Some(-1) => return Ok((next, None)),
+        Some(len) if (0..=MAX_BULK_LEN as i64).contains(&len) => len as usize,
+        _ => return Err(FrameError::Invalid("invalid bulk length".to_string())),
+    };
+    if src.len() < next + len + 2 {
+        return Err(FrameError::Incomplete);
+    }
+    if &src[next + len..next + len + 2] != b"\r\n" {
+        return Err(FrameError::Invalid(
+            "bulk string is not terminated by CRLF".to_string(),
+        ));
+    }
+    Ok((next + len + 2, Some(&src[next..next + len])))
+}
+
+// 解析"*", "%", "~", ">", "|"这类聚合类型, 返回其中的n个(对于map类型为2n个)元素;
+// depth为当前的嵌套层数
+fn parse_aggregate(
+    src: &[u8],
+    pairs: bool,
+    depth: usize,
+) -> Result<(usize, Option<Vec<RESP>>), FrameError> {
+    if depth >= MAX_NESTING {
+        return Err(FrameError::Invalid("too deep nesting".to_string()));
+    }
+    let (line, mut i) = read_line(src, 1)?;
+    let len = match parse_decimal(line) {
+        Some(-1) => return Ok((i, None)),
+        Some(len) if (0..=i32::MAX as i64).contains(&len) => len as usize,
+        _ => return Err(FrameError::Invalid("invalid multibulk length".to_string())),
+    };
+    let len = if pairs { len * 2 } else { len };
+    // 长度来自客户端, 不能直接按其预分配
+    let mut arr = Vec::with_capacity(len.min(1024));
+    for _ in 0..len {
+        let (j, resp) = RESP::parse_nested(&src[i..], depth + 1)?;
+        i += j;
+        arr.push(resp);
+    }
+    Ok((i, Some(arr)))
+}
+
+// 应为"$"字符串的位置出现了其他字节, 与redis一样报告实际收到的字节;
+// 转义\r等控制字符, 避免破坏错误回复的格式
+fn expected_bulk(c: u8) -> FrameError {
+    FrameError::Invalid(format!(
+        "expected '$', got '{}'",
+        (c as char).escape_default()
+    ))
+}
+
+fn into_pairs(arr: Vec<RESP>) -> Vec<(RESP, RESP)> {
+    let mut pairs = Vec::with_capacity(arr.len() / 2);
+    let mut iter = arr.into_iter();
+    while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
+        pairs.push((k, v));
+    }
+    pairs
+}
+
+fn parse_line<T: std::str::FromStr>(src: &[u8], what: &str) -> Result<(usize, T), FrameError> {
+    let (line, next) = read_line(src, 1)?;
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(|v| (next, v))
+        .ok_or_else(|| FrameError::Invalid(format!("invalid {}", what)))
+}
+
+/// redis格式的浮点数: inf, -inf, nan; 其他值取能还原的最短有效数字,
+/// 按%.17g的规则在指数小于-4或不小于17时使用科学计数法, 如1e+21
+pub fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        return "nan".to_string();
+    } else if d.is_infinite() {
+        return if d > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let sci = format!("{:e}", d);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    if (-4..17).contains(&exp) {
+        d.to_string()
+    } else {
+        let sign = if exp < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exp.abs())
+    }
 }
 
 impl RESP {
-    pub fn read_next_resp(src: &[u8]) -> Option<(usize, RESP)> {
-        let mut i = 1;
+    /// 从src的开头解析一个完整的RESP, 返回其占用的字节数.
+    /// 数据不完整时返回`FrameError::Incomplete`, 格式错误时返回`FrameError::Invalid`.
+    pub fn parse(src: &[u8]) -> Result<(usize, RESP), FrameError> {
+        RESP::parse_nested(src, 0)
+    }
+
+    // depth为当前的嵌套层数
+    fn parse_nested(src: &[u8], depth: usize) -> Result<(usize, RESP), FrameError> {
         match src.first() {
+            None => Err(FrameError::Incomplete),
             Some(b'+') => {
-                while let Some(c) = src.get(i) {
-                    if *c != b'\r' {
-                        i += 1
-                    } else {
-                        break;
-                    }
-                }
-                match src.get(i) {
-                    Some(c) if *c == b'\r' => {
-                        i += 2;
-                        Some((
-                            i,
-                            RESP::Simple(
-                                String::from_utf8_lossy(&src[1..i - 2])
-                                    .to_string()
-                                    .to_lowercase(),
-                            ),
-                        ))
-                    }
-                    _ => None,
-                }
-            }
-            Some(b'$') => {
-                let mut len = 0;
-                let is_negative = match src.get(1) {
-                    Some(c) => {
-                        if *c == b'-' {
-                            i += 1;
-                            true
-                        } else {
-                            false
-                        }
-                    }
-                    _ => return None,
+                let (line, next) = read_line(src, 1)?;
+                Ok((
+                    next,
+                    RESP::Simple(String::from_utf8_lossy(line).to_string()),
+                ))
+            }
+            Some(b'-') => {
+                let (line, next) = read_line(src, 1)?;
+                Ok((next, RESP::Error(String::from_utf8_lossy(line).to_string())))
+            }
+            Some(b':') => parse_line(src, "integer").map(|(i, n)| (i, RESP::Integer(n))),
+            Some(b'(') => parse_line(src, "big number").map(|(i, n)| (i, RESP::BigNumber(n))),
+            Some(b',') => {
+                let (i, d): (usize, String) = parse_line(src, "double")?;
+                let d = match d.as_str() {
+                    "inf" => f64::INFINITY,
+                    "-inf" => f64::NEG_INFINITY,
+                    d => d
+                        .parse()
+                        .map_err(|_| FrameError::Invalid("invalid double".to_string()))?,
                 };
-                while let Some(c) = src.get(i) {
-                    if *c != b'\r' {
-                        len = len * 10 + (src[i] - b'0') as usize;
-                        i += 1;
-                    } else {
-                        break;
-                    }
-                }
-                match src.get(i) {
-                    Some(c) if *c == b'\r' => {
-                        i += 2;
-                        if is_negative {
-                            Some((i, RESP::Null))
-                        } else {
-                            // 取末尾可能存在的\r\n
-                            match src.get(i + len) {
-                                // Bulk String
-                                Some(c) if *c == b'\r' => Some((
-                                    i + len + 2,
-                                    RESP::Bulk(
-                                        String::from_utf8_lossy(&src[i..i + len])
-                                            .to_string()
-                                            .to_lowercase(),
-                                    ),
-                                )),
-                                // 取不到值或者取到的不是'\r', 为RDB File
-                                _ => Some((
-                                    i + len,
-                                    RESP::RDBFile(Bytes::copy_from_slice(&src[i..i + len])),
-                                )),
-                            }
-                        }
-                    }
-                    _ => None,
-                }
+                Ok((i, RESP::Double(d)))
             }
-            Some(b'*') => {
-                // println!("frame arr:{}", String::from_utf8_lossy(src));
-                let mut len = 0;
-                while let Some(c) = src.get(i) {
-                    if *c != b'\r' {
-                        len = len * 10 + (src[i] - b'0') as usize;
-                        i += 1;
-                    } else {
-                        break;
-                    }
-                }
-                match src.get(i) {
-                    Some(c) if *c == b'\r' => {
-                        i += 2;
-                        let mut arr = Vec::with_capacity(len);
-                        for _ in 0..len {
-                            if let Some((j, resp)) = RESP::read_next_resp(&src[i..]) {
-                                i += j;
-                                arr.push(resp);
-                            } else {
-                                return None;
-                            }
-                        }
-                        Some((i, RESP::Array(arr)))
-                    }
-                    _ => None,
-                }
+            Some(b'#') => match read_line(src, 1)? {
+                (b"t", next) => Ok((next, RESP::Boolean(true))),
+                (b"f", next) => Ok((next, RESP::Boolean(false))),
+                _ => Err(FrameError::Invalid("invalid boolean".to_string())),
+            },
+            Some(b'_') => match read_line(src, 1)? {
+                (b"", next) => Ok((next, RESP::Null)),
+                _ => Err(FrameError::Invalid("invalid null".to_string())),
+            },
+            Some(b'$') => match parse_blob(src)? {
+                (i, Some(b)) => Ok((i, RESP::Bulk(Bytes::copy_from_slice(b)))),
+                (i, None) => Ok((i, RESP::Null)),
+            },
+            Some(b'!') => match parse_blob(src)? {
+                (i, Some(b)) => Ok((i, RESP::BlobError(Bytes::copy_from_slice(b)))),
+                (i, None) => Ok((i, RESP::Null)),
+            },
+            Some(b'=') => match parse_blob(src)? {
+                // 前4个字节是"txt:"这样的格式说明
+                (i, Some(b)) if b.len() >= 4 && b[3] == b':' => Ok((
+                    i,
+                    RESP::Verbatim(String::from_utf8_lossy(&b[4..]).to_string()),
+                )),
+                (i, None) => Ok((i, RESP::Null)),
+                _ => Err(FrameError::Invalid("invalid verbatim string".to_string())),
+            },
+            Some(b'*') => match parse_aggregate(src, false, depth)? {
+                (i, Some(arr)) => Ok((i, RESP::Array(arr))),
+                (i, None) => Ok((i, RESP::Null)),
+            },
+            Some(b'~') => match parse_aggregate(src, false, depth)? {
+                (i, Some(arr)) => Ok((i, RESP::Set(arr))),
+                (i, None) => Ok((i, RESP::Null)),
+            },
+            Some(b'>') => match parse_aggregate(src, false, depth)? {
+                (i, Some(arr)) => Ok((i, RESP::Push(arr))),
+                (i, None) => Ok((i, RESP::Null)),
+            },
+            Some(b'%') => match parse_aggregate(src, true, depth)? {
+                (i, Some(arr)) => Ok((i, RESP::Map(into_pairs(arr)))),
+                (i, None) => Ok((i, RESP::Null)),
+            },
+            Some(b'|') => {
+                let (i, attrs) = parse_aggregate(src, true, depth)?;
+                // 属性之后的回复也计入嵌套层数, 避免连续的属性无限递归
+                let (j, reply) = RESP::parse_nested(&src[i..], depth + 1)?;
+                Ok((
+                    i + j,
+                    RESP::Attribute(into_pairs(attrs.unwrap_or_default()), Box::new(reply)),
+                ))
             }
-            _ => None,
+            Some(c) => Err(FrameError::Invalid(format!(
+                "unknown type byte '{}'",
+                (*c as char).escape_default()
+            ))),
         }
     }
 
-    pub fn new_bulk(str: String) -> Self {
-        RESP::Bulk(str)
+    pub fn new_bulk(b: impl Into<Bytes>) -> Self {
+        RESP::Bulk(b.into())
     }
 
     pub fn new_simple(str: String) -> Self {
@@ -136,15 +260,109 @@ impl RESP {
     pub fn new_cmd_array(strs: Vec<String>) -> Self {
         RESP::Array(strs.into_iter().map(RESP::new_bulk).collect())
     }
+
+    /// 以RESP2编码到dst, 用于主从复制等不经过HELLO协商的场景
+    pub fn encode(&self, dst: &mut BytesMut) {
+        self.encode_as(dst, Protocol::Resp2)
+    }
+
+    /// 按连接协商的协议版本直接编码到dst, RESP2连接会收到RESP3类型的降级形式
+    pub fn encode_as(&self, dst: &mut BytesMut, proto: Protocol) {
+        let resp3 = proto == Protocol::Resp3;
+        match self {
+            RESP::Integer(i) => encode_line(dst, b':', i),
+            RESP::Simple(s) => encode_line(dst, b'+', s),
+            RESP::Error(e) => encode_line(dst, b'-', e),
+            RESP::Bulk(b) => encode_blob(dst, b'$', b),
+            RESP::Array(arr) => {
+                encode_line(dst, b'*', arr.len());
+                for resp in arr {
+                    resp.encode_as(dst, proto);
+                }
+            }
+            RESP::Set(arr) | RESP::Push(arr) => {
+                let prefix = match (resp3, self) {
+                    (false, _) => b'*',
+                    (true, RESP::Set(_)) => b'~',
+                    _ => b'>',
+                };
+                encode_line(dst, prefix, arr.len());
+                for resp in arr {
+                    resp.encode_as(dst, proto);
+                }
+            }
+            RESP::Map(pairs) => {
+                if resp3 {
+                    encode_line(dst, b'%', pairs.len());
+                } else {
+                    encode_line(dst, b'*', pairs.len() * 2);
+                }
+                for (k, v) in pairs {
+                    k.encode_as(dst, proto);
+                    v.encode_as(dst, proto);
+                }
+            }
+            RESP::Attribute(attrs, reply) => {
+                // RESP2没有属性类型, 只发送实际回复
+                if resp3 {
+                    encode_line(dst, b'|', attrs.len());
+                    for (k, v) in attrs {
+                        k.encode_as(dst, proto);
+                        v.encode_as(dst, proto);
+                    }
+                }
+                reply.encode_as(dst, proto);
+            }
+            RESP::BlobError(e) if resp3 => encode_blob(dst, b'!', e),
+            RESP::BlobError(e) => {
+                let e = String::from_utf8_lossy(e).replace(['\r', '\n'], " ");
+                encode_line(dst, b'-', e);
+            }
+            RESP::RDBFile(bytes) => {
+                encode_line(dst, b'$', bytes.len());
+                dst.put_slice(bytes);
+            }
+            RESP::Null if resp3 => dst.put_slice(b"_\r\n"),
+            RESP::Null => dst.put_slice(b"$-1\r\n"),
+            RESP::NullArray if resp3 => dst.put_slice(b"_\r\n"),
+            RESP::NullArray => dst.put_slice(b"*-1\r\n"),
+            RESP::Boolean(b) if resp3 => dst.put_slice(if *b { b"#t\r\n" } else { b"#f\r\n" }),
+            RESP::Boolean(b) => dst.put_slice(if *b { b":1\r\n" } else { b":0\r\n" }),
+            RESP::Double(d) if resp3 => encode_line(dst, b',', format_double(*d)),
+            RESP::Double(d) => encode_blob(dst, b'$', format_double(*d).as_bytes()),
+            RESP::BigNumber(n) if resp3 => encode_line(dst, b'(', n),
+            RESP::BigNumber(n) => encode_blob(dst, b'$', n.to_string().as_bytes()),
+            RESP::Verbatim(v) if resp3 => {
+                encode_line(dst, b'=', v.len() + 4);
+                dst.put_slice(b"txt:");
+                dst.put_slice(v.as_bytes());
+                dst.put_slice(b"\r\n");
+            }
+            RESP::Verbatim(v) => encode_blob(dst, b'$', v.as_bytes()),
+        }
+    }
+}
+
+// 写入"<prefix><value>\r\n", 直接格式化到dst中而不经过中间的String
+fn encode_line(dst: &mut BytesMut, prefix: u8, value: impl Display) {
+    dst.put_u8(prefix);
+    let _ = write!(dst, "{}", value);
+    dst.put_slice(b"\r\n");
+}
+
+fn encode_blob(dst: &mut BytesMut, prefix: u8, b: &[u8]) {
+    encode_line(dst, prefix, b.len());
+    dst.put_slice(b);
+    dst.put_slice(b"\r\n");
 }
 
 impl Display for RESP {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RESP::Integer(i) => write!(f, ":{:+}\r\n", i),
+            RESP::Integer(i) => write!(f, ":{}\r\n", i),
             RESP::Simple(s) => write!(f, "+{}\r\n", s),
             RESP::Error(e) => write!(f, "-{}\r\n", e),
-            RESP::Bulk(b) => write!(f, "${}\r\n{}\r\n", b.len(), b),
+            RESP::Bulk(b) => write!(f, "${}\r\n{}\r\n", b.len(), String::from_utf8_lossy(b)),
             RESP::Array(arr) => {
                 write!(f, "*{}\r\n", arr.len())?;
                 for resp in arr {
@@ -153,42 +371,360 @@ impl Display for RESP {
                 Ok(())
             }
             RESP::Null => write!(f, "$-1\r\n"),
+            RESP::NullArray => write!(f, "*-1\r\n"),
             RESP::Boolean(b) => write!(f, "#{}\r\n", if *b { 't' } else { 'f' }),
-            RESP::Double(d) => write!(f, ",{}\r\n", d),
+            RESP::Double(d) => write!(f, ",{}\r\n", format_double(*d)),
             RESP::BigNumber(n) => write!(f, "({}\r\n", n),
-            RESP::Verbatim(v) => write!(f, "={}\r\ntxt:{}\r\n", v.len(), v),
-            RESP::RDBFile(bytes) => write!(f, "${}\r\n{:?}", bytes.len(), bytes),
+            RESP::Verbatim(v) => write!(f, "={}\r\ntxt:{}\r\n", v.len() + 4, v),
+            // RDB是二进制数据, 只显示长度和开头部分的转义形式; 发送时使用encode
+            RESP::RDBFile(bytes) => {
+                let head = &bytes[..bytes.len().min(RDB_DISPLAY_LEN)];
+                let ellipsis = if head.len() < bytes.len() { "..." } else { "" };
+                write!(
+                    f,
+                    "<RDB file, {} bytes: {}{}>",
+                    bytes.len(),
+                    head.escape_ascii(),
+                    ellipsis
+                )
+            }
+            RESP::Map(pairs) => {
+                write!(f, "%{}\r\n", pairs.len())?;
+                for (k, v) in pairs {
+                    write!(f, "{}{}", k, v)?;
+                }
+                Ok(())
+            }
+            RESP::Set(arr) | RESP::Push(arr) => {
+                let prefix = if matches!(self, RESP::Set(_)) {
+                    '~'
+                } else {
+                    '>'
+                };
+                write!(f, "{}{}\r\n", prefix, arr.len())?;
+                for resp in arr {
+                    write!(f, "{}", resp)?;
+                }
+                Ok(())
+            }
+            RESP::Attribute(attrs, reply) => {
+                write!(f, "|{}\r\n", attrs.len())?;
+                for (k, v) in attrs {
+                    write!(f, "{}{}", k, v)?;
+                }
+                write!(f, "{}", reply)
+            }
+            RESP::BlobError(e) => write!(f, "!{}\r\n{}\r\n", e.len(), String::from_utf8_lossy(e)),
         }
     }
 }
 
+impl From<CmdError> for RESP {
+    fn from(e: CmdError) -> Self {
+        RESP::Error(e.to_string())
+    }
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+// 按redis的sdssplitargs规则切分inline命令: 空白分隔, 支持单双引号以及双引号内的转义,
+// 引号不匹配时返回None
+fn split_inline_args(line: &[u8]) -> Option<Vec<Bytes>> {
+    let mut args = vec![];
+    let mut i = 0;
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Some(args);
+        }
+        let (mut in_dq, mut in_sq) = (false, false);
+        let mut current = vec![];
+        loop {
+            let c = line.get(i).copied();
+            if in_dq {
+                match c? {
+                    b'\\'
+                        if line.get(i + 1) == Some(&b'x')
+                            && line.get(i + 2).copied().and_then(hex_digit).is_some()
+                            && line.get(i + 3).copied().and_then(hex_digit).is_some() =>
+                    {
+                        current.push(hex_digit(line[i + 2])? * 16 + hex_digit(line[i + 3])?);
+                        i += 3;
+                    }
+                    b'\\' if i + 1 < line.len() => {
+                        i += 1;
+                        current.push(match line[i] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            c => c,
+                        });
+                    }
+                    b'"' => {
+                        // 右引号后面必须是空白或结尾
+                        if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                            return None;
+                        }
+                        i += 1;
+                        break;
+                    }
+                    c => current.push(c),
+                }
+            } else if in_sq {
+                match c? {
+                    b'\\' if line.get(i + 1) == Some(&b'\'') => {
+                        current.push(b'\'');
+                        i += 1;
+                    }
+                    b'\'' => {
+                        if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                            return None;
+                        }
+                        i += 1;
+                        break;
+                    }
+                    c => current.push(c),
+                }
+            } else {
+                match c {
+                    None => break,
+                    Some(c) if c.is_ascii_whitespace() => break,
+                    Some(b'"') => in_dq = true,
+                    Some(b'\'') => in_sq = true,
+                    Some(c) => current.push(c),
+                }
+            }
+            i += 1;
+        }
+        args.push(Bytes::from(current));
+    }
+}
+
+/// 流式RESP解码器: 累积从socket读到的数据, 直到其中包含完整的RESP
+pub struct RespDecoder {
+    buf: BytesMut,
+    // 未读完的请求数组, 已解析的参数会保留下来, 不必每次读到数据都从头解析
+    request: Option<PartialRequest>,
+}
+
+// 请求数组的解析进度
+struct PartialRequest {
+    // 还未读到的参数个数
+    remaining: usize,
+    args: Vec<RESP>,
+    // 已经从缓冲区中取出的字节数
+    consumed: usize,
+}
+
+impl Default for RespDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RespDecoder {
+    pub fn new() -> Self {
+        RespDecoder {
+            buf: BytesMut::with_capacity(4096),
+            request: None,
+        }
+    }
+
+    pub fn extend(&mut self, src: &[u8]) {
+        self.buf.extend_from_slice(src);
+    }
+
+    /// 从reader读取数据追加到缓冲区, 返回读取的字节数, 0表示对端已关闭
+    pub async fn read_from<R: AsyncRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+    ) -> std::io::Result<usize> {
+        reader.read_buf(&mut self.buf).await
+    }
+
+    /// 解码下一个RESP及其字节长度, 缓冲区中的数据不足时返回Ok(None);
+    /// 每次都从头解析, 只用于握手时的回复这类短小的数据
+    pub fn decode(&mut self) -> Result<Option<(usize, RESP)>, FrameError> {
+        let res = incomplete_to_none(RESP::parse(&self.buf))?;
+        if let Some((len, _)) = &res {
+            self.buf.advance(*len);
+        }
+        Ok(res)
+    }
+
+    /// 解码客户端请求: 与redis一样, "*"开头的按只包含"$"字符串的数组解析, 其余按inline命令解析,
+    /// 使telnet以及健康检查发送的"PING\r\n"也能得到回复
+    pub fn decode_request(&mut self) -> Result<Option<(usize, RESP)>, FrameError> {
+        loop {
+            if let Some(mut request) = self.request.take() {
+                if !self.decode_args(&mut request)? {
+                    self.request = Some(request);
+                    return Ok(None);
+                }
+                return Ok(Some((request.consumed, RESP::Array(request.args))));
+            }
+            match self.buf.first() {
+                None => return Ok(None),
+                Some(b'*') => {
+                    let (line, next) = match incomplete_to_none(read_line(&self.buf, 1))? {
+                        Some(res) => res,
+                        None if self.buf.len() > MAX_INLINE_LEN => {
+                            return Err(FrameError::Invalid(
+                                "too big mbulk count string".to_string(),
+                            ))
+                        }
+                        None => return Ok(None),
+                    };
+                    let count = match parse_decimal(line) {
+                        Some(count) if count <= i32::MAX as i64 => count,
+                        _ => {
+                            return Err(FrameError::Invalid("invalid multibulk length".to_string()))
+                        }
+                    };
+                    self.buf.advance(next);
+                    // *0和*-1是空请求, 直接忽略
+                    if count > 0 {
+                        self.request = Some(PartialRequest {
+                            remaining: count as usize,
+                            // 个数来自客户端, 不能直接按其预分配
+                            args: Vec::with_capacity((count as usize).min(1024)),
+                            consumed: next,
+                        });
+                    }
+                    continue;
+                }
+                // RESP3的聚合类型只出现在回复中, 请求里不接受, 也不当作inline命令
+                Some(c @ (b'%' | b'~' | b'>' | b'|')) => {
+                    return Err(FrameError::Invalid(format!(
+                        "expected '*', got '{}'",
+                        *c as char
+                    )))
+                }
+                Some(_) => (),
+            }
+            let pos = match self.buf.iter().position(|&c| c == b'\n') {
+                Some(pos) if pos <= MAX_INLINE_LEN => pos,
+                None if self.buf.len() <= MAX_INLINE_LEN => return Ok(None),
+                _ => return Err(FrameError::Invalid("too big inline request".to_string())),
+            };
+            let line = self.buf.split_to(pos + 1);
+            let args = split_inline_args(&line[..pos]).ok_or_else(|| {
+                FrameError::Invalid("unbalanced quotes in inline request".to_string())
+            })?;
+            // 空行直接忽略
+            if !args.is_empty() {
+                return Ok(Some((
+                    pos + 1,
+                    RESP::Array(args.into_iter().map(RESP::Bulk).collect()),
+                )));
+            }
+        }
+    }
+
+    // 继续读取请求数组的参数, 每个参数都必须是"$"字符串; 返回是否已读完全部参数
+    fn decode_args(&mut self, request: &mut PartialRequest) -> Result<bool, FrameError> {
+        while request.remaining > 0 {
+            match self.buf.first() {
+                None => return Ok(false),
+                Some(b'$') => (),
+                Some(c) => return Err(expected_bulk(*c)),
+            }
+            let (line, next) = match incomplete_to_none(read_line(&self.buf, 1))? {
+                Some(res) => res,
+                None if self.buf.len() > MAX_INLINE_LEN => {
+                    return Err(FrameError::Invalid("too big bulk count string".to_string()))
+                }
+                None => return Ok(false),
+            };
+            let len = match parse_decimal(line) {
+                Some(len) if (0..=MAX_BULK_LEN as i64).contains(&len) => len as usize,
+                _ => return Err(FrameError::Invalid("invalid bulk length".to_string())),
+            };
+            if self.buf.len() < next + len + 2 {
+                return Ok(false);
+            }
+            if &self.buf[next + len..next + len + 2] != b"\r\n" {
+                return Err(FrameError::Invalid(
+                    "bulk string is not terminated by CRLF".to_string(),
+                ));
+            }
+            let arg = Bytes::copy_from_slice(&self.buf[next..next + len]);
+            self.buf.advance(next + len + 2);
+            request.args.push(RESP::Bulk(arg));
+            request.remaining -= 1;
+            request.consumed += next + len + 2;
+        }
+        Ok(true)
+    }
+
+    /// 解码FULLRESYNC之后的RDB文件: "$<len>\r\n<bytes>", 末尾没有"\r\n"
+    pub fn decode_rdb(&mut self) -> Result<Option<Bytes>, FrameError> {
+        match self.buf.first() {
+            None => return Ok(None),
+            Some(b'$') => (),
+            Some(c) => return Err(expected_bulk(*c)),
+        }
+        let (line, next) = match incomplete_to_none(read_line(&self.buf, 1))? {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+        let len = match parse_decimal(line) {
+            Some(len) if (0..=MAX_BULK_LEN as i64).contains(&len) => len as usize,
+            _ => return Err(FrameError::Invalid("invalid bulk length".to_string())),
+        };
+        if self.buf.len() < next + len {
+            return Ok(None);
+        }
+        self.buf.advance(next);
+        Ok(Some(self.buf.split_to(len).freeze()))
+    }
+}
+
 #[cfg(test)]
 mod resp_test {
     use super::*;
 
+    impl RESP {
+        fn to_bytes(&self) -> BytesMut {
+            self.to_bytes_as(Protocol::Resp2)
+        }
+
+        fn to_bytes_as(&self, proto: Protocol) -> BytesMut {
+            let mut dst = BytesMut::new();
+            self.encode_as(&mut dst, proto);
+            dst
+        }
+    }
+
     #[test]
     fn test_simple_string() {
         let src = b"+FULLRESYNC 75cd7bc10c49047e0d163660f3b90625b1af31dc 0\r\n";
-        let (len, resp) = RESP::read_next_resp(src).unwrap();
+        let (len, resp) = RESP::parse(src).unwrap();
         assert_eq!(len, 56);
         assert_eq!(
             resp,
-            RESP::Simple("fullresync 75cd7bc10c49047e0d163660f3b90625b1af31dc 0".to_string())
+            RESP::Simple("FULLRESYNC 75cd7bc10c49047e0d163660f3b90625b1af31dc 0".to_string())
         );
     }
 
     #[test]
     fn test_bulk_string() {
         let src = b"$6\r\nfoobar\r\n";
-        let (len, resp) = RESP::read_next_resp(src).unwrap();
+        let (len, resp) = RESP::parse(src).unwrap();
         assert_eq!(len, 12);
-        assert_eq!(resp, RESP::Bulk("foobar".to_string()));
+        assert_eq!(resp, RESP::new_bulk("foobar"));
     }
 
     #[test]
     fn test_null_bulk_string() {
         let src = b"$-1\r\n";
-        let (len, resp) = RESP::read_next_resp(src).unwrap();
+        let (len, resp) = RESP::parse(src).unwrap();
         assert_eq!(len, 5);
         assert_eq!(resp, RESP::Null);
     }
@@ -196,29 +732,26 @@ mod resp_test {
     #[test]
     fn test_array() {
         let src = b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n";
-        let (len, resp) = RESP::read_next_resp(src).unwrap();
+        let (len, resp) = RESP::parse(src).unwrap();
         assert_eq!(len, 22);
         assert_eq!(
             resp,
-            RESP::Array(vec![
-                RESP::Bulk("foo".to_string()),
-                RESP::Bulk("bar".to_string())
-            ])
+            RESP::Array(vec![RESP::new_bulk("foo"), RESP::new_bulk("bar")])
         );
     }
 
     #[test]
     fn test_another_array() {
         let src = b"*4\r\n$5\r\napple\r\n$6\r\nbanana\r\n$2\r\npx\r\n$3\r\n123\r\n";
-        let (_, resp) = RESP::read_next_resp(src).unwrap();
+        let (_, resp) = RESP::parse(src).unwrap();
         // println!("resp: {}", resp);
         assert_eq!(
             resp,
             RESP::Array(vec![
-                RESP::Bulk("apple".to_string()),
-                RESP::Bulk("banana".to_string()),
-                RESP::Bulk("px".to_string()),
-                RESP::Bulk("123".to_string()),
+                RESP::new_bulk("apple"),
+                RESP::new_bulk("banana"),
+                RESP::new_bulk("px"),
+                RESP::new_bulk("123"),
             ])
         )
     }
@@ -227,17 +760,370 @@ mod resp_test {
     fn test_multiple_resp() {
         let mut i = 0;
         let src = b"*4\r\n$5\r\napple\r\n$6\r\nbanana\r\n$2\r\npx\r\n$3\r\n123\r\n*4\r\n$5\r\napple\r\n$6\r\nbanana\r\n$2\r\npx\r\n$3\r\n123\r\n";
-        while let Some((_i, resp)) = RESP::read_next_resp(&src[i..]) {
+        while let Ok((_i, resp)) = RESP::parse(&src[i..]) {
             i += _i;
             assert_eq!(
                 resp,
                 RESP::Array(vec![
-                    RESP::Bulk("apple".to_string()),
-                    RESP::Bulk("banana".to_string()),
-                    RESP::Bulk("px".to_string()),
-                    RESP::Bulk("123".to_string()),
+                    RESP::new_bulk("apple"),
+                    RESP::new_bulk("banana"),
+                    RESP::new_bulk("px"),
+                    RESP::new_bulk("123"),
                 ])
             )
         }
     }
+
+    #[test]
+    fn test_incomplete_frame() {
+        let src = b"*2\r\n$3\r\nfoo\r\n$3\r\nba";
+        assert_eq!(RESP::parse(src), Err(FrameError::Incomplete));
+        let src = b"$10\r\nfoo";
+        assert_eq!(RESP::parse(src), Err(FrameError::Incomplete));
+    }
+
+    #[test]
+    fn test_invalid_frame() {
+        assert!(matches!(
+            RESP::parse(b"$abc\r\nfoo\r\n"),
+            Err(FrameError::Invalid(_))
+        ));
+        assert!(matches!(
+            RESP::parse(b"$3\r\nfoobar\r\n"),
+            Err(FrameError::Invalid(_))
+        ));
+        assert!(matches!(RESP::parse(b"?\r\n"), Err(FrameError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_decoder_split_frames() {
+        let src = b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n+OK\r\n";
+        let mut decoder = RespDecoder::new();
+        let mut frames = vec![];
+        // 每次只喂入一个字节, 模拟被拆分到多次TCP读取的数据
+        for b in src.iter() {
+            decoder.extend(&[*b]);
+            while let Some((len, resp)) = decoder.decode().unwrap() {
+                frames.push((len, resp));
+            }
+        }
+        assert_eq!(
+            frames,
+            vec![
+                (
+                    22,
+                    RESP::Array(vec![RESP::new_bulk("foo"), RESP::new_bulk("bar")])
+                ),
+                (5, RESP::Simple("OK".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decoder_large_bulk() {
+        let value = "x".repeat(5000);
+        let src =
+            RESP::new_cmd_array(vec!["set".to_string(), "k".to_string(), value.clone()]).to_bytes();
+        let mut decoder = RespDecoder::new();
+        decoder.extend(&src[..1024]);
+        assert_eq!(decoder.decode(), Ok(None));
+        decoder.extend(&src[1024..]);
+        let (len, resp) = decoder.decode().unwrap().unwrap();
+        assert_eq!(len, src.len());
+        assert_eq!(
+            resp,
+            RESP::new_cmd_array(vec!["set".to_string(), "k".to_string(), value])
+        );
+    }
+
+    #[test]
+    fn test_multibulk_request() {
+        let src = b"*2\r\n$3\r\nget\r\n$1\r\nk\r\n*0\r\n*1\r\n$4\r\nPING\r\n";
+        let mut decoder = RespDecoder::new();
+        let mut requests = vec![];
+        // 逐字节喂入, 已解析的参数在多次调用之间保留
+        for b in src.iter() {
+            decoder.extend(&[*b]);
+            while let Some(request) = decoder.decode_request().unwrap() {
+                requests.push(request);
+            }
+        }
+        assert_eq!(
+            requests,
+            vec![
+                (
+                    20,
+                    RESP::Array(vec![RESP::new_bulk("get"), RESP::new_bulk("k")])
+                ),
+                (14, RESP::Array(vec![RESP::new_bulk("PING")])),
+            ]
+        );
+
+        // 请求中只能有"$"字符串, 不会递归解析嵌套的数组
+        let mut decoder = RespDecoder::new();
+        decoder.extend(&b"*1\r\n".repeat(100_000));
+        assert_eq!(
+            decoder.decode_request(),
+            Err(FrameError::Invalid("expected '$', got '*'".to_string()))
+        );
+        let mut decoder = RespDecoder::new();
+        decoder.extend(b"*1\r\n:1\r\n");
+        assert_eq!(
+            decoder.decode_request(),
+            Err(FrameError::Invalid("expected '$', got ':'".to_string()))
+        );
+        // 缺少参数时收到的是空行
+        let mut decoder = RespDecoder::new();
+        decoder.extend(b"*2\r\n$1\r\na\r\n\r\n");
+        assert_eq!(
+            decoder.decode_request(),
+            Err(FrameError::Invalid("expected '$', got '\\r'".to_string()))
+        );
+        let mut decoder = RespDecoder::new();
+        decoder.extend(b"*1\r\nPING\r\n");
+        assert_eq!(
+            decoder.decode_request(),
+            Err(FrameError::Invalid("expected '$', got 'P'".to_string()))
+        );
+        let mut decoder = RespDecoder::new();
+        decoder.extend(b"REDIS");
+        assert_eq!(
+            decoder.decode_rdb(),
+            Err(FrameError::Invalid("expected '$', got 'R'".to_string()))
+        );
+        let mut decoder = RespDecoder::new();
+        decoder.extend(b"*x\r\n");
+        assert_eq!(
+            decoder.decode_request(),
+            Err(FrameError::Invalid("invalid multibulk length".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let mut src = b"*1\r\n".repeat(MAX_NESTING + 1);
+        src.extend_from_slice(b"$1\r\na\r\n");
+        assert!(RESP::parse(&src[4..]).is_ok());
+        assert_eq!(
+            RESP::parse(&src),
+            Err(FrameError::Invalid("too deep nesting".to_string()))
+        );
+        // 回复中的RESP3聚合类型同样受限, 包括连续的属性
+        let mut src = b"%1\r\n+k\r\n".repeat(MAX_NESTING + 1);
+        src.extend_from_slice(b"+v\r\n");
+        assert!(RESP::parse(&src).is_err());
+        let mut src = b"|0\r\n".repeat(MAX_NESTING + 1);
+        src.extend_from_slice(b"+OK\r\n");
+        assert_eq!(
+            RESP::parse(&src),
+            Err(FrameError::Invalid("too deep nesting".to_string()))
+        );
+        assert!(RESP::parse(&src[4..]).is_ok());
+    }
+
+    #[test]
+    fn test_resp3_request_rejected() {
+        for prefix in [b'%', b'~', b'>', b'|'] {
+            let mut decoder = RespDecoder::new();
+            decoder.extend(&[prefix]);
+            decoder.extend(b"1\r\n$1\r\na\r\n$1\r\nb\r\n");
+            assert_eq!(
+                decoder.decode_request(),
+                Err(FrameError::Invalid(format!(
+                    "expected '*', got '{}'",
+                    prefix as char
+                )))
+            );
+            // 嵌套在请求数组中
+            let mut decoder = RespDecoder::new();
+            decoder.extend(b"*2\r\n$4\r\nPING\r\n");
+            decoder.extend(&[prefix]);
+            decoder.extend(b"1\r\n$1\r\na\r\n$1\r\nb\r\n");
+            assert_eq!(
+                decoder.decode_request(),
+                Err(FrameError::Invalid(format!(
+                    "expected '$', got '{}'",
+                    prefix as char
+                )))
+            );
+        }
+    }
+
+    #[test]
+    fn test_decoder_rdb() {
+        let mut decoder = RespDecoder::new();
+        decoder.extend(b"$5\r\nREDIS*1\r\n$4\r\nping\r\n");
+        assert_eq!(decoder.decode_rdb(), Ok(Some(Bytes::from_static(b"REDIS"))));
+        assert_eq!(
+            decoder.decode(),
+            Ok(Some((14, RESP::Array(vec![RESP::new_bulk("ping")]))))
+        );
+    }
+
+    #[test]
+    fn test_binary_bulk_round_trip() {
+        let value = Bytes::from_static(b"\x00\xff\r\nFoo");
+        let src = RESP::Array(vec![RESP::new_bulk("SET"), RESP::Bulk(value.clone())]).to_bytes();
+        let (len, resp) = RESP::parse(&src).unwrap();
+        assert_eq!(len, src.len());
+        assert_eq!(
+            resp,
+            RESP::Array(vec![RESP::new_bulk("SET"), RESP::Bulk(value)])
+        );
+    }
+
+    #[test]
+    fn test_resp3_types() {
+        let src = b"%2\r\n+first\r\n:1\r\n$6\r\nsecond\r\n#t\r\n";
+        let (len, resp) = RESP::parse(src).unwrap();
+        assert_eq!(len, src.len());
+        assert_eq!(
+            resp,
+            RESP::Map(vec![
+                (RESP::Simple("first".to_string()), RESP::Integer(1)),
+                (RESP::new_bulk("second"), RESP::Boolean(true)),
+            ])
+        );
+        let src = b"~2\r\n,1.5\r\n_\r\n";
+        assert_eq!(
+            RESP::parse(src).unwrap().1,
+            RESP::Set(vec![RESP::Double(1.5), RESP::Null])
+        );
+        let src = b">2\r\n$7\r\nmessage\r\n(3492890328409238509324850943850943825024385\r\n";
+        assert!(matches!(RESP::parse(src), Err(FrameError::Invalid(_))));
+        let src = b">2\r\n$7\r\nmessage\r\n(12345678901234567890\r\n";
+        assert_eq!(
+            RESP::parse(src).unwrap().1,
+            RESP::Push(vec![
+                RESP::new_bulk("message"),
+                RESP::BigNumber(12345678901234567890)
+            ])
+        );
+        let src = b"|1\r\n+ttl\r\n:3\r\n!21\r\nSYNTAX invalid syntax\r\n";
+        assert_eq!(
+            RESP::parse(src).unwrap().1,
+            RESP::Attribute(
+                vec![(RESP::Simple("ttl".to_string()), RESP::Integer(3))],
+                Box::new(RESP::BlobError(Bytes::from("SYNTAX invalid syntax")))
+            )
+        );
+        let src = b"=15\r\ntxt:Some string\r\n";
+        assert_eq!(
+            RESP::parse(src).unwrap().1,
+            RESP::Verbatim("Some string".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_double() {
+        assert_eq!(format_double(1.5), "1.5");
+        assert_eq!(format_double(3.0), "3");
+        assert_eq!(format_double(-0.0001), "-0.0001");
+        assert_eq!(format_double(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format_double(1e16), "10000000000000000");
+        assert_eq!(format_double(1e21), "1e+21");
+        assert_eq!(format_double(1.5e-7), "1.5e-07");
+        assert_eq!(format_double(f64::NEG_INFINITY), "-inf");
+    }
+
+    #[test]
+    fn test_encode_as_protocol() {
+        let resp = RESP::Map(vec![
+            (RESP::new_bulk("a"), RESP::Double(f64::INFINITY)),
+            (RESP::new_bulk("b"), RESP::Null),
+        ]);
+        assert_eq!(
+            resp.to_bytes_as(Protocol::Resp3),
+            &b"%2\r\n$1\r\na\r\n,inf\r\n$1\r\nb\r\n_\r\n"[..]
+        );
+        assert_eq!(
+            resp.to_bytes_as(Protocol::Resp2),
+            &b"*4\r\n$1\r\na\r\n$3\r\ninf\r\n$1\r\nb\r\n$-1\r\n"[..]
+        );
+        let resp = RESP::Verbatim("Some string".to_string());
+        assert_eq!(
+            resp.to_bytes_as(Protocol::Resp3),
+            &b"=15\r\ntxt:Some string\r\n"[..]
+        );
+        let (_, parsed) = RESP::parse(&resp.to_bytes_as(Protocol::Resp3)).unwrap();
+        assert_eq!(parsed, resp);
+        // Display与RESP2的形式一致
+        assert_eq!(RESP::Null.to_string(), "$-1\r\n");
+    }
+
+    #[test]
+    fn test_inline_request() {
+        let mut decoder = RespDecoder::new();
+        decoder.extend(b"PING\r\n\r\nset  key \"a \\x41\\n\" 'it\\'s'\nECHO");
+        assert_eq!(
+            decoder.decode_request(),
+            Ok(Some((6, RESP::Array(vec![RESP::new_bulk("PING")]))))
+        );
+        assert_eq!(
+            decoder.decode_request(),
+            Ok(Some((
+                28,
+                RESP::Array(vec![
+                    RESP::new_bulk("set"),
+                    RESP::new_bulk("key"),
+                    RESP::new_bulk("a A\n"),
+                    RESP::new_bulk("it's"),
+                ])
+            )))
+        );
+        // 没有换行符, 需要更多数据
+        assert_eq!(decoder.decode_request(), Ok(None));
+        decoder.extend(b" hi\r\n*1\r\n$4\r\nPING\r\n");
+        assert_eq!(
+            decoder.decode_request(),
+            Ok(Some((
+                9,
+                RESP::Array(vec![RESP::new_bulk("ECHO"), RESP::new_bulk("hi")])
+            )))
+        );
+        assert_eq!(
+            decoder.decode_request(),
+            Ok(Some((14, RESP::Array(vec![RESP::new_bulk("PING")]))))
+        );
+    }
+
+    #[test]
+    fn test_inline_request_errors() {
+        let mut decoder = RespDecoder::new();
+        decoder.extend(b"set \"key\r\n");
+        assert!(matches!(
+            decoder.decode_request(),
+            Err(FrameError::Invalid(_))
+        ));
+        let mut decoder = RespDecoder::new();
+        decoder.extend(b"get \"a\"b\r\n");
+        assert!(matches!(
+            decoder.decode_request(),
+            Err(FrameError::Invalid(_))
+        ));
+        let mut decoder = RespDecoder::new();
+        decoder.extend(&vec![b'a'; MAX_INLINE_LEN + 1]);
+        assert_eq!(
+            decoder.decode_request(),
+            Err(FrameError::Invalid("too big inline request".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_encode_matches_display() {
+        let resp = RESP::Array(vec![
+            RESP::Integer(-3),
+            RESP::Integer(42),
+            RESP::Simple("OK".to_string()),
+            RESP::new_bulk("foo"),
+            RESP::Error("ERR x".to_string()),
+        ]);
+        assert_eq!(resp.to_bytes(), resp.to_string().as_bytes());
+        assert_eq!(&resp.to_bytes()[..9], b"*5\r\n:-3\r\n");
+        let rdb = RESP::RDBFile(Bytes::from_static(b"REDIS0011\xfa\x09"));
+        assert_eq!(&rdb.to_bytes()[..], b"$11\r\nREDIS0011\xfa\x09");
+        assert_eq!(rdb.to_string(), "<RDB file, 11 bytes: REDIS0011\\xfa\\t>");
+        let rdb = RESP::RDBFile(Bytes::from(vec![0xff; 100]));
+        assert!(rdb.to_string().ends_with("\\xff...>"));
+    }
 }
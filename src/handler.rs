@@ -0,0 +1,135 @@
+use std::{future::Future, pin::Pin};
+
+use bytes::Bytes;
+use tokio::sync::RwLock;
+
+use crate::{
+    cmd_table::CommandSpec,
+    db::Shard,
+    error::CmdError,
+    frame::RESP,
+    server::{now_millis, shard_index, ClientState, ServerState, ShardedConfig, ShardedDb},
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// 自定义命令, 通过`Server::register_command`注册后与内置命令一样参与参数校验, COMMAND以及复制
+///
+/// ```ignore
+/// struct Lease { key: Bytes }
+///
+/// impl CommandHandler for Lease {
+///     fn parse(args: &[RESP]) -> Result<Self, CmdError> {
+///         let mut parser = CmdParser::new(args)?;
+///         Ok(Lease { key: parser.next_bytes()? })
+///     }
+///
+///     fn execute<'a>(&'a self, ctx: &'a mut Context<'_>) -> BoxFuture<'a, Result<RESP, CmdError>> {
+///         Box::pin(async move { ... })
+///     }
+/// }
+///
+/// server.register_command::<Lease>(CommandSpec::new("lease", 2, CMD_WRITE, (1, 1, 1)))?;
+/// ```
+pub trait CommandHandler: Send + Sync {
+    /// 从完整的命令数组(包括命令名)解析, 参数个数已按spec校验过
+    fn parse(args: &[RESP]) -> Result<Self, CmdError>
+    where
+        Self: Sized;
+
+    /// 执行命令, 返回的回复会按客户端协商的协议编码
+    fn execute<'a>(&'a self, ctx: &'a mut Context<'_>) -> BoxFuture<'a, Result<RESP, CmdError>>;
+}
+
+type ParseFn = fn(&[RESP]) -> Result<Box<dyn CommandHandler>, CmdError>;
+
+pub(crate) struct CustomCommand {
+    pub spec: CommandSpec,
+    parse: ParseFn,
+}
+
+impl CustomCommand {
+    pub fn new<T: CommandHandler + 'static>(spec: CommandSpec) -> Self {
+        CustomCommand {
+            spec,
+            parse: |args| T::parse(args).map(|cmd| Box::new(cmd) as Box<dyn CommandHandler>),
+        }
+    }
+
+    pub fn parse(&self, args: &[RESP]) -> Result<Box<dyn CommandHandler>, CmdError> {
+        self.spec.check_arity(args.len())?;
+        (self.parse)(args)
+    }
+}
+
+/// 命令执行时可以访问的服务器状态和当前连接的状态
+pub struct Context<'a> {
+    state: &'a ServerState,
+    pub client: &'a mut ClientState,
+    propagation: Option<Vec<RESP>>,
+    // 本次执行中可能有了新数据的(db, key), 用于唤醒阻塞的客户端
+    ready_keys: Vec<(usize, Bytes)>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(state: &'a ServerState, client: &'a mut ClientState) -> Self {
+        Context {
+            state,
+            client,
+            propagation: None,
+            ready_keys: Vec::new(),
+        }
+    }
+
+    /// 当前连接SELECT的db
+    pub fn db(&self) -> &ShardedDb {
+        &self.state.dbs[self.client.db]
+    }
+
+    /// key在当前db中所在的分片
+    pub fn shard(&self, key: &[u8]) -> &RwLock<Shard> {
+        let db = self.db();
+        &db[shard_index(db, key)]
+    }
+
+    /// 判断过期使用的当前时间; master连接上的命令返回0, 即不认为任何key已过期,
+    /// replica上的过期完全由master同步的DEL决定
+    pub fn now_millis(&self) -> u128 {
+        if self.client.master_link {
+            0
+        } else {
+            now_millis()
+        }
+    }
+
+    pub fn config(&self) -> &ShardedConfig {
+        &self.state.config
+    }
+
+    pub(crate) fn state(&self) -> &'a ServerState {
+        self.state
+    }
+
+    /// 写命令默认将原命令同步给replica, 这里可以改为同步其他命令(例如把相对时间改为绝对时间)
+    pub fn rewrite_propagation(&mut self, frame: RESP) {
+        self.propagation = Some(vec![frame]);
+    }
+
+    /// 不将本次执行同步给replica
+    pub fn prevent_propagation(&mut self) {
+        self.propagation = Some(vec![]);
+    }
+
+    pub(crate) fn take_propagation(&mut self) -> Option<Vec<RESP>> {
+        self.propagation.take()
+    }
+
+    /// 向列表等类型的key写入了数据, 命令同步给replica之后会唤醒阻塞在该key上的客户端
+    pub fn signal_key_ready(&mut self, db: usize, key: Bytes) {
+        self.ready_keys.push((db, key));
+    }
+
+    pub(crate) fn take_ready_keys(&mut self) -> Vec<(usize, Bytes)> {
+        std::mem::take(&mut self.ready_keys)
+    }
+}
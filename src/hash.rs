@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+/// 紧凑编码的阈值: 元素个数或者单个元素的长度超过后转换为常规编码
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ListpackLimits {
+    pub max_entries: usize,
+    pub max_value: usize,
+}
+
+/// 哈希类型; 字段较少且都较短时按插入顺序存放在数组中(类似listpack), 线性查找比哈希表更省内存,
+/// 超过阈值后转换为HashMap, 之后不会再转换回来
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hash {
+    Listpack(Vec<(Bytes, Bytes)>),
+    Table(HashMap<Bytes, Bytes>),
+}
+
+impl Default for Hash {
+    fn default() -> Self {
+        Hash::Listpack(Vec::new())
+    }
+}
+
+impl Hash {
+    pub fn len(&self) -> usize {
+        match self {
+            Hash::Listpack(pairs) => pairs.len(),
+            Hash::Table(map) => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// OBJECT ENCODING中的编码名
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Hash::Listpack(_) => "listpack",
+            Hash::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        match self {
+            Hash::Listpack(pairs) => pairs.iter().find(|(f, _)| f == field).map(|(_, v)| v),
+            Hash::Table(map) => map.get(field),
+        }
+    }
+
+    pub fn contains(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// 写入字段, 返回是否为新字段
+    pub fn insert(&mut self, field: Bytes, value: Bytes, limits: ListpackLimits) -> bool {
+        if let Hash::Listpack(pairs) = self {
+            if let Some((_, v)) = pairs.iter_mut().find(|(f, _)| *f == field) {
+                *v = value;
+                return false;
+            }
+            let fits = pairs.len() < limits.max_entries
+                && field.len() <= limits.max_value
+                && value.len() <= limits.max_value;
+            if fits {
+                pairs.push((field, value));
+                return true;
+            }
+            *self = Hash::Table(std::mem::take(pairs).into_iter().collect());
+        }
+        match self {
+            Hash::Table(map) => map.insert(field, value).is_none(),
+            Hash::Listpack(_) => unreachable!(),
+        }
+    }
+
+    /// 删除字段, 返回字段是否存在
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        match self {
+            Hash::Listpack(pairs) => match pairs.iter().position(|(f, _)| f == field) {
+                Some(i) => {
+                    pairs.remove(i);
+                    true
+                }
+                None => false,
+            },
+            Hash::Table(map) => map.remove(field).is_some(),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, &Bytes)> + '_> {
+        match self {
+            Hash::Listpack(pairs) => Box::new(pairs.iter().map(|(f, v)| (f, v))),
+            Hash::Table(map) => Box::new(map.iter()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod hash_test {
+    use super::*;
+
+    #[test]
+    fn test_encoding_conversion() {
+        let limits = ListpackLimits {
+            max_entries: 2,
+            max_value: 4,
+        };
+        let b = |s: &'static str| Bytes::from(s);
+        let mut hash = Hash::default();
+        assert!(hash.insert(b("a"), b("1"), limits));
+        assert!(!hash.insert(b("a"), b("2"), limits));
+        assert!(hash.insert(b("b"), b("3"), limits));
+        assert_eq!(hash.encoding(), "listpack");
+        // 超过元素个数
+        assert!(hash.insert(b("c"), b("4"), limits));
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.get(b"a"), Some(&b("2")));
+        assert_eq!(hash.len(), 3);
+        // 删除后也不会转换回来
+        assert!(hash.remove(b"c") && !hash.remove(b"c"));
+        assert_eq!(hash.encoding(), "hashtable");
+
+        // 超过单个值的长度
+        let mut hash = Hash::default();
+        hash.insert(b("a"), b("12345"), limits);
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.iter().count(), 1);
+    }
+}
@@ -1,6 +1,15 @@
+pub mod blocking;
 pub mod cmd;
+pub mod cmd_table;
+pub mod db;
+pub mod error;
 pub mod frame;
+pub mod handler;
+pub mod hash;
 pub mod server;
+pub mod set;
+pub mod stream;
+pub mod zset;
 
 #[derive(Debug)]
 pub struct Config {
@@ -10,6 +19,15 @@ pub struct Config {
     pub role: String,
     pub master_replid: String,
     pub master_repl_offset: usize,
+    // 逻辑数据库的个数
+    pub databases: usize,
+    // 哈希使用紧凑编码的最大字段数和最大字段/值长度
+    pub hash_max_listpack_entries: usize,
+    pub hash_max_listpack_value: usize,
+    // 集合使用整数编码的最大成员数
+    pub set_max_intset_entries: usize,
+    // 消息流每个节点的消息数, 近似裁剪按节点删除
+    pub stream_node_max_entries: usize,
 }
 
 impl Default for Config {
@@ -29,8 +47,21 @@ impl Config {
             master_replid: "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb".to_string(),
             // starts from 0
             master_repl_offset: 0,
+            databases: 16,
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            set_max_intset_entries: 512,
+            stream_node_max_entries: 100,
         }
     }
+
+    pub fn hash_limits(&self) -> hash::ListpackLimits {
+        hash::ListpackLimits {
+            max_entries: self.hash_max_listpack_entries,
+            max_value: self.hash_max_listpack_value,
+        }
+    }
+
     pub fn from_args(mut args: std::env::Args) -> Self {
         let mut config = Config::new();
         while let Some(x) = args.next() {
@@ -41,6 +72,32 @@ impl Config {
                         config.port = port
                     }
                 }
+                "--databases" => {
+                    let databases = args.next().and_then(|s| s.parse().ok());
+                    if let Some(databases) = databases.filter(|n| *n > 0) {
+                        config.databases = databases
+                    }
+                }
+                "--hash-max-listpack-entries" => {
+                    if let Some(n) = args.next().and_then(|s| s.parse().ok()) {
+                        config.hash_max_listpack_entries = n
+                    }
+                }
+                "--hash-max-listpack-value" => {
+                    if let Some(n) = args.next().and_then(|s| s.parse().ok()) {
+                        config.hash_max_listpack_value = n
+                    }
+                }
+                "--set-max-intset-entries" => {
+                    if let Some(n) = args.next().and_then(|s| s.parse().ok()) {
+                        config.set_max_intset_entries = n
+                    }
+                }
+                "--stream-node-max-entries" => {
+                    if let Some(n) = args.next().and_then(|s| s.parse().ok()) {
+                        config.stream_node_max_entries = n
+                    }
+                }
                 "--replicaof" => {
                     config.master_host = match args.next() {
                         Some(host) => host,
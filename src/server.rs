@@ -1,43 +1,133 @@
-use crate::{cmd::Cmd, frame::RESP, Config};
+use crate::{
+    blocking::{self, BlockedClients},
+    cmd::{Cmd, Expiry, SetCond, ZRangeBy},
+    cmd_table::{self, CommandSpec, CMD_BLOCKING},
+    db::{self, ExpirePolicy, Shard, ShardGuards, Value},
+    error::CmdError,
+    frame::{Protocol, RespDecoder, MAX_BULK_LEN, RESP},
+    handler::{CommandHandler, Context, CustomCommand},
+    hash::Hash,
+    set::{self, Set},
+    stream::{Stream, StreamEntry, StreamId, Trim, TrimStrategy},
+    zset::{self, ScoredMembers, ZSet},
+    Config,
+};
 use anyhow::{anyhow, Result};
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use std::{
-    collections::HashMap,
-    sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::{HashMap, VecDeque},
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
     sync::{
-        mpsc::{self, Receiver, Sender},
-        OnceCell, RwLock,
+        mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender},
+        OnceCell, RwLock, RwLockWriteGuard,
     },
 };
 
-pub type ShardedDb = Arc<Vec<RwLock<HashMap<String, (RESP, u128)>>>>;
-pub type CmdSender = Sender<RESP>;
-pub type CmdReceiver = Receiver<RESP>;
-pub type ReplicaSender = Sender<Vec<u8>>;
-pub type ReplicaRecevier = Receiver<Vec<u8>>;
+pub type ShardedDb = Arc<Vec<RwLock<Shard>>>;
+// 同步给replica的(db下标, 命令); 无界通道: 过期产生的DEL需要在持有分片锁时同步发出
+pub type CmdSender = UnboundedSender<(usize, RESP)>;
+pub type CmdReceiver = UnboundedReceiver<(usize, RESP)>;
+pub type ReplicaSender = Sender<Bytes>;
+pub type ReplicaRecevier = Receiver<Bytes>;
 type ShardedT<T> = Arc<RwLock<T>>;
 pub type ShardedTxList = ShardedT<Vec<ReplicaSender>>;
 pub type ShardedConfig = ShardedT<Config>;
 
 static EMPTY_RDB_HEX: &str = "524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2";
 static EMPTY_RDB_BYTES: OnceCell<Bytes> = OnceCell::const_new();
-static RESP_NULL_BYTES: OnceCell<Bytes> = OnceCell::const_new();
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+// 主动过期: 每100ms运行一次, 每次最多占用25ms, 每个分片一批最多删除20个key
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+const ACTIVE_EXPIRE_TIME_LIMIT: Duration = Duration::from_millis(25);
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+const REDIS_VERSION: &str = "7.2.0";
+
+/// 每个客户端连接独有的状态
+#[derive(Debug)]
+pub struct ClientState {
+    pub id: u64,
+    pub protocol: Protocol,
+    pub name: Option<Bytes>,
+    // replica上与master之间的连接
+    pub master_link: bool,
+    // SELECT选择的db
+    pub db: usize,
+}
+
+impl Default for ClientState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientState {
+    pub fn new() -> Self {
+        ClientState {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: Protocol::Resp2,
+            name: None,
+            master_link: false,
+            db: 0,
+        }
+    }
+
+    // 没有配置密码时default用户接受任意密码, 与redis的nopass行为一致
+    fn hello(
+        &mut self,
+        protocol: Option<Protocol>,
+        auth: Option<(Bytes, Bytes)>,
+        setname: Option<Bytes>,
+    ) -> Result<(), CmdError> {
+        if let Some((username, _)) = auth {
+            if username.as_ref() != b"default" {
+                return Err(CmdError::WrongPass);
+            }
+        }
+        if let Some(name) = setname {
+            if name.iter().any(|c| !(b'!'..=b'~').contains(c)) {
+                return Err(CmdError::InvalidClientName);
+            }
+            self.name = Some(name);
+        }
+        if let Some(protocol) = protocol {
+            self.protocol = protocol;
+        }
+        Ok(())
+    }
+}
+
+/// 当前的unix时间戳(毫秒)
+pub fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+}
 
-fn hash(s: &str) -> usize {
+fn hash(s: &[u8]) -> usize {
     const MOD: usize = 1e9 as usize + 7;
     const P: usize = 26;
-    s.chars().fold(0, |acc, x| (acc * P + x as usize) % MOD)
+    s.iter().fold(0, |acc, x| (acc * P + *x as usize) % MOD)
+}
+
+/// key所在分片的下标
+pub fn shard_index(db: &ShardedDb, key: &[u8]) -> usize {
+    hash(key) % db.len()
 }
 
-pub fn new_sharded_db(num_shards: usize) -> ShardedDb {
+pub fn new_sharded_db(num_shards: usize, policy: ExpirePolicy) -> ShardedDb {
     let mut db = Vec::with_capacity(num_shards);
     for _ in 0..num_shards {
-        db.push(RwLock::new(HashMap::new()));
+        db.push(RwLock::new(Shard::new(policy.clone())));
     }
     Arc::new(db)
 }
@@ -76,10 +166,25 @@ pub async fn handle_replica(mut stream: TcpStream, mut rx: ReplicaRecevier) {
 }
 
 pub async fn trans_write_cmd(mut cmd_rx: CmdReceiver, tx_list: ShardedTxList) {
+    let mut buf = BytesMut::with_capacity(4096);
+    // replica当前所在的db, 与redis一样只在db变化时插入SELECT
+    let mut selected = None;
+    let mut num_replicas = 0;
     loop {
-        if let Some(cmd) = cmd_rx.recv().await {
+        if let Some((db, cmd)) = cmd_rx.recv().await {
             let read_tx_list = tx_list.read().await;
-            let cmd = cmd.to_string().into_bytes();
+            // 新加入的replica从db 0开始, 需要重新发送SELECT
+            if read_tx_list.len() != num_replicas {
+                num_replicas = read_tx_list.len();
+                selected = None;
+            }
+            if selected != Some(db) {
+                RESP::new_cmd_array(vec!["SELECT".to_string(), db.to_string()]).encode(&mut buf);
+                selected = Some(db);
+            }
+            // 只编码一次, 各个replica共享同一份Bytes
+            cmd.encode(&mut buf);
+            let cmd = buf.split().freeze();
             for tx in read_tx_list.iter() {
                 tx.send(cmd.clone()).await.unwrap();
             }
@@ -87,281 +192,3108 @@ pub async fn trans_write_cmd(mut cmd_rx: CmdReceiver, tx_list: ShardedTxList) {
     }
 }
 
-pub async fn handle_client(
-    mut stream: TcpStream,
-    db: ShardedDb,
-    config: ShardedConfig,
-    tx_list: ShardedTxList,
-    write_cmd_tx: CmdSender,
-    num_replica: Arc<RwLock<usize>>,
-) {
-    let resp_null_bytes = RESP_NULL_BYTES
-        .get_or_init(|| async { Bytes::from(RESP::Null.to_string()) })
-        .await;
-    // let resp_null_bytes = RESP::Null.to_string();
-    // let resp_null_bytes = resp_null_bytes.as_bytes();
-    let mut buf = [0; 1024];
-    loop {
-        let mut i = 0;
-        let count = stream.read(&mut buf).await.unwrap();
-        if count == 0 {
-            break;
-        }
-        while let Some((j, resp)) = RESP::read_next_resp(&buf[i..]) {
-            i += j;
-            if let Some(cmd) = Cmd::from(&resp) {
-                let mut is_write_cmd = false;
-                let res;
-                let response = match cmd {
-                    Cmd::Ping => "+PONG\r\n".as_bytes(),
-                    Cmd::Echo(s) => {
-                        res = RESP::new_bulk(s).to_string();
-                        res.as_bytes()
-                    }
-                    Cmd::Set(key, value, mut expire_time) => {
-                        let shard = hash(&key) % db.len();
-                        let now_millis = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis();
-                        let mut write_db = db[shard].write().await;
-                        if expire_time != u128::MAX {
-                            expire_time += now_millis
-                        }
-                        write_db.insert(key, (RESP::new_bulk(value), expire_time));
-                        res = RESP::new_simple("OK".to_string()).to_string();
-                        is_write_cmd = true;
-                        res.as_bytes()
-                    }
-                    Cmd::Get(key) => {
-                        let shard = hash(&key) % db.len();
-                        let now_millis = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis();
-                        let mut write_db = db[shard].write().await;
-                        // println!("handle_client get db:{:?}", db);
-                        if let Some((value, expire_time)) = write_db.get(&key) {
-                            if now_millis < *expire_time {
-                                res = value.to_string();
-                                res.as_bytes()
-                            } else {
-                                write_db.remove(&key);
-                                resp_null_bytes
-                            }
-                        } else {
-                            resp_null_bytes
-                        }
-                    }
-                    Cmd::Info(rep) => {
-                        if rep == "replication" {
-                            let read_config = config.read().await;
-                            // println!("info config:{:?}", read_config);
-                            res = RESP::new_bulk(format!(
-                                "role:{}\r\nmaster_replid:{}\r\nmaster_repl_offset:{}",
-                                read_config.role,
-                                read_config.master_replid,
-                                read_config.master_repl_offset
-                            ))
-                            .to_string();
-                            res.as_bytes()
-                        } else {
-                            resp_null_bytes
-                        }
-                    }
-                    Cmd::ReplConf(_, _) => "+OK\r\n".as_bytes(),
-                    Cmd::Psync(repl_id, offset) => {
-                        if repl_id.as_str() == "?" && offset == -1 {
-                            let read_config = config.read().await;
-                            stream
-                                .write_all(
-                                    format!("+FULLRESYNC {} 0\r\n", read_config.master_replid)
-                                        .as_bytes(),
-                                )
-                                .await
-                                .unwrap();
-                            let empty_rdb_bytes = EMPTY_RDB_BYTES
-                                .get_or_init(|| async {
-                                    let empty_rdb_bytes = hex2bytes(EMPTY_RDB_HEX);
-                                    let front = format!("${}\r\n", empty_rdb_bytes.len());
-                                    Bytes::from([front.into_bytes(), empty_rdb_bytes].concat())
-                                })
-                                .await;
-                            stream.write_all(empty_rdb_bytes).await.unwrap();
-                        }
-                        let (tx, rx) = mpsc::channel(32);
-                        tx_list.write().await.push(tx);
-                        let mut num = num_replica.write().await;
-                        *num += 1;
-                        tokio::spawn(handle_replica(stream, rx));
-                        return;
-                    }
-                    Cmd::Wait(_numreplicas, _timeout) => {
-                        res = RESP::Integer(*num_replica.read().await as i64).to_string();
-                        res.as_bytes()
-                    }
-                    _ => resp_null_bytes,
-                };
-                stream.write_all(response).await.unwrap();
-                if is_write_cmd {
-                    write_cmd_tx.send(resp).await.unwrap();
+/// 所有连接共享的服务器状态
+pub struct ServerState {
+    // 按下标访问的逻辑数据库
+    pub dbs: Vec<ShardedDb>,
+    pub config: ShardedConfig,
+    pub tx_list: ShardedTxList,
+    pub write_cmd_tx: CmdSender,
+    pub num_replica: Arc<RwLock<usize>>,
+    pub blocking: BlockedClients,
+    commands: HashMap<&'static str, CustomCommand>,
+}
+
+/// 解析后的命令: 内置命令或者通过`Server::register_command`注册的自定义命令
+pub enum Command {
+    Builtin(Cmd),
+    Custom(Box<dyn CommandHandler>),
+}
+
+impl ServerState {
+    /// 解析命令并返回其元信息, 命令名不区分大小写
+    pub fn parse_command(&self, frame: &RESP) -> Result<(Command, &CommandSpec), CmdError> {
+        if let RESP::Array(arr) = frame {
+            if let Some(RESP::Bulk(name)) = arr.first() {
+                let name = String::from_utf8_lossy(name).to_lowercase();
+                if let Some(custom) = self.commands.get(name.as_str()) {
+                    return Ok((Command::Custom(custom.parse(arr)?), &custom.spec));
                 }
             }
-            if i > count {
-                break;
-            }
         }
+        let cmd = Cmd::from(frame)?;
+        // 内置命令在Cmd::from中已经查过命令表, 只有FULLRESYNC这样的回复没有对应的命令
+        let spec = cmd_table::lookup_frame(frame).unwrap_or(cmd_table::NO_COMMAND);
+        Ok((Command::Builtin(cmd), spec))
+    }
+
+    pub fn custom_specs(&self) -> Vec<&CommandSpec> {
+        self.commands.values().map(|custom| &custom.spec).collect()
     }
 }
 
-async fn handshake(
-    config: &mut Config,
-    stream: &mut TcpStream,
-    buf: &mut [u8],
-) -> Result<(usize, usize)> {
-    // handshake
-    println!("Begin handshake");
-    // 1.1 send "PING" to master
-    stream
-        .write_all(Cmd::new_ping_resp().to_string().as_bytes())
-        .await?;
-    // 1.2 receive "PONG" from master
-    if stream.read(buf).await? == 0 {
-        return Err(anyhow!(
-            "handshake: 1.2 receive \"PONG\" from master failed"
-        ));
+pub struct Server {
+    state: ServerState,
+    cmd_rx: CmdReceiver,
+}
+
+impl Server {
+    pub fn new(config: Config) -> Self {
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        // 过期由master决定, replica只隐藏过期的key并等待master同步DEL
+        let dbs = (0..config.databases)
+            .map(|i| {
+                let policy = if config.role == "slave" {
+                    ExpirePolicy::Hide
+                } else {
+                    ExpirePolicy::Propagate(cmd_tx.clone(), i)
+                };
+                new_sharded_db(32, policy)
+            })
+            .collect();
+        Server {
+            state: ServerState {
+                dbs,
+                config: Arc::new(RwLock::new(config)),
+                tx_list: Arc::new(RwLock::new(Vec::new())),
+                write_cmd_tx: cmd_tx,
+                num_replica: Arc::new(RwLock::new(0)),
+                blocking: BlockedClients::default(),
+                commands: HashMap::new(),
+            },
+            cmd_rx,
+        }
     }
-    assert_eq!(
-        RESP::read_next_resp(buf).unwrap().1,
-        RESP::new_simple("pong".to_string())
-    );
-    println!("handshake: PING finished");
-    // 2.1 send "REPLCONF listening-port <PORT>" to master
-    stream
-        .write_all(
-            format!(
-                "*3\r\n$8\r\nREPLCONF\r\n$14\r\nlistening-port\r\n$4\r\n{}\r\n",
-                config.port
-            )
-            .as_bytes(),
-        )
-        .await?;
-    // 2.2 receive "OK" from master
-    if stream.read(buf).await? == 0 {
-        return Err(anyhow!("handshake: 2.2 receive \"OK\" from master failed"));
+
+    /// 注册自定义命令, 命令名不能与已有命令重复
+    pub fn register_command<T: CommandHandler + 'static>(
+        &mut self,
+        spec: CommandSpec,
+    ) -> Result<()> {
+        if cmd_table::lookup(spec.name).is_some() || self.state.commands.contains_key(spec.name) {
+            return Err(anyhow!("command '{}' already exists", spec.name));
+        }
+        if spec.name.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(anyhow!("command name '{}' must be lowercase", spec.name));
+        }
+        self.state
+            .commands
+            .insert(spec.name, CustomCommand::new::<T>(spec));
+        Ok(())
     }
-    assert_eq!(
-        RESP::read_next_resp(buf).unwrap().1,
-        RESP::new_simple("ok".to_string())
-    );
-    // 2.3 send "REPLCONF capa psync2" to master
-    stream
-        .write_all("*3\r\n$8\r\nREPLCONF\r\n$4\r\ncapa\r\n$6\r\npsync2\r\n".as_bytes())
-        .await?;
-    // 2.4 receive "OK" from master
-    if stream.read(buf).await? == 0 {
-        return Err(anyhow!("handshake: 2.4 receive \"OK\" from master failed"));
+
+    pub async fn run(self) -> Result<()> {
+        let state = Arc::new(self.state);
+        // 只有当前服务器为slave时, 这里能连接到1个master服务器, 在这里接收到的"write"命令只需静默执行
+        tokio::spawn(handle_master(state.clone()));
+
+        let listener = {
+            let read_config = state.config.read().await;
+            TcpListener::bind(format!("127.0.0.1:{}", read_config.port)).await?
+        };
+
+        tokio::spawn(trans_write_cmd(self.cmd_rx, state.tx_list.clone()));
+        if state.config.read().await.role != "slave" {
+            tokio::spawn(active_expire_cycle(state.clone()));
+        }
+        loop {
+            // 若当前服务器为master, 则: 在n个stream中有m个是客户端, n - m个是slave服务器, 需要将客户端发来的"write"命令转发到slave服务器
+            // 若当前服务器为slave, 则: 在此处的stream全都是客户端, 无需特殊处理
+            let (stream, _) = listener.accept().await?;
+            println!("accepted new connection");
+            tokio::spawn(handle_client(stream, state.clone()));
+        }
     }
-    assert_eq!(
-        RESP::read_next_resp(buf).unwrap().1,
-        RESP::new_simple("ok".to_string())
-    );
-    println!("handshake: REPLCONF finished");
-    // 3.1 send "PSYNC ? -1" to master
-    stream
-        .write_all("*3\r\n$5\r\nPSYNC\r\n$1\r\n?\r\n$2\r\n-1\r\n".as_bytes())
-        .await?;
-    // 3.2 receive "+FULLRESYNC <REPL_ID> 0\r\n" and RDB file from master
-    let final_count = stream.read(buf).await?;
-    if final_count == 0 {
-        return Err(anyhow!(
-            "3.2 receive \"+FULLRESYNC <REPL_ID> 0\r\n\" and RDB file from master failed"
-        ));
+}
+
+// 修改过期时间后同步给replica的命令, 统一使用绝对时间
+fn expire_propagation(key: Bytes, deadline: u128, now_millis: u128) -> RESP {
+    let args = if deadline <= now_millis {
+        vec![RESP::new_bulk("DEL"), RESP::Bulk(key)]
+    } else if deadline == u128::MAX {
+        vec![RESP::new_bulk("PERSIST"), RESP::Bulk(key)]
+    } else {
+        vec![
+            RESP::new_bulk("PEXPIREAT"),
+            RESP::Bulk(key),
+            RESP::new_bulk(deadline.to_string()),
+        ]
+    };
+    RESP::Array(args)
+}
+
+// SELECT等命令的db下标
+fn db_index(state: &ServerState, index: i64) -> Result<usize, CmdError> {
+    usize::try_from(index)
+        .ok()
+        .filter(|index| *index < state.dbs.len())
+        .ok_or(CmdError::DbIndexOutOfRange)
+}
+
+// COPY需要写入目标key的条目, 目标已存在且没有REPLACE时返回None; 已过期的目标须事先删除
+fn copy_source(
+    src_shard: &Shard,
+    dst_shard: &Shard,
+    src: &[u8],
+    dst: &[u8],
+    replace: bool,
+    now_millis: u128,
+) -> Option<(Value, u128)> {
+    if !replace && db::get_live(dst_shard, dst, now_millis).is_some() {
+        return None;
     }
-    let (len, res) = RESP::read_next_resp(buf).unwrap();
-    println!("handshake FULLRESYNC resp:{}", res);
-    if let Some(Cmd::FullReSync(repl_id, offset)) = Cmd::from(&res) {
-        config.master_replid = repl_id;
-        config.master_repl_offset = offset;
-        println!("handshake: receive FULLRESYNC and RDB file finished");
-        Ok((len, final_count))
+    db::get_live(src_shard, src, now_millis).cloned()
+}
+
+// GETRANGE/LRANGE/LTRIM的下标换算, 负数从末尾倒数, 返回闭区间; 区间为空时返回None
+fn index_range(len: usize, start: i64, end: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    if len == 0 || (start < 0 && end < 0 && start > end) {
+        return None;
+    }
+    let start = if start < 0 {
+        (len + start).max(0)
     } else {
-        Err(anyhow!(
-            "failed in 2.6 receive \"+FULLRESYNC <REPL_ID> 0\r\n\" from master"
-        ))
+        start
+    };
+    let end = if end < 0 {
+        (len + end).max(0)
+    } else {
+        end.min(len - 1)
+    };
+    (start <= end).then_some((start as usize, end as usize))
+}
+
+// LINDEX/LSET的下标, 负数从末尾倒数
+fn list_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+fn list_push(list: &mut VecDeque<Bytes>, element: Bytes, left: bool) {
+    if left {
+        list.push_front(element);
+    } else {
+        list.push_back(element);
     }
 }
 
-// master slave 握手后master可能连续发送命令使得上一次stream.read后残留了需要同步的命令
-async fn handle_master_loop(
-    mut stream: TcpStream,
-    mut buf: [u8; 1024],
-    mut offset: usize,
-    mut count: usize,
-    db: ShardedDb,
-) -> Result<()> {
-    let mut total_len = 0;
-    loop {
-        while let Some((len, resp)) = RESP::read_next_resp(&buf[offset..]) {
-            offset += len;
-            if let Some(cmd) = Cmd::from(&resp) {
-                // println!("handle_master_loop: receive cmd:{:?}", &cmd);
-                match cmd {
-                    // 这里只处理需要回显的命令
-                    Cmd::Set(key, value, mut expire_time) => {
-                        let shard = hash(&key) % db.len();
-                        let now_millis = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis();
-                        let mut write_db = db[shard].write().await;
-                        if expire_time != u128::MAX {
-                            expire_time += now_millis
-                        }
-                        write_db.insert(key, (RESP::new_bulk(value), expire_time));
-                        // println!("handle_master db:{:?}", &write_db);
-                    }
-                    Cmd::ReplConf(r#type, arg) => {
-                        if &r#type == "getack" && &arg == "*" {
-                            let res = RESP::new_cmd_array(vec![
-                                "REPLCONF".to_string(),
-                                "ACK".to_string(),
-                                total_len.to_string(),
-                            ]);
-                            stream.write_all(res.to_string().as_bytes()).await?;
-                        }
-                    }
-                    _ => (),
-                };
-                // 即使不回显的命令也需要记录其长度
-                total_len += len;
-            }
-            if offset > count {
-                break;
+fn list_pop(list: &mut VecDeque<Bytes>, left: bool) -> Option<Bytes> {
+    if left {
+        list.pop_front()
+    } else {
+        list.pop_back()
+    }
+}
+
+type WriteGuards<'a> = ShardGuards<'a, RwLockWriteGuard<'a, Shard>>;
+
+// 从keys中第一个非空列表的一端弹出最多count个元素, 所有key都不存在时返回None
+fn pop_first_list(
+    guards: &mut WriteGuards<'_>,
+    keys: &[Bytes],
+    left: bool,
+    count: usize,
+    now_millis: u128,
+) -> Result<Option<(Bytes, Vec<Bytes>)>, CmdError> {
+    for key in keys {
+        let shard = guards.shard_mut(key);
+        if let Some(value) = db::get_live_mut(shard, key, now_millis) {
+            let list = value.as_list_mut()?;
+            let n = count.min(list.len());
+            let popped = (0..n).filter_map(|_| list_pop(list, left)).collect();
+            if list.is_empty() {
+                shard.remove(key);
             }
+            return Ok(Some((key.clone(), popped)));
         }
-        count = stream.read(&mut buf).await.unwrap();
-        if count == 0 {
-            break Ok(());
+    }
+    Ok(None)
+}
+
+// 阻塞弹出在replica上对应的LPOP/RPOP
+fn pop_propagation(key: Bytes, left: bool, count: Option<usize>) -> RESP {
+    let mut args = vec![
+        RESP::new_bulk(if left { "LPOP" } else { "RPOP" }),
+        RESP::Bulk(key),
+    ];
+    if let Some(count) = count {
+        args.push(RESP::new_bulk(count.to_string()));
+    }
+    RESP::Array(args)
+}
+
+// LMOVE/BLMOVE, 源列表不存在时返回None
+fn list_move(
+    guards: &mut WriteGuards<'_>,
+    src: &Bytes,
+    dst: &Bytes,
+    from_left: bool,
+    to_left: bool,
+    now_millis: u128,
+) -> Result<Option<Bytes>, CmdError> {
+    match db::get_live(guards.shard(src), src, now_millis) {
+        Some((value, _)) => value.as_list()?,
+        None => return Ok(None),
+    };
+    // 目标key类型不对时不弹出
+    if let Some((value, _)) = db::get_live(guards.shard(dst), dst, now_millis) {
+        value.as_list()?;
+    }
+    let src_list = guards.shard_mut(src).get_mut(src).unwrap().as_list_mut()?;
+    let element = list_pop(src_list, from_left).unwrap();
+    let src_empty = src_list.is_empty();
+    match db::get_live_mut(guards.shard_mut(dst), dst, now_millis) {
+        Some(value) => list_push(value.as_list_mut()?, element.clone(), to_left),
+        None => {
+            let list = VecDeque::from([element.clone()]);
+            guards
+                .shard_mut(dst)
+                .insert(dst.clone(), (Value::List(list), u128::MAX));
         }
-        offset = 0;
     }
+    // 源和目标相同时元素已经放回, 列表不会为空
+    if src_empty && src != dst {
+        guards.shard_mut(src).remove(src);
+    }
+    Ok(Some(element))
 }
 
-pub async fn handle_master(config: ShardedConfig, db: ShardedDb) -> Result<()> {
-    let mut write_config = config.write().await;
-    if write_config.role.as_str() == "slave" {
-        let mut stream = TcpStream::connect(format!(
-            "{}:{}",
-            write_config.master_host, write_config.master_port
-        ))
-        .await?;
-        let mut buf = [0; 1024];
-        let (offset, count) = handshake(&mut write_config, &mut stream, &mut buf).await?;
-        println!("slave: handshake has finished, listening from master begins");
-        tokio::spawn(handle_master_loop(stream, buf, offset, count, db));
-    } else {
-        println!("master: no need for handshaking");
+// 读取多个集合, 不存在的key为None
+fn live_sets<'a, G: Deref<Target = Shard>>(
+    guards: &'a ShardGuards<'_, G>,
+    keys: &[Bytes],
+    now_millis: u128,
+) -> Result<Vec<Option<&'a Set>>, CmdError> {
+    keys.iter()
+        .map(|key| {
+            db::get_live(guards.shard(key), key, now_millis)
+                .map(|(value, _)| value.as_set())
+                .transpose()
+        })
+        .collect()
+}
+
+// 从keys中第一个非空有序集合弹出最多count个分数最低(或最高)的成员, 所有key都不存在时返回None
+fn pop_first_zset(
+    guards: &mut WriteGuards<'_>,
+    keys: &[Bytes],
+    max: bool,
+    count: usize,
+    now_millis: u128,
+) -> Result<Option<(Bytes, ScoredMembers)>, CmdError> {
+    for key in keys {
+        let shard = guards.shard_mut(key);
+        if let Some(value) = db::get_live_mut(shard, key, now_millis) {
+            let zset = value.as_zset_mut()?;
+            let popped = zset.pop(max, count);
+            if zset.is_empty() {
+                shard.remove(key);
+            }
+            return Ok(Some((key.clone(), popped)));
+        }
+    }
+    Ok(None)
+}
+
+// 阻塞弹出在replica上对应的ZPOPMIN/ZPOPMAX
+fn zpop_propagation(key: Bytes, max: bool, count: Option<usize>) -> RESP {
+    let mut args = vec![
+        RESP::new_bulk(if max { "ZPOPMAX" } else { "ZPOPMIN" }),
+        RESP::Bulk(key),
+    ];
+    if let Some(count) = count {
+        args.push(RESP::new_bulk(count.to_string()));
+    }
+    RESP::Array(args)
+}
+
+// 裁剪选项的传播形式; 近似裁剪的结果依赖节点大小, 改写为裁剪后长度的精确MAXLEN, 保证副本一致
+fn trim_propagation(trim: &Trim, len: usize) -> [RESP; 3] {
+    let (strategy, threshold) = match trim.strategy {
+        _ if trim.approx => ("MAXLEN", len.to_string()),
+        TrimStrategy::MaxLen(maxlen) => ("MAXLEN", maxlen.to_string()),
+        TrimStrategy::MinId(minid) => ("MINID", minid.to_string()),
+    };
+    [
+        RESP::new_bulk(strategy),
+        RESP::new_bulk("="),
+        RESP::new_bulk(threshold),
+    ]
+}
+
+// 每条消息回复为[id, [field, value, ...]]
+fn stream_reply(entries: Vec<(StreamId, &StreamEntry)>) -> RESP {
+    RESP::Array(
+        entries
+            .into_iter()
+            .map(|(id, entry)| {
+                let fields = entry
+                    .iter()
+                    .flat_map(|(field, value)| {
+                        [RESP::Bulk(field.clone()), RESP::Bulk(value.clone())]
+                    })
+                    .collect();
+                RESP::Array(vec![RESP::new_bulk(id.to_string()), RESP::Array(fields)])
+            })
+            .collect(),
+    )
+}
+
+// ZUNIONSTORE等的输入, 集合视为分数都为1的有序集合; 不存在的key为None
+fn live_zset_sources<G: Deref<Target = Shard>>(
+    guards: &ShardGuards<'_, G>,
+    keys: &[Bytes],
+    now_millis: u128,
+) -> Result<Vec<Option<ScoredMembers>>, CmdError> {
+    keys.iter()
+        .map(
+            |key| match db::get_live(guards.shard(key), key, now_millis) {
+                Some((Value::ZSet(zset), _)) => Ok(Some(
+                    zset.iter()
+                        .map(|(member, score)| (member.clone(), score))
+                        .collect(),
+                )),
+                Some((Value::Set(set), _)) => Ok(Some(
+                    set.members()
+                        .into_iter()
+                        .map(|member| (member, 1.0))
+                        .collect(),
+                )),
+                Some(_) => Err(CmdError::WrongType),
+                None => Ok(None),
+            },
+        )
+        .collect()
+}
+
+// 有序集合成员的回复, WITHSCORES时RESP2中成员和分数交替排列, RESP3中每对是一个数组
+fn zset_reply(items: Vec<(Bytes, f64)>, withscores: bool, protocol: Protocol) -> RESP {
+    let mut reply = Vec::with_capacity(items.len() * 2);
+    for (member, score) in items {
+        match (withscores, protocol) {
+            (false, _) => reply.push(RESP::Bulk(member)),
+            (true, Protocol::Resp3) => {
+                reply.push(RESP::Array(vec![RESP::Bulk(member), RESP::Double(score)]))
+            }
+            (true, Protocol::Resp2) => reply.extend([RESP::Bulk(member), RESP::Double(score)]),
+        }
+    }
+    RESP::Array(reply)
+}
+
+async fn execute(cmd: Cmd, ctx: &mut Context<'_>) -> Result<RESP, CmdError> {
+    let state = ctx.state();
+    let db = &state.dbs[ctx.client.db];
+    let response = match cmd {
+        Cmd::Ping => RESP::new_simple("PONG".to_string()),
+        Cmd::Echo(s) => RESP::new_bulk(s),
+        Cmd::Set(name, key, value, opts) => {
+            let now_millis = ctx.now_millis();
+            let deadline = match opts.expire {
+                Some(expire) => expire.deadline(now_millis, name)?,
+                None => None,
+            };
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            // NX/XX以master上的判断为准, 已过期的key先删除并同步DEL
+            write_db.expire_if_needed(&key, now_millis);
+            let old = db::get_live(&write_db, &key, now_millis);
+            let old_expire = old.map(|(_, expire_time)| *expire_time);
+            // GET要求原值是字符串, 类型不对时不写入
+            let old_value = match old {
+                Some((value, _)) if opts.get => Some(value.to_bytes()?),
+                _ => None,
+            };
+            let allowed = match opts.cond {
+                SetCond::Always => true,
+                SetCond::Nx => old_expire.is_none(),
+                SetCond::Xx => old_expire.is_some(),
+            };
+            if allowed {
+                let expire_time = match opts.expire {
+                    Some(Expiry::Keep) => old_expire.unwrap_or(u128::MAX),
+                    _ => deadline.unwrap_or(u128::MAX),
+                };
+                write_db.insert(key.clone(), (Value::from_bytes(value.clone()), expire_time));
+                // 条件已在master上判定, replica只需无条件写入; 相对时间改为绝对时间, 保证replica上的结果一致
+                let mut args = vec![RESP::new_bulk("SET"), RESP::Bulk(key), RESP::Bulk(value)];
+                match opts.expire {
+                    Some(Expiry::Keep) => args.push(RESP::new_bulk("KEEPTTL")),
+                    Some(_) => {
+                        args.push(RESP::new_bulk("PXAT"));
+                        args.push(RESP::new_bulk(expire_time.to_string()));
+                    }
+                    None => {}
+                }
+                ctx.rewrite_propagation(RESP::Array(args));
+            } else {
+                ctx.prevent_propagation();
+            }
+            match (opts.get, old_value) {
+                (true, Some(old_value)) => RESP::Bulk(old_value),
+                (true, None) => RESP::Null,
+                (false, _) if allowed => RESP::new_simple("OK".to_string()),
+                (false, _) => RESP::Null,
+            }
+        }
+        Cmd::Get(key) => {
+            let now_millis = ctx.now_millis();
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            // println!("handle_client get db:{:?}", db);
+            match db::get_live_mut(&mut write_db, &key, now_millis) {
+                Some(value) => RESP::Bulk(value.to_bytes()?),
+                None => RESP::Null,
+            }
+        }
+        Cmd::MSet(pairs, nx) => {
+            let keys: Vec<_> = pairs.iter().map(|(key, _)| key.clone()).collect();
+            let mut guards = db::write_shards(db, &keys).await;
+            let now_millis = ctx.now_millis();
+            for key in &keys {
+                guards.shard_mut(key).expire_if_needed(key, now_millis);
+            }
+            if nx
+                && keys
+                    .iter()
+                    .any(|key| db::get_live(guards.shard(key), key, now_millis).is_some())
+            {
+                ctx.prevent_propagation();
+                RESP::Integer(0)
+            } else {
+                for (key, value) in pairs {
+                    guards
+                        .shard_mut(&key)
+                        .insert(key, (Value::from_bytes(value), u128::MAX));
+                }
+                if nx {
+                    RESP::Integer(1)
+                } else {
+                    RESP::new_simple("OK".to_string())
+                }
+            }
+        }
+        Cmd::MGet(keys) => {
+            let guards = db::read_shards(db, &keys).await;
+            let now_millis = ctx.now_millis();
+            RESP::Array(
+                keys.iter()
+                    .map(
+                        |key| match db::get_live(guards.shard(key), key, now_millis) {
+                            // 不是字符串的key返回nil
+                            Some((value, _)) => value.to_bytes().map_or(RESP::Null, RESP::Bulk),
+                            None => RESP::Null,
+                        },
+                    )
+                    .collect(),
+            )
+        }
+        Cmd::SetNx(key, value) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            if db::get_live_mut(&mut write_db, &key, ctx.now_millis()).is_some() {
+                ctx.prevent_propagation();
+                RESP::Integer(0)
+            } else {
+                write_db.insert(key, (Value::from_bytes(value), u128::MAX));
+                RESP::Integer(1)
+            }
+        }
+        Cmd::Append(key, value) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            // 追加不改变过期时间
+            match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(old) => {
+                    let old_bytes = old.to_bytes()?;
+                    let len = old_bytes.len() + value.len();
+                    if len > MAX_BULK_LEN {
+                        return Err(CmdError::StringTooLong);
+                    }
+                    let mut buf = BytesMut::with_capacity(len);
+                    buf.extend_from_slice(&old_bytes);
+                    buf.extend_from_slice(&value);
+                    *old = Value::Str(buf.freeze());
+                    RESP::Integer(len as i64)
+                }
+                None => {
+                    let len = value.len();
+                    write_db.insert(key, (Value::from_bytes(value), u128::MAX));
+                    RESP::Integer(len as i64)
+                }
+            }
+        }
+        Cmd::Strlen(key) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let len = db::get_live(&read_db, &key, ctx.now_millis())
+                .map_or(Ok(0), |(value, _)| value.strlen())?;
+            RESP::Integer(len as i64)
+        }
+        Cmd::GetRange(key, start, end) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => {
+                    let value = value.to_bytes()?;
+                    match index_range(value.len(), start, end) {
+                        Some((start, end)) => RESP::Bulk(value.slice(start..=end)),
+                        None => RESP::new_bulk(""),
+                    }
+                }
+                None => RESP::new_bulk(""),
+            }
+        }
+        Cmd::SetRange(key, offset, value) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let entry = db::get_live_mut(&mut write_db, &key, ctx.now_millis());
+            let old = entry
+                .as_ref()
+                .map_or(Ok(Bytes::new()), |value| value.to_bytes())?;
+            if value.is_empty() {
+                // 不修改任何内容, 也不会创建key
+                ctx.prevent_propagation();
+                RESP::Integer(old.len() as i64)
+            } else {
+                let end = offset + value.len();
+                if end > MAX_BULK_LEN {
+                    return Err(CmdError::StringTooLong);
+                }
+                let mut buf = BytesMut::from(&old[..]);
+                if buf.len() < end {
+                    buf.resize(end, 0);
+                }
+                buf[offset..end].copy_from_slice(&value);
+                let len = buf.len();
+                match entry {
+                    Some(old) => *old = Value::Str(buf.freeze()),
+                    None => {
+                        write_db.insert(key, (Value::Str(buf.freeze()), u128::MAX));
+                    }
+                }
+                RESP::Integer(len as i64)
+            }
+        }
+        Cmd::GetDel(key) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let now_millis = ctx.now_millis();
+            // 类型不对时不删除
+            let value = db::get_live(&write_db, &key, now_millis)
+                .map(|(value, _)| value.to_bytes())
+                .transpose()?;
+            match value {
+                Some(value) => {
+                    write_db.remove(&key);
+                    RESP::Bulk(value)
+                }
+                None => {
+                    ctx.prevent_propagation();
+                    RESP::Null
+                }
+            }
+        }
+        Cmd::GetEx(key, expire) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let now_millis = ctx.now_millis();
+            let deadline = match expire {
+                Some(expire) => expire.deadline(now_millis, "getex")?,
+                None => None,
+            };
+            match db::get_live_mut(&mut write_db, &key, now_millis) {
+                Some(entry) => {
+                    let value = entry.to_bytes()?;
+                    match deadline {
+                        None => ctx.prevent_propagation(),
+                        Some(deadline) => {
+                            if deadline <= now_millis {
+                                write_db.remove(&key);
+                            } else {
+                                write_db.set_expire(&key, deadline);
+                            }
+                            ctx.rewrite_propagation(expire_propagation(key, deadline, now_millis));
+                        }
+                    }
+                    RESP::Bulk(value)
+                }
+                None => {
+                    ctx.prevent_propagation();
+                    RESP::Null
+                }
+            }
+        }
+        Cmd::IncrBy(key, incr) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            // 不存在的key视为0, 保留原有的过期时间
+            let entry = db::get_live_mut(&mut write_db, &key, ctx.now_millis());
+            let old = entry.as_ref().map_or(Ok(0), |value| value.as_int())?;
+            let new = old.checked_add(incr).ok_or(CmdError::Overflow)?;
+            match entry {
+                Some(entry) => *entry = Value::Int(new),
+                None => {
+                    write_db.insert(key, (Value::Int(new), u128::MAX));
+                }
+            }
+            RESP::Integer(new)
+        }
+        Cmd::IncrByFloat(key, incr) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let entry = db::get_live_mut(&mut write_db, &key, ctx.now_millis());
+            let old = entry.as_ref().map_or(Ok(0.0), |value| value.as_float())?;
+            let new = old + incr;
+            if !new.is_finite() {
+                return Err(CmdError::NanOrInfinity);
+            }
+            let new = Bytes::from(db::format_float(new));
+            match entry {
+                Some(entry) => *entry = Value::from_bytes(new.clone()),
+                None => {
+                    write_db.insert(key.clone(), (Value::from_bytes(new.clone()), u128::MAX));
+                }
+            }
+            // 浮点运算在不同机器上可能有差异, 以SET最终结果的方式同步给replica
+            ctx.rewrite_propagation(RESP::Array(vec![
+                RESP::new_bulk("SET"),
+                RESP::Bulk(key),
+                RESP::Bulk(new.clone()),
+                RESP::new_bulk("KEEPTTL"),
+            ]));
+            RESP::Bulk(new)
+        }
+        Cmd::Del(keys) => {
+            let mut guards = db::write_shards(db, &keys).await;
+            let now_millis = ctx.now_millis();
+            let deleted = keys
+                .iter()
+                .filter(|key| guards.shard_mut(key).remove_live(key, now_millis).is_some())
+                .count();
+            if deleted == 0 {
+                ctx.prevent_propagation();
+            }
+            RESP::Integer(deleted as i64)
+        }
+        Cmd::Exists(keys) => {
+            let guards = db::read_shards(db, &keys).await;
+            let now_millis = ctx.now_millis();
+            // 重复的key会被重复计数
+            let count = keys
+                .iter()
+                .filter(|key| db::get_live(guards.shard(key), key, now_millis).is_some())
+                .count();
+            RESP::Integer(count as i64)
+        }
+        Cmd::Type(key) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => RESP::new_simple(value.type_name().to_string()),
+                None => RESP::new_simple("none".to_string()),
+            }
+        }
+        Cmd::Rename(src, dst, nx) => {
+            // 同时持有两个分片的写锁, 其他连接看不到中间状态
+            let mut guards = db::write_shards(db, &[src.clone(), dst.clone()]).await;
+            let now_millis = ctx.now_millis();
+            if db::get_live_mut(guards.shard_mut(&src), &src, now_millis).is_none() {
+                return Err(CmdError::NoSuchKey);
+            }
+            guards.shard_mut(&dst).expire_if_needed(&dst, now_millis);
+            if src == dst || (nx && db::get_live(guards.shard(&dst), &dst, now_millis).is_some()) {
+                if nx {
+                    ctx.prevent_propagation();
+                    RESP::Integer(0)
+                } else {
+                    RESP::new_simple("OK".to_string())
+                }
+            } else {
+                // 过期时间随key一起转移
+                let entry = guards.shard_mut(&src).remove(&src).unwrap();
+                guards.shard_mut(&dst).insert(dst.clone(), entry);
+                ctx.signal_key_ready(ctx.client.db, dst);
+                if nx {
+                    RESP::Integer(1)
+                } else {
+                    RESP::new_simple("OK".to_string())
+                }
+            }
+        }
+        Cmd::Copy(src, dst, dst_db, replace) => {
+            let (src_db, dst_db) = match dst_db {
+                Some(index) => (ctx.client.db, db_index(state, index)?),
+                None => (ctx.client.db, ctx.client.db),
+            };
+            if src_db == dst_db && src == dst {
+                return Err(CmdError::SameObject);
+            }
+            let now_millis = ctx.now_millis();
+            let copied = if src_db == dst_db {
+                let mut guards = db::write_shards(db, &[src.clone(), dst.clone()]).await;
+                guards.shard_mut(&dst).expire_if_needed(&dst, now_millis);
+                let entry = copy_source(
+                    guards.shard(&src),
+                    guards.shard(&dst),
+                    &src,
+                    &dst,
+                    replace,
+                    now_millis,
+                );
+                entry
+                    .map(|entry| guards.shard_mut(&dst).insert(dst.clone(), entry))
+                    .is_some()
+            } else {
+                let target = &state.dbs[dst_db];
+                let (src_shard, mut dst_shard) = db::write_pair(
+                    (&db[shard_index(db, &src)], src_db),
+                    (&target[shard_index(target, &dst)], dst_db),
+                )
+                .await;
+                dst_shard.expire_if_needed(&dst, now_millis);
+                let entry = copy_source(&src_shard, &dst_shard, &src, &dst, replace, now_millis);
+                entry
+                    .map(|entry| dst_shard.insert(dst.clone(), entry))
+                    .is_some()
+            };
+            if copied {
+                ctx.signal_key_ready(dst_db, dst);
+            } else {
+                ctx.prevent_propagation();
+            }
+            RESP::Integer(copied as i64)
+        }
+        Cmd::Select(index) => {
+            ctx.client.db = db_index(state, index)?;
+            RESP::new_simple("OK".to_string())
+        }
+        Cmd::Move(key, index) => {
+            let (src_db, dst_db) = (ctx.client.db, db_index(state, index)?);
+            if src_db == dst_db {
+                return Err(CmdError::SameObject);
+            }
+            let target = &state.dbs[dst_db];
+            let (mut src_shard, mut dst_shard) = db::write_pair(
+                (&db[shard_index(db, &key)], src_db),
+                (&target[shard_index(target, &key)], dst_db),
+            )
+            .await;
+            let now_millis = ctx.now_millis();
+            dst_shard.expire_if_needed(&key, now_millis);
+            // 目标db中已存在同名key时不移动
+            if db::get_live_mut(&mut src_shard, &key, now_millis).is_some()
+                && db::get_live(&dst_shard, &key, now_millis).is_none()
+            {
+                let entry = src_shard.remove(&key).unwrap();
+                dst_shard.insert(key.clone(), entry);
+                ctx.signal_key_ready(dst_db, key);
+                RESP::Integer(1)
+            } else {
+                ctx.prevent_propagation();
+                RESP::Integer(0)
+            }
+        }
+        Cmd::SwapDb(first, second) => {
+            let (first, second) = (db_index(state, first)?, db_index(state, second)?);
+            if first != second {
+                // 先锁下标小的db的全部分片, 与其他跨db命令的加锁顺序一致
+                let (lo, hi) = (first.min(second), first.max(second));
+                let mut lo_guards = Vec::new();
+                for shard in state.dbs[lo].iter() {
+                    lo_guards.push(shard.write().await);
+                }
+                let mut hi_guards = Vec::new();
+                for shard in state.dbs[hi].iter() {
+                    hi_guards.push(shard.write().await);
+                }
+                for (lo_shard, hi_shard) in lo_guards.iter_mut().zip(hi_guards.iter_mut()) {
+                    lo_shard.swap_data(hi_shard);
+                }
+                // 阻塞在这两个db上的客户端需要重新检查
+                for db in [first, second] {
+                    for key in state.blocking.blocked_keys(db) {
+                        ctx.signal_key_ready(db, key);
+                    }
+                }
+            }
+            RESP::new_simple("OK".to_string())
+        }
+        Cmd::Flush(all, lazy) => {
+            let dbs = if all {
+                &state.dbs[..]
+            } else {
+                std::slice::from_ref(db)
+            };
+            // 先按(db下标, 分片下标)的顺序锁住全部分片再清空, 与SWAPDB的加锁顺序一致,
+            // 其他连接看不到只清空了一部分的db
+            let mut guards = Vec::new();
+            for shard in dbs.iter().flat_map(|db| db.iter()) {
+                guards.push(shard.write().await);
+            }
+            let old: Vec<_> = guards.iter_mut().map(|shard| shard.clear()).collect();
+            drop(guards);
+            // ASYNC: 在后台线程释放旧数据
+            if lazy {
+                tokio::task::spawn_blocking(move || drop(old));
+            }
+            RESP::new_simple("OK".to_string())
+        }
+        Cmd::DbSize => {
+            let mut size = 0;
+            for shard in db.iter() {
+                size += shard.read().await.len();
+            }
+            RESP::Integer(size as i64)
+        }
+        Cmd::Expire(name, key, time, cond) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let now_millis = ctx.now_millis();
+            let deadline = time.deadline(now_millis, name)?;
+            let current = db::get_live(&write_db, &key, now_millis).map(|(_, t)| *t);
+            match current {
+                Some(current) if cond.allows(current, deadline) => {
+                    // 已经过去的时间点直接删除key
+                    if deadline <= now_millis {
+                        write_db.remove(&key);
+                    } else {
+                        write_db.set_expire(&key, deadline);
+                    }
+                    ctx.rewrite_propagation(expire_propagation(key, deadline, now_millis));
+                    RESP::Integer(1)
+                }
+                _ => {
+                    ctx.prevent_propagation();
+                    RESP::Integer(0)
+                }
+            }
+        }
+        Cmd::Ttl(key, millis, absolute) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let now_millis = ctx.now_millis();
+            match db::get_live(&read_db, &key, now_millis) {
+                Some((_, u128::MAX)) => RESP::Integer(-1),
+                Some((_, expire_time)) => {
+                    let ttl = if absolute {
+                        *expire_time
+                    } else {
+                        expire_time - now_millis
+                    };
+                    // 换算为秒时四舍五入
+                    let ttl = if millis { ttl } else { (ttl + 500) / 1000 };
+                    RESP::Integer(ttl as i64)
+                }
+                None => RESP::Integer(-2),
+            }
+        }
+        Cmd::Persist(key) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            match db::get_live(&write_db, &key, ctx.now_millis()) {
+                Some((_, expire_time)) if *expire_time != u128::MAX => {
+                    write_db.set_expire(&key, u128::MAX);
+                    RESP::Integer(1)
+                }
+                _ => {
+                    ctx.prevent_propagation();
+                    RESP::Integer(0)
+                }
+            }
+        }
+        Cmd::Keys(pattern) => {
+            let now_millis = ctx.now_millis();
+            let mut keys = Vec::new();
+            for shard in db.iter() {
+                let read_db = shard.read().await;
+                keys.extend(
+                    read_db
+                        .iter()
+                        .filter(|(key, (_, expire_time))| {
+                            now_millis < *expire_time && db::glob_match(&pattern, key)
+                        })
+                        .map(|(key, _)| RESP::Bulk(key.clone())),
+                );
+            }
+            RESP::Array(keys)
+        }
+        Cmd::Scan(cursor, opts) => {
+            let (next, keys) = db::scan(db, cursor, opts.count, ctx.now_millis()).await;
+            // 与redis一样先按COUNT取出再过滤, 因此可能返回空的一批
+            let keys = keys
+                .into_iter()
+                .filter(|(key, type_name)| {
+                    opts.pattern
+                        .iter()
+                        .all(|pattern| db::glob_match(pattern, key))
+                        && opts.type_name.iter().all(|t| t == type_name)
+                })
+                .map(|(key, _)| RESP::Bulk(key))
+                .collect();
+            RESP::Array(vec![RESP::new_bulk(next.to_string()), RESP::Array(keys)])
+        }
+        Cmd::Push(key, elements, left, xx) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let len = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => {
+                    let list = value.as_list_mut()?;
+                    for element in elements {
+                        list_push(list, element, left);
+                    }
+                    list.len()
+                }
+                None if xx => {
+                    ctx.prevent_propagation();
+                    0
+                }
+                None => {
+                    let mut list = VecDeque::with_capacity(elements.len());
+                    for element in elements {
+                        list_push(&mut list, element, left);
+                    }
+                    let len = list.len();
+                    write_db.insert(key.clone(), (Value::List(list), u128::MAX));
+                    len
+                }
+            };
+            if len > 0 {
+                ctx.signal_key_ready(ctx.client.db, key);
+            }
+            RESP::Integer(len as i64)
+        }
+        Cmd::Pop(key, count, left) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let list = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_list_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(if count.is_some() {
+                        RESP::NullArray
+                    } else {
+                        RESP::Null
+                    });
+                }
+            };
+            let n = count.unwrap_or(1).min(list.len());
+            let popped: Vec<_> = (0..n).filter_map(|_| list_pop(list, left)).collect();
+            if list.is_empty() {
+                write_db.remove(&key);
+            }
+            if popped.is_empty() {
+                ctx.prevent_propagation();
+            }
+            match count {
+                Some(_) => RESP::Array(popped.into_iter().map(RESP::Bulk).collect()),
+                // 列表不会为空, 至少弹出了一个元素
+                None => RESP::Bulk(popped.into_iter().next().unwrap()),
+            }
+        }
+        Cmd::LLen(key) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let len = db::get_live(&read_db, &key, ctx.now_millis())
+                .map_or(Ok(0), |(value, _)| value.as_list().map(|list| list.len()))?;
+            RESP::Integer(len as i64)
+        }
+        Cmd::LRange(key, start, end) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let list = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_list()?,
+                None => return Ok(RESP::Array(vec![])),
+            };
+            match index_range(list.len(), start, end) {
+                Some((start, end)) => {
+                    RESP::Array(list.range(start..=end).cloned().map(RESP::Bulk).collect())
+                }
+                None => RESP::Array(vec![]),
+            }
+        }
+        Cmd::LIndex(key, index) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let list = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_list()?,
+                None => return Ok(RESP::Null),
+            };
+            match list_index(list.len(), index) {
+                Some(index) => RESP::Bulk(list[index].clone()),
+                None => RESP::Null,
+            }
+        }
+        Cmd::LSet(key, index, element) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let list = db::get_live_mut(&mut write_db, &key, ctx.now_millis())
+                .ok_or(CmdError::NoSuchKey)?
+                .as_list_mut()?;
+            let index = list_index(list.len(), index).ok_or(CmdError::IndexOutOfRange)?;
+            list[index] = element;
+            RESP::new_simple("OK".to_string())
+        }
+        Cmd::LRem(key, count, element) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let list = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_list_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+            };
+            // count为负数时从尾部开始删除, 为0时删除全部
+            let limit = match count {
+                0 => usize::MAX,
+                count => count.unsigned_abs() as usize,
+            };
+            let len = list.len();
+            let mut keep = vec![true; len];
+            let mut removed = 0;
+            for i in 0..len {
+                let i = if count < 0 { len - 1 - i } else { i };
+                if removed == limit {
+                    break;
+                }
+                if list[i] == element {
+                    keep[i] = false;
+                    removed += 1;
+                }
+            }
+            let mut keep = keep.into_iter();
+            list.retain(|_| keep.next().unwrap());
+            if list.is_empty() {
+                write_db.remove(&key);
+            }
+            if removed == 0 {
+                ctx.prevent_propagation();
+            }
+            RESP::Integer(removed as i64)
+        }
+        Cmd::LTrim(key, start, end) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => {
+                    let list = value.as_list_mut()?;
+                    match index_range(list.len(), start, end) {
+                        Some((start, end)) => {
+                            list.truncate(end + 1);
+                            list.drain(..start);
+                        }
+                        None => list.clear(),
+                    }
+                    if list.is_empty() {
+                        write_db.remove(&key);
+                    }
+                }
+                None => ctx.prevent_propagation(),
+            }
+            RESP::new_simple("OK".to_string())
+        }
+        Cmd::LInsert(key, before, pivot, element) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let list = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_list_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+            };
+            match list.iter().position(|e| *e == pivot) {
+                Some(index) => {
+                    list.insert(if before { index } else { index + 1 }, element);
+                    RESP::Integer(list.len() as i64)
+                }
+                None => {
+                    ctx.prevent_propagation();
+                    RESP::Integer(-1)
+                }
+            }
+        }
+        Cmd::LPos(key, element, opts) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let list = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_list()?,
+                None if opts.count.is_some() => return Ok(RESP::Array(vec![])),
+                None => return Ok(RESP::Null),
+            };
+            let len = list.len();
+            let maxlen = match opts.maxlen {
+                0 => len,
+                maxlen => maxlen.min(len),
+            };
+            // RANK为负数时从尾部开始查找, 跳过前|rank|-1个匹配
+            let position = |i: usize| if opts.rank > 0 { i } else { len - 1 - i };
+            let limit = match opts.count {
+                Some(0) => usize::MAX,
+                Some(count) => count,
+                None => 1,
+            };
+            let found: Vec<_> = (0..maxlen)
+                .map(position)
+                .filter(|i| list[*i] == element)
+                .skip((opts.rank.unsigned_abs() - 1) as usize)
+                .take(limit)
+                .map(|i| RESP::Integer(i as i64))
+                .collect();
+            match opts.count {
+                Some(_) => RESP::Array(found),
+                None => found.into_iter().next().unwrap_or(RESP::Null),
+            }
+        }
+        Cmd::LMove(src, dst, from_left, to_left) => {
+            let mut guards = db::write_shards(db, &[src.clone(), dst.clone()]).await;
+            match list_move(
+                &mut guards,
+                &src,
+                &dst,
+                from_left,
+                to_left,
+                ctx.now_millis(),
+            )? {
+                Some(element) => {
+                    ctx.signal_key_ready(ctx.client.db, dst);
+                    RESP::Bulk(element)
+                }
+                None => {
+                    ctx.prevent_propagation();
+                    RESP::Null
+                }
+            }
+        }
+        Cmd::BPop(keys, left, timeout) => {
+            let deadline = blocking::deadline(timeout);
+            let mut blocked = None;
+            loop {
+                let mut guards = db::write_shards(db, &keys).await;
+                if let Some((key, mut popped)) =
+                    pop_first_list(&mut guards, &keys, left, 1, ctx.now_millis())?
+                {
+                    // replica上执行对应的非阻塞命令
+                    ctx.rewrite_propagation(pop_propagation(key.clone(), left, None));
+                    break RESP::Array(vec![RESP::Bulk(key), RESP::Bulk(popped.remove(0))]);
+                }
+                // 持有分片锁时排队, 不会错过其他连接在此之后写入的数据
+                let guard = blocked.get_or_insert_with(|| {
+                    state.blocking.block(ctx.client.db, &keys, ctx.client.id)
+                });
+                drop(guards);
+                if !guard.wait(deadline).await {
+                    ctx.prevent_propagation();
+                    break RESP::NullArray;
+                }
+            }
+        }
+        Cmd::BLMove(src, dst, from_left, to_left, timeout) => {
+            let deadline = blocking::deadline(timeout);
+            let mut blocked = None;
+            loop {
+                let mut guards = db::write_shards(db, &[src.clone(), dst.clone()]).await;
+                if let Some(element) = list_move(
+                    &mut guards,
+                    &src,
+                    &dst,
+                    from_left,
+                    to_left,
+                    ctx.now_millis(),
+                )? {
+                    let side = |left| RESP::new_bulk(if left { "LEFT" } else { "RIGHT" });
+                    ctx.rewrite_propagation(RESP::Array(vec![
+                        RESP::new_bulk("LMOVE"),
+                        RESP::Bulk(src),
+                        RESP::Bulk(dst.clone()),
+                        side(from_left),
+                        side(to_left),
+                    ]));
+                    ctx.signal_key_ready(ctx.client.db, dst);
+                    break RESP::Bulk(element);
+                }
+                let guard = blocked.get_or_insert_with(|| {
+                    state
+                        .blocking
+                        .block(ctx.client.db, std::slice::from_ref(&src), ctx.client.id)
+                });
+                drop(guards);
+                if !guard.wait(deadline).await {
+                    ctx.prevent_propagation();
+                    break RESP::Null;
+                }
+            }
+        }
+        Cmd::LMPop(keys, left, count, timeout) => {
+            let deadline = timeout.and_then(blocking::deadline);
+            let mut blocked = None;
+            loop {
+                let mut guards = db::write_shards(db, &keys).await;
+                if let Some((key, popped)) =
+                    pop_first_list(&mut guards, &keys, left, count, ctx.now_millis())?
+                {
+                    ctx.rewrite_propagation(pop_propagation(key.clone(), left, Some(count)));
+                    break RESP::Array(vec![
+                        RESP::Bulk(key),
+                        RESP::Array(popped.into_iter().map(RESP::Bulk).collect()),
+                    ]);
+                }
+                if timeout.is_none() {
+                    ctx.prevent_propagation();
+                    break RESP::NullArray;
+                }
+                let guard = blocked.get_or_insert_with(|| {
+                    state.blocking.block(ctx.client.db, &keys, ctx.client.id)
+                });
+                drop(guards);
+                if !guard.wait(deadline).await {
+                    ctx.prevent_propagation();
+                    break RESP::NullArray;
+                }
+            }
+        }
+        Cmd::HSet(key, pairs, hmset) => {
+            let limits = state.config.read().await.hash_limits();
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let hash = db::get_live_or_insert_with(&mut write_db, &key, ctx.now_millis(), || {
+                Value::Hash(Hash::default())
+            })
+            .as_hash_mut()?;
+            let mut added = 0;
+            for (field, value) in pairs {
+                added += hash.insert(field, value, limits) as i64;
+            }
+            if hmset {
+                RESP::new_simple("OK".to_string())
+            } else {
+                RESP::Integer(added)
+            }
+        }
+        Cmd::HSetNx(key, field, value) => {
+            let limits = state.config.read().await.hash_limits();
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let hash = db::get_live_or_insert_with(&mut write_db, &key, ctx.now_millis(), || {
+                Value::Hash(Hash::default())
+            })
+            .as_hash_mut()?;
+            if hash.contains(&field) {
+                ctx.prevent_propagation();
+                RESP::Integer(0)
+            } else {
+                hash.insert(field, value, limits);
+                RESP::Integer(1)
+            }
+        }
+        Cmd::HGet(key, field) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value
+                    .as_hash()?
+                    .get(&field)
+                    .map_or(RESP::Null, |v| RESP::Bulk(v.clone())),
+                None => RESP::Null,
+            }
+        }
+        Cmd::HMGet(key, fields) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let hash = db::get_live(&read_db, &key, ctx.now_millis())
+                .map(|(value, _)| value.as_hash())
+                .transpose()?;
+            RESP::Array(
+                fields
+                    .iter()
+                    .map(|field| match hash.and_then(|hash| hash.get(field)) {
+                        Some(v) => RESP::Bulk(v.clone()),
+                        None => RESP::Null,
+                    })
+                    .collect(),
+            )
+        }
+        Cmd::HGetAll(key) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let pairs = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value
+                    .as_hash()?
+                    .iter()
+                    .map(|(f, v)| (RESP::Bulk(f.clone()), RESP::Bulk(v.clone())))
+                    .collect(),
+                None => vec![],
+            };
+            // RESP2下编码为字段和值交替的数组
+            RESP::Map(pairs)
+        }
+        Cmd::HDel(key, fields) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let hash = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_hash_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+            };
+            let removed = fields.iter().filter(|field| hash.remove(field)).count();
+            if hash.is_empty() {
+                write_db.remove(&key);
+            }
+            if removed == 0 {
+                ctx.prevent_propagation();
+            }
+            RESP::Integer(removed as i64)
+        }
+        Cmd::HIncrBy(key, field, incr) => {
+            let limits = state.config.read().await.hash_limits();
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let now_millis = ctx.now_millis();
+            // 先校验类型和原值, 出错时不创建key
+            let old = match db::get_live(&write_db, &key, now_millis) {
+                Some((value, _)) => match value.as_hash()?.get(&field) {
+                    Some(v) => db::parse_i64_strict(v).ok_or(CmdError::HashNotInteger)?,
+                    None => 0,
+                },
+                None => 0,
+            };
+            let new = old.checked_add(incr).ok_or(CmdError::Overflow)?;
+            db::get_live_or_insert_with(&mut write_db, &key, now_millis, || {
+                Value::Hash(Hash::default())
+            })
+            .as_hash_mut()?
+            .insert(field, Bytes::from(new.to_string()), limits);
+            RESP::Integer(new)
+        }
+        Cmd::HIncrByFloat(key, field, incr) => {
+            let limits = state.config.read().await.hash_limits();
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let now_millis = ctx.now_millis();
+            let old = match db::get_live(&write_db, &key, now_millis) {
+                Some((value, _)) => match value.as_hash()?.get(&field) {
+                    Some(v) => db::parse_float(v).map_err(|_| CmdError::HashNotFloat)?,
+                    None => 0.0,
+                },
+                None => 0.0,
+            };
+            let new = old + incr;
+            if !new.is_finite() {
+                return Err(CmdError::NanOrInfinity);
+            }
+            let new = Bytes::from(db::format_float(new));
+            db::get_live_or_insert_with(&mut write_db, &key, now_millis, || {
+                Value::Hash(Hash::default())
+            })
+            .as_hash_mut()?
+            .insert(field.clone(), new.clone(), limits);
+            // 与INCRBYFLOAT一样以最终结果同步给replica
+            ctx.rewrite_propagation(RESP::Array(vec![
+                RESP::new_bulk("HSET"),
+                RESP::Bulk(key),
+                RESP::Bulk(field),
+                RESP::Bulk(new.clone()),
+            ]));
+            RESP::Bulk(new)
+        }
+        Cmd::HKeys(ref key) | Cmd::HVals(ref key) => {
+            let keys = matches!(cmd, Cmd::HKeys(_));
+            let read_db = db[shard_index(db, key)].read().await;
+            let hash = match db::get_live(&read_db, key, ctx.now_millis()) {
+                Some((value, _)) => value.as_hash()?,
+                None => return Ok(RESP::Array(vec![])),
+            };
+            RESP::Array(
+                hash.iter()
+                    .map(|(f, v)| RESP::Bulk(if keys { f.clone() } else { v.clone() }))
+                    .collect(),
+            )
+        }
+        Cmd::HLen(key) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let len = db::get_live(&read_db, &key, ctx.now_millis())
+                .map_or(Ok(0), |(value, _)| value.as_hash().map(|hash| hash.len()))?;
+            RESP::Integer(len as i64)
+        }
+        Cmd::HExists(key, field) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let exists = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_hash()?.contains(&field),
+                None => false,
+            };
+            RESP::Integer(exists as i64)
+        }
+        Cmd::HStrlen(key, field) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let len = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_hash()?.get(&field).map_or(0, |v| v.len()),
+                None => 0,
+            };
+            RESP::Integer(len as i64)
+        }
+        Cmd::HScan(key, cursor, opts) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let hash = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_hash()?,
+                None => return Ok(RESP::Array(vec![RESP::new_bulk("0"), RESP::Array(vec![])])),
+            };
+            let (next, pairs) = db::scan_collection(hash.iter(), cursor, opts.count);
+            let mut items = Vec::new();
+            for (field, value) in pairs {
+                if opts.pattern.iter().all(|p| db::glob_match(p, field)) {
+                    items.push(RESP::Bulk(field.clone()));
+                    if !opts.novalues {
+                        items.push(RESP::Bulk(value.clone()));
+                    }
+                }
+            }
+            RESP::Array(vec![RESP::new_bulk(next.to_string()), RESP::Array(items)])
+        }
+        Cmd::HRandField(key, count) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let hash = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_hash()?,
+                None if count.is_some() => return Ok(RESP::Array(vec![])),
+                None => return Ok(RESP::Null),
+            };
+            // 哈希为空时key已被删除, 这里至少有一个字段
+            let pairs: Vec<_> = hash.iter().collect();
+            let random = || pairs[(db::random_u64() % pairs.len() as u64) as usize];
+            let (count, withvalues) = match count {
+                Some(count) => count,
+                None => return Ok(RESP::Bulk(random().0.clone())),
+            };
+            // 正数返回不重复的字段, 负数允许重复且恰好返回|count|个
+            let picked = if count >= 0 {
+                db::sample(pairs.clone(), count as usize)
+            } else {
+                (0..count.unsigned_abs()).map(|_| random()).collect()
+            };
+            let resp3 = ctx.client.protocol == Protocol::Resp3;
+            let mut items = Vec::with_capacity(picked.len() * 2);
+            for (field, value) in picked {
+                let (field, value) = (RESP::Bulk(field.clone()), RESP::Bulk(value.clone()));
+                match (withvalues, resp3) {
+                    (false, _) => items.push(field),
+                    // RESP3下每对字段和值是一个数组
+                    (true, true) => items.push(RESP::Array(vec![field, value])),
+                    (true, false) => items.extend([field, value]),
+                }
+            }
+            RESP::Array(items)
+        }
+        Cmd::SAdd(key, members) => {
+            let max_intset = state.config.read().await.set_max_intset_entries;
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let set = db::get_live_or_insert_with(&mut write_db, &key, ctx.now_millis(), || {
+                Value::Set(Set::default())
+            })
+            .as_set_mut()?;
+            let mut added = 0;
+            for member in members {
+                added += set.insert(member, max_intset) as i64;
+            }
+            if added == 0 {
+                ctx.prevent_propagation();
+            }
+            RESP::Integer(added)
+        }
+        Cmd::SRem(key, members) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let set = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_set_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+            };
+            let removed = members.iter().filter(|member| set.remove(member)).count();
+            if set.is_empty() {
+                write_db.remove(&key);
+            }
+            if removed == 0 {
+                ctx.prevent_propagation();
+            }
+            RESP::Integer(removed as i64)
+        }
+        Cmd::SMembers(key) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let members = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_set()?.members(),
+                None => vec![],
+            };
+            RESP::Set(members.into_iter().map(RESP::Bulk).collect())
+        }
+        Cmd::SIsMember(key, member) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let exists = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_set()?.contains(&member),
+                None => false,
+            };
+            RESP::Integer(exists as i64)
+        }
+        Cmd::SMIsMember(key, members) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let set = db::get_live(&read_db, &key, ctx.now_millis())
+                .map(|(value, _)| value.as_set())
+                .transpose()?;
+            RESP::Array(
+                members
+                    .iter()
+                    .map(|member| RESP::Integer(set.is_some_and(|set| set.contains(member)) as i64))
+                    .collect(),
+            )
+        }
+        Cmd::SCard(key) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let len = db::get_live(&read_db, &key, ctx.now_millis())
+                .map_or(Ok(0), |(value, _)| value.as_set().map(|set| set.len()))?;
+            RESP::Integer(len as i64)
+        }
+        Cmd::SPop(key, count) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let set = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_set_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(if count.is_some() {
+                        RESP::Set(vec![])
+                    } else {
+                        RESP::Null
+                    });
+                }
+            };
+            let popped = db::sample(set.members(), count.unwrap_or(1));
+            for member in &popped {
+                set.remove(member);
+            }
+            if set.is_empty() {
+                write_db.remove(&key);
+            }
+            if popped.is_empty() {
+                ctx.prevent_propagation();
+            } else {
+                // 弹出的成员是随机的, 以SREM同步给replica
+                let mut args = vec![RESP::new_bulk("SREM"), RESP::Bulk(key)];
+                args.extend(popped.iter().cloned().map(RESP::Bulk));
+                ctx.rewrite_propagation(RESP::Array(args));
+            }
+            match count {
+                Some(_) => RESP::Set(popped.into_iter().map(RESP::Bulk).collect()),
+                // 集合为空时key已被删除, 这里一定弹出了一个成员
+                None => RESP::Bulk(popped[0].clone()),
+            }
+        }
+        Cmd::SRandMember(key, count) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let members = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_set()?.members(),
+                None if count.is_some() => return Ok(RESP::Array(vec![])),
+                None => return Ok(RESP::Null),
+            };
+            let random = || members[(db::random_u64() % members.len() as u64) as usize].clone();
+            // 与HRANDFIELD一样, 正数返回不重复的成员, 负数允许重复
+            let picked = match count {
+                None => return Ok(RESP::Bulk(random())),
+                Some(count) if count >= 0 => db::sample(members.clone(), count as usize),
+                Some(count) => (0..count.unsigned_abs()).map(|_| random()).collect(),
+            };
+            RESP::Array(picked.into_iter().map(RESP::Bulk).collect())
+        }
+        Cmd::SMove(src, dst, member) => {
+            let max_intset = state.config.read().await.set_max_intset_entries;
+            let mut guards = db::write_shards(db, &[src.clone(), dst.clone()]).await;
+            let now_millis = ctx.now_millis();
+            let found = match db::get_live(guards.shard(&src), &src, now_millis) {
+                Some((value, _)) => value.as_set()?.contains(&member),
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+            };
+            // 目标key类型不对时不移动
+            if let Some((value, _)) = db::get_live(guards.shard(&dst), &dst, now_millis) {
+                value.as_set()?;
+            }
+            if !found || src == dst {
+                ctx.prevent_propagation();
+                return Ok(RESP::Integer(found as i64));
+            }
+            let src_set = guards.shard_mut(&src).get_mut(&src).unwrap().as_set_mut()?;
+            src_set.remove(&member);
+            if src_set.is_empty() {
+                guards.shard_mut(&src).remove(&src);
+            }
+            db::get_live_or_insert_with(guards.shard_mut(&dst), &dst, now_millis, || {
+                Value::Set(Set::default())
+            })
+            .as_set_mut()?
+            .insert(member, max_intset);
+            RESP::Integer(1)
+        }
+        Cmd::SScan(key, cursor, opts) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let members = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_set()?.members(),
+                None => vec![],
+            };
+            let (next, batch) = db::scan_collection(
+                members.iter().map(|member| (member, ())),
+                cursor,
+                opts.count,
+            );
+            let items = batch
+                .into_iter()
+                .filter(|(member, _)| opts.pattern.iter().all(|p| db::glob_match(p, member)))
+                .map(|(member, _)| RESP::Bulk(member.clone()))
+                .collect();
+            RESP::Array(vec![RESP::new_bulk(next.to_string()), RESP::Array(items)])
+        }
+        Cmd::SCombine(op, keys) => {
+            // 按分片顺序同时持有所有读锁, 读到的是同一时刻的数据
+            let guards = db::read_shards(db, &keys).await;
+            let sets = live_sets(&guards, &keys, ctx.now_millis())?;
+            let members = set::combine(op, &sets, 0);
+            RESP::Set(members.into_iter().map(RESP::Bulk).collect())
+        }
+        Cmd::SCombineStore(op, dst, keys) => {
+            let max_intset = state.config.read().await.set_max_intset_entries;
+            let mut all_keys = keys.clone();
+            all_keys.push(dst.clone());
+            let mut guards = db::write_shards(db, &all_keys).await;
+            let now_millis = ctx.now_millis();
+            let members = set::combine(op, &live_sets(&guards, &keys, now_millis)?, 0);
+            let shard = guards.shard_mut(&dst);
+            if members.is_empty() {
+                // 结果为空时删除目标key, 在replica上对应DEL
+                if shard.remove_live(&dst, now_millis).is_some() {
+                    ctx.rewrite_propagation(RESP::Array(vec![
+                        RESP::new_bulk("DEL"),
+                        RESP::Bulk(dst),
+                    ]));
+                } else {
+                    ctx.prevent_propagation();
+                }
+                return Ok(RESP::Integer(0));
+            }
+            let len = members.len();
+            let mut result = Set::default();
+            for member in members {
+                result.insert(member, max_intset);
+            }
+            // 覆盖目标key的类型和过期时间; 运算是确定的, replica执行原命令得到相同结果
+            shard.insert(dst, (Value::Set(result), u128::MAX));
+            RESP::Integer(len as i64)
+        }
+        Cmd::SInterCard(keys, limit) => {
+            let guards = db::read_shards(db, &keys).await;
+            let sets = live_sets(&guards, &keys, ctx.now_millis())?;
+            RESP::Integer(set::combine(set::SetOp::Inter, &sets, limit).len() as i64)
+        }
+        Cmd::ZAdd(key, pairs, opts) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let now_millis = ctx.now_millis();
+            // XX不会创建key
+            if opts.xx && db::get_live(&write_db, &key, now_millis).is_none() {
+                ctx.prevent_propagation();
+                return Ok(if opts.incr {
+                    RESP::Null
+                } else {
+                    RESP::Integer(0)
+                });
+            }
+            let zset = db::get_live_or_insert_with(&mut write_db, &key, now_millis, || {
+                Value::ZSet(ZSet::default())
+            })
+            .as_zset_mut()?;
+            let (mut added, mut changed, mut result) = (0, 0, None);
+            for (score, member) in pairs {
+                let new = match zset.score(&member) {
+                    Some(_) if opts.nx => continue,
+                    None if opts.xx => continue,
+                    Some(old) => {
+                        let new = if opts.incr { old + score } else { score };
+                        if new.is_nan() {
+                            return Err(CmdError::ScoreNaN);
+                        }
+                        // GT/LT只限制更新, 不影响新增成员
+                        if opts.gt && new <= old || opts.lt && new >= old {
+                            continue;
+                        }
+                        if new != old {
+                            changed += 1;
+                        }
+                        new
+                    }
+                    None => {
+                        added += 1;
+                        score
+                    }
+                };
+                zset.insert(member, new);
+                result = Some(new);
+            }
+            if added + changed == 0 {
+                ctx.prevent_propagation();
+            } else if added > 0 {
+                ctx.signal_key_ready(ctx.client.db, key);
+            }
+            if opts.incr {
+                result.map_or(RESP::Null, RESP::Double)
+            } else if opts.ch {
+                RESP::Integer(added + changed)
+            } else {
+                RESP::Integer(added)
+            }
+        }
+        Cmd::ZRem(key, members) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let zset = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_zset_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+            };
+            let removed = members.iter().filter(|member| zset.remove(member)).count();
+            if zset.is_empty() {
+                write_db.remove(&key);
+            }
+            if removed == 0 {
+                ctx.prevent_propagation();
+            }
+            RESP::Integer(removed as i64)
+        }
+        Cmd::ZScore(key, member) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let score = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_zset()?.score(&member),
+                None => None,
+            };
+            score.map_or(RESP::Null, RESP::Double)
+        }
+        Cmd::ZCard(key) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let len = db::get_live(&read_db, &key, ctx.now_millis())
+                .map_or(Ok(0), |(value, _)| value.as_zset().map(|zset| zset.len()))?;
+            RESP::Integer(len as i64)
+        }
+        Cmd::ZRank(key, member, rev, withscore) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let zset = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_zset()?,
+                None => return Ok(RESP::Null),
+            };
+            match (zset.rank(&member, rev), zset.score(&member)) {
+                (Some(rank), Some(score)) if withscore => {
+                    RESP::Array(vec![RESP::Integer(rank as i64), RESP::Double(score)])
+                }
+                (Some(rank), _) => RESP::Integer(rank as i64),
+                _ => RESP::Null,
+            }
+        }
+        Cmd::ZRange(key, range) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let zset = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_zset()?,
+                None => return Ok(RESP::Array(vec![])),
+            };
+            let (offset, count) = match range.limit {
+                Some((offset, _)) if offset < 0 => return Ok(RESP::Array(vec![])),
+                // count为负数时不限制个数
+                Some((offset, count)) => (offset as usize, usize::try_from(count).ok()),
+                None => (0, None),
+            };
+            let items = match &range.by {
+                ZRangeBy::Rank(start, stop) => match index_range(zset.len(), *start, *stop) {
+                    Some((start, end)) => zset.range_by_rank(start, end, range.rev),
+                    None => vec![],
+                },
+                ZRangeBy::Score(by) => zset.range_by_score(by, range.rev, offset, count),
+                ZRangeBy::Lex(by) => zset.range_by_lex(by, range.rev, offset, count),
+            };
+            zset_reply(items, range.withscores, ctx.client.protocol)
+        }
+        Cmd::ZRemRange(key, by) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let zset = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_zset_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+            };
+            let items = match &by {
+                ZRangeBy::Rank(start, stop) => match index_range(zset.len(), *start, *stop) {
+                    Some((start, end)) => zset.range_by_rank(start, end, false),
+                    None => vec![],
+                },
+                ZRangeBy::Score(range) => zset.range_by_score(range, false, 0, None),
+                ZRangeBy::Lex(range) => zset.range_by_lex(range, false, 0, None),
+            };
+            for (member, _) in &items {
+                zset.remove(member);
+            }
+            if zset.is_empty() {
+                write_db.remove(&key);
+            }
+            if items.is_empty() {
+                ctx.prevent_propagation();
+            }
+            RESP::Integer(items.len() as i64)
+        }
+        Cmd::ZPop(key, max, count) => {
+            let mut guards = db::write_shards(db, std::slice::from_ref(&key)).await;
+            let popped = pop_first_zset(
+                &mut guards,
+                std::slice::from_ref(&key),
+                max,
+                count.unwrap_or(1),
+                ctx.now_millis(),
+            )?
+            .map_or(vec![], |(_, popped)| popped);
+            if popped.is_empty() {
+                ctx.prevent_propagation();
+            }
+            match count {
+                Some(_) => zset_reply(popped, true, ctx.client.protocol),
+                // 不带count时总是成员和分数交替的数组
+                None => zset_reply(popped, true, Protocol::Resp2),
+            }
+        }
+        Cmd::BZPop(keys, max, timeout) => {
+            let deadline = blocking::deadline(timeout);
+            let mut blocked = None;
+            loop {
+                let mut guards = db::write_shards(db, &keys).await;
+                if let Some((key, mut popped)) =
+                    pop_first_zset(&mut guards, &keys, max, 1, ctx.now_millis())?
+                {
+                    ctx.rewrite_propagation(zpop_propagation(key.clone(), max, None));
+                    let (member, score) = popped.remove(0);
+                    break RESP::Array(vec![
+                        RESP::Bulk(key),
+                        RESP::Bulk(member),
+                        RESP::Double(score),
+                    ]);
+                }
+                let guard = blocked.get_or_insert_with(|| {
+                    state.blocking.block(ctx.client.db, &keys, ctx.client.id)
+                });
+                drop(guards);
+                if !guard.wait(deadline).await {
+                    ctx.prevent_propagation();
+                    break RESP::NullArray;
+                }
+            }
+        }
+        Cmd::ZMPop(keys, max, count, timeout) => {
+            let deadline = timeout.and_then(blocking::deadline);
+            let mut blocked = None;
+            loop {
+                let mut guards = db::write_shards(db, &keys).await;
+                if let Some((key, popped)) =
+                    pop_first_zset(&mut guards, &keys, max, count, ctx.now_millis())?
+                {
+                    ctx.rewrite_propagation(zpop_propagation(key.clone(), max, Some(count)));
+                    // 成员和分数总是成对嵌套
+                    break RESP::Array(vec![
+                        RESP::Bulk(key),
+                        zset_reply(popped, true, Protocol::Resp3),
+                    ]);
+                }
+                if timeout.is_none() {
+                    ctx.prevent_propagation();
+                    break RESP::NullArray;
+                }
+                let guard = blocked.get_or_insert_with(|| {
+                    state.blocking.block(ctx.client.db, &keys, ctx.client.id)
+                });
+                drop(guards);
+                if !guard.wait(deadline).await {
+                    ctx.prevent_propagation();
+                    break RESP::NullArray;
+                }
+            }
+        }
+        Cmd::ZCombine(op, keys, opts) => {
+            let guards = db::read_shards(db, &keys).await;
+            let sources = live_zset_sources(&guards, &keys, ctx.now_millis())?;
+            let items = zset::combine(op, &sources, &opts.weights, opts.aggregate);
+            zset_reply(items, opts.withscores, ctx.client.protocol)
+        }
+        Cmd::ZCombineStore(op, dst, keys, opts) => {
+            let mut all_keys = keys.clone();
+            all_keys.push(dst.clone());
+            let mut guards = db::write_shards(db, &all_keys).await;
+            let now_millis = ctx.now_millis();
+            let sources = live_zset_sources(&guards, &keys, now_millis)?;
+            let items = zset::combine(op, &sources, &opts.weights, opts.aggregate);
+            let shard = guards.shard_mut(&dst);
+            if items.is_empty() {
+                // 与SINTERSTORE一样, 结果为空时删除目标key
+                if shard.remove_live(&dst, now_millis).is_some() {
+                    ctx.rewrite_propagation(RESP::Array(vec![
+                        RESP::new_bulk("DEL"),
+                        RESP::Bulk(dst),
+                    ]));
+                } else {
+                    ctx.prevent_propagation();
+                }
+                return Ok(RESP::Integer(0));
+            }
+            let len = items.len();
+            let mut result = ZSet::default();
+            for (member, score) in items {
+                result.insert(member, score);
+            }
+            shard.insert(dst.clone(), (Value::ZSet(result), u128::MAX));
+            ctx.signal_key_ready(ctx.client.db, dst);
+            RESP::Integer(len as i64)
+        }
+        Cmd::XAdd(key, id, fields, opts) => {
+            let node_max_entries = state.config.read().await.stream_node_max_entries;
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let now_millis = ctx.now_millis();
+            if opts.nomkstream && db::get_live(&write_db, &key, now_millis).is_none() {
+                ctx.prevent_propagation();
+                return Ok(RESP::Null);
+            }
+            // 0-0在解析时已被拒绝, 新建的stream不会出错, 因此可以先插入key
+            let stream = db::get_live_or_insert_with(&mut write_db, &key, now_millis, || {
+                Value::Stream(Stream::default())
+            })
+            .as_stream_mut()?;
+            let id = stream.next_id(id, now_millis as u64)?;
+            // 自动生成的ID以具体的值同步给replica
+            let mut entry = vec![RESP::new_bulk(id.to_string())];
+            for (field, value) in &fields {
+                entry.push(RESP::Bulk(field.clone()));
+                entry.push(RESP::Bulk(value.clone()));
+            }
+            stream.add(id, fields);
+            let mut args = vec![RESP::new_bulk("XADD"), RESP::Bulk(key)];
+            if let Some(trim) = opts.trim {
+                stream.trim(&trim, node_max_entries);
+                args.extend(trim_propagation(&trim, stream.len()));
+            }
+            args.extend(entry);
+            ctx.rewrite_propagation(RESP::Array(args));
+            RESP::new_bulk(id.to_string())
+        }
+        Cmd::XTrim(key, trim) => {
+            let node_max_entries = state.config.read().await.stream_node_max_entries;
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let stream = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_stream_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+            };
+            let removed = stream.trim(&trim, node_max_entries);
+            if removed == 0 {
+                ctx.prevent_propagation();
+            } else if trim.approx {
+                let mut args = vec![RESP::new_bulk("XTRIM"), RESP::Bulk(key)];
+                args.extend(trim_propagation(&trim, stream.len()));
+                ctx.rewrite_propagation(RESP::Array(args));
+            }
+            RESP::Integer(removed as i64)
+        }
+        Cmd::XDel(key, ids) => {
+            let mut write_db = db[shard_index(db, &key)].write().await;
+            let stream = match db::get_live_mut(&mut write_db, &key, ctx.now_millis()) {
+                Some(value) => value.as_stream_mut()?,
+                None => {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+            };
+            // 与列表等不同, 消息删光后key依然保留
+            let removed = ids.iter().filter(|id| stream.remove(id)).count();
+            if removed == 0 {
+                ctx.prevent_propagation();
+            }
+            RESP::Integer(removed as i64)
+        }
+        Cmd::XLen(key) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let len = db::get_live(&read_db, &key, ctx.now_millis())
+                .map_or(Ok(0), |(value, _)| {
+                    value.as_stream().map(|stream| stream.len())
+                })?;
+            RESP::Integer(len as i64)
+        }
+        Cmd::XRange(key, start, end, rev, count) => {
+            let read_db = db[shard_index(db, &key)].read().await;
+            let stream = match db::get_live(&read_db, &key, ctx.now_millis()) {
+                Some((value, _)) => value.as_stream()?,
+                None => return Ok(RESP::Array(vec![])),
+            };
+            match count {
+                Some(0) => RESP::NullArray,
+                _ => stream_reply(stream.range(start, end, rev, count.unwrap_or(usize::MAX))),
+            }
+        }
+        Cmd::Info(section) => {
+            let all = matches!(section.as_str(), "" | "all" | "default" | "everything");
+            let mut sections = Vec::new();
+            if all || section == "replication" {
+                let read_config = state.config.read().await;
+                // println!("info config:{:?}", read_config);
+                sections.push(format!(
+                    "# Replication\r\nrole:{}\r\nmaster_replid:{}\r\nmaster_repl_offset:{}\r\n",
+                    read_config.role, read_config.master_replid, read_config.master_repl_offset
+                ));
+            }
+            if all || section == "stats" {
+                let mut expired_keys = 0;
+                for shard in state.dbs.iter().flat_map(|db| db.iter()) {
+                    expired_keys += shard.read().await.expired_keys();
+                }
+                sections.push(format!("# Stats\r\nexpired_keys:{}\r\n", expired_keys));
+            }
+            RESP::new_bulk(sections.join("\r\n"))
+        }
+        Cmd::ReplConf(_, _) => RESP::new_simple("OK".to_string()),
+        Cmd::Wait(_numreplicas, _timeout) => RESP::Integer(*state.num_replica.read().await as i64),
+        Cmd::Hello(protocol, auth, setname) => {
+            ctx.client.hello(protocol, auth, setname)?;
+            let role = match state.config.read().await.role.as_str() {
+                "slave" => "replica",
+                _ => "master",
+            };
+            RESP::Map(vec![
+                (RESP::new_bulk("server"), RESP::new_bulk("redis")),
+                (RESP::new_bulk("version"), RESP::new_bulk(REDIS_VERSION)),
+                (
+                    RESP::new_bulk("proto"),
+                    RESP::Integer(match ctx.client.protocol {
+                        Protocol::Resp2 => 2,
+                        Protocol::Resp3 => 3,
+                    }),
+                ),
+                (RESP::new_bulk("id"), RESP::Integer(ctx.client.id as i64)),
+                (RESP::new_bulk("mode"), RESP::new_bulk("standalone")),
+                (RESP::new_bulk("role"), RESP::new_bulk(role)),
+                (RESP::new_bulk("modules"), RESP::Array(vec![])),
+            ])
+        }
+        Cmd::Command(args) => cmd_table::command(&args, &state.custom_specs()),
+        _ => RESP::Null,
+    };
+    Ok(response)
+}
+
+async fn run_command(command: Command, ctx: &mut Context<'_>) -> RESP {
+    let res = match command {
+        Command::Builtin(cmd) => execute(cmd, ctx).await,
+        Command::Custom(handler) => handler.execute(ctx).await,
+    };
+    res.unwrap_or_else(RESP::from)
+}
+
+// 全量同步: 发送FULLRESYNC和RDB文件, 之后该连接只用于向replica转发写命令
+async fn psync(
+    mut stream: TcpStream,
+    mut out: BytesMut,
+    state: &ServerState,
+    repl_id: String,
+    offset: i64,
+) {
+    if repl_id.as_str() == "?" && offset == -1 {
+        let read_config = state.config.read().await;
+        RESP::new_simple(format!("FULLRESYNC {} 0", read_config.master_replid)).encode(&mut out);
+        let empty_rdb_bytes = EMPTY_RDB_BYTES
+            .get_or_init(|| async {
+                let mut buf = BytesMut::new();
+                RESP::RDBFile(Bytes::from(hex2bytes(EMPTY_RDB_HEX))).encode(&mut buf);
+                buf.freeze()
+            })
+            .await;
+        out.extend_from_slice(empty_rdb_bytes);
+    }
+    // 此前pipeline中的回复也要在转为replica连接之前发出
+    stream.write_all(&out).await.unwrap();
+    let (tx, rx) = mpsc::channel(32);
+    state.tx_list.write().await.push(tx);
+    let mut num = state.num_replica.write().await;
+    *num += 1;
+    tokio::spawn(handle_replica(stream, rx));
+}
+
+pub async fn handle_client(mut stream: TcpStream, state: Arc<ServerState>) {
+    let mut client = ClientState::new();
+    let mut decoder = RespDecoder::new();
+    // 回复先编码到out中, 处理完缓冲区中所有(pipeline)请求后再一次性写入socket
+    let mut out = BytesMut::with_capacity(4096);
+    loop {
+        let resp = match decoder.decode_request() {
+            Ok(Some((_, resp))) => resp,
+            Ok(None) => {
+                if !out.is_empty() {
+                    if stream.write_all(&out).await.is_err() {
+                        break;
+                    }
+                    out.clear();
+                }
+                match decoder.read_from(&mut stream).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => continue,
+                }
+            }
+            Err(e) => {
+                // 协议错误时与redis一样回复错误并关闭连接
+                RESP::Error(format!("ERR {}", e)).encode_as(&mut out, client.protocol);
+                let _ = stream.write_all(&out).await;
+                break;
+            }
+        };
+        let (command, spec) = match state.parse_command(&resp) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                RESP::from(e).encode_as(&mut out, client.protocol);
+                continue;
+            }
+        };
+        if let Command::Builtin(Cmd::Psync(repl_id, offset)) = command {
+            psync(stream, out, &state, repl_id, offset).await;
+            return;
+        }
+        let mut ctx = Context::new(&state, &mut client);
+        let response = if spec.has(CMD_BLOCKING) {
+            // 阻塞前先发出pipeline中之前的回复; 阻塞期间继续读取socket以便发现连接断开,
+            // 断开时丢弃执行中的命令, 客户端随之退出等待队列
+            if !out.is_empty() {
+                if stream.write_all(&out).await.is_err() {
+                    break;
+                }
+                out.clear();
+            }
+            let closed = async {
+                while let Ok(n) = decoder.read_from(&mut stream).await {
+                    if n == 0 {
+                        break;
+                    }
+                }
+            };
+            tokio::select! {
+                response = run_command(command, &mut ctx) => response,
+                _ = closed => break,
+            }
+        } else {
+            run_command(command, &mut ctx).await
+        };
+        propagate(ctx, spec, resp, &response);
+        response.encode_as(&mut out, client.protocol);
+    }
+}
+
+// 执行成功的写命令同步给replica, 默认同步原命令
+fn propagate(mut ctx: Context<'_>, spec: &CommandSpec, request: RESP, response: &RESP) {
+    let state = ctx.state();
+    if spec.is_write() && !matches!(response, RESP::Error(_)) {
+        for frame in ctx.take_propagation().unwrap_or_else(|| vec![request]) {
+            state.write_cmd_tx.send((ctx.client.db, frame)).unwrap();
+        }
+    }
+    // 写入同步给replica之后再唤醒阻塞的客户端, 保证replica上先写入再弹出
+    for (db, key) in ctx.take_ready_keys() {
+        state.blocking.wake(db, &key);
+    }
+}
+
+// 编码到out后一次性写入stream, out在多次调用间复用
+async fn write_frame(stream: &mut TcpStream, out: &mut BytesMut, resp: &RESP) -> Result<()> {
+    out.clear();
+    resp.encode(out);
+    stream.write_all(out).await?;
+    Ok(())
+}
+
+// 读取下一个完整的RESP, 数据不足时继续从stream中读取
+async fn read_frame(stream: &mut TcpStream, decoder: &mut RespDecoder) -> Result<RESP> {
+    loop {
+        if let Some((_, resp)) = decoder.decode()? {
+            return Ok(resp);
+        }
+        if decoder.read_from(stream).await? == 0 {
+            return Err(anyhow!("connection closed by master"));
+        }
+    }
+}
+
+async fn handshake(
+    config: &mut Config,
+    stream: &mut TcpStream,
+    decoder: &mut RespDecoder,
+) -> Result<()> {
+    // handshake
+    println!("Begin handshake");
+    let mut out = BytesMut::new();
+    // 1.1 send "PING" to master
+    write_frame(stream, &mut out, &Cmd::new_ping_resp()).await?;
+    // 1.2 receive "PONG" from master
+    let resp = read_frame(stream, decoder)
+        .await
+        .map_err(|e| anyhow!("handshake: 1.2 receive \"PONG\" from master failed: {}", e))?;
+    assert_eq!(resp, RESP::new_simple("PONG".to_string()));
+    println!("handshake: PING finished");
+    // 2.1 send "REPLCONF listening-port <PORT>" to master
+    let replconf = RESP::new_cmd_array(vec![
+        "REPLCONF".to_string(),
+        "listening-port".to_string(),
+        config.port.to_string(),
+    ]);
+    write_frame(stream, &mut out, &replconf).await?;
+    // 2.2 receive "OK" from master
+    let resp = read_frame(stream, decoder)
+        .await
+        .map_err(|e| anyhow!("handshake: 2.2 receive \"OK\" from master failed: {}", e))?;
+    assert_eq!(resp, RESP::new_simple("OK".to_string()));
+    // 2.3 send "REPLCONF capa psync2" to master
+    let replconf = RESP::new_cmd_array(vec![
+        "REPLCONF".to_string(),
+        "capa".to_string(),
+        "psync2".to_string(),
+    ]);
+    write_frame(stream, &mut out, &replconf).await?;
+    // 2.4 receive "OK" from master
+    let resp = read_frame(stream, decoder)
+        .await
+        .map_err(|e| anyhow!("handshake: 2.4 receive \"OK\" from master failed: {}", e))?;
+    assert_eq!(resp, RESP::new_simple("OK".to_string()));
+    println!("handshake: REPLCONF finished");
+    // 3.1 send "PSYNC ? -1" to master
+    let psync = RESP::new_cmd_array(vec!["PSYNC".to_string(), "?".to_string(), "-1".to_string()]);
+    write_frame(stream, &mut out, &psync).await?;
+    // 3.2 receive "+FULLRESYNC <REPL_ID> 0\r\n" and RDB file from master
+    let res = read_frame(stream, decoder).await.map_err(|e| {
+        anyhow!(
+            "3.2 receive \"+FULLRESYNC <REPL_ID> 0\r\n\" from master failed: {}",
+            e
+        )
+    })?;
+    println!("handshake FULLRESYNC resp:{}", res);
+    if let Ok(Cmd::FullReSync(repl_id, offset)) = Cmd::from(&res) {
+        config.master_replid = repl_id;
+        config.master_repl_offset = offset;
+    } else {
+        return Err(anyhow!(
+            "failed in 2.6 receive \"+FULLRESYNC <REPL_ID> 0\r\n\" from master"
+        ));
+    }
+    // RDB文件可能和FULLRESYNC一起到达, 也可能被拆分到多次读取
+    while decoder.decode_rdb()?.is_none() {
+        if decoder.read_from(stream).await? == 0 {
+            return Err(anyhow!("3.2 receive RDB file from master failed"));
+        }
+    }
+    println!("handshake: receive FULLRESYNC and RDB file finished");
+    Ok(())
+}
+
+// master slave 握手后master可能连续发送命令使得握手时的读取中残留了需要同步的命令, 它们保存在decoder中
+async fn handle_master_loop(
+    mut stream: TcpStream,
+    mut decoder: RespDecoder,
+    state: Arc<ServerState>,
+) -> Result<()> {
+    let mut total_len = 0;
+    let mut out = BytesMut::new();
+    let mut client = ClientState::new();
+    client.master_link = true;
+    loop {
+        // master同步的命令与客户端请求的格式相同
+        while let Some((len, resp)) = decoder.decode_request()? {
+            match state.parse_command(&resp) {
+                // 这里只处理需要回显的命令
+                Ok((Command::Builtin(Cmd::ReplConf(r#type, arg)), _))
+                    if r#type.eq_ignore_ascii_case("getack") && &arg == "*" =>
+                {
+                    let res = RESP::new_cmd_array(vec![
+                        "REPLCONF".to_string(),
+                        "ACK".to_string(),
+                        total_len.to_string(),
+                    ]);
+                    write_frame(&mut stream, &mut out, &res).await?;
+                }
+                // 写命令和SELECT静默执行, 不回复也不再向下同步
+                Ok((command, spec)) if spec.is_write() || spec.name == "select" => {
+                    let mut ctx = Context::new(&state, &mut client);
+                    run_command(command, &mut ctx).await;
+                    for (db, key) in ctx.take_ready_keys() {
+                        state.blocking.wake(db, &key);
+                    }
+                }
+                _ => (),
+            };
+            // 即使不回显的命令也需要记录其长度
+            total_len += len;
+        }
+        if decoder.read_from(&mut stream).await? == 0 {
+            break Ok(());
+        }
+    }
+}
+
+/// 定期删除已过期但一直没有被访问的key
+async fn active_expire_cycle(state: Arc<ServerState>) {
+    // 所有db的分片依次处理
+    let shards: Vec<_> = state.dbs.iter().flat_map(|db| db.iter()).collect();
+    let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
+    // 时间用完时记录下一次开始的分片, 避免靠后的分片一直轮不到
+    let mut next_shard = 0;
+    loop {
+        interval.tick().await;
+        let start = Instant::now();
+        for i in 0..shards.len() {
+            let shard = (next_shard + i) % shards.len();
+            if start.elapsed() > ACTIVE_EXPIRE_TIME_LIMIT {
+                next_shard = shard;
+                break;
+            }
+            // 一批删满说明还有较多过期的key, 继续处理当前分片; 每批之间释放锁
+            loop {
+                let expired = shards[shard]
+                    .write()
+                    .await
+                    .remove_expired(now_millis(), ACTIVE_EXPIRE_KEYS_PER_LOOP);
+                if expired.len() < ACTIVE_EXPIRE_KEYS_PER_LOOP
+                    || start.elapsed() > ACTIVE_EXPIRE_TIME_LIMIT
+                {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+pub async fn handle_master(state: Arc<ServerState>) -> Result<()> {
+    let mut write_config = state.config.write().await;
+    if write_config.role.as_str() == "slave" {
+        let mut stream = TcpStream::connect(format!(
+            "{}:{}",
+            write_config.master_host, write_config.master_port
+        ))
+        .await?;
+        let mut decoder = RespDecoder::new();
+        handshake(&mut write_config, &mut stream, &mut decoder).await?;
+        println!("slave: handshake has finished, listening from master begins");
+        tokio::spawn(handle_master_loop(stream, decoder, state.clone()));
+    } else {
+        println!("master: no need for handshaking");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod server_test {
+    use super::*;
+    use crate::{cmd::CmdParser, cmd_table::CMD_WRITE, handler::BoxFuture};
+    use tokio::task::JoinHandle;
+
+    #[test]
+    fn test_index_range() {
+        assert_eq!(index_range(10, 0, -1), Some((0, 9)));
+        assert_eq!(index_range(10, -3, 100), Some((7, 9)));
+        assert_eq!(index_range(10, 5, 3), None);
+        assert_eq!(index_range(10, -1, -5), None);
+        assert_eq!(index_range(10, 20, 30), None);
+        assert_eq!(index_range(0, 0, -1), None);
+        assert_eq!(list_index(3, -1), Some(2));
+        assert_eq!(list_index(3, -4), None);
+        assert_eq!(list_index(3, 3), None);
+    }
+
+    // 只在key不存在时写入的租约命令, 模拟下游注册的自定义命令
+    struct Lease {
+        key: Bytes,
+        owner: Bytes,
+    }
+
+    impl CommandHandler for Lease {
+        fn parse(args: &[RESP]) -> Result<Self, CmdError> {
+            let mut parser = CmdParser::new(args)?;
+            Ok(Lease {
+                key: parser.next_bytes()?,
+                owner: parser.next_bytes()?,
+            })
+        }
+
+        fn execute<'a>(
+            &'a self,
+            ctx: &'a mut Context<'_>,
+        ) -> BoxFuture<'a, Result<RESP, CmdError>> {
+            Box::pin(async move {
+                let mut shard = ctx.shard(&self.key).write().await;
+                if shard.contains_key(&self.key) {
+                    return Err(CmdError::Custom("ERR lease is held".to_string()));
+                }
+                shard.insert(
+                    self.key.clone(),
+                    (Value::Str(self.owner.clone()), u128::MAX),
+                );
+                Ok(RESP::Integer(1))
+            })
+        }
+    }
+
+    // 释放租约, 释放成功时同步为DEL, 未持有时不同步
+    struct Release {
+        key: Bytes,
+    }
+
+    impl CommandHandler for Release {
+        fn parse(args: &[RESP]) -> Result<Self, CmdError> {
+            let mut parser = CmdParser::new(args)?;
+            Ok(Release {
+                key: parser.next_bytes()?,
+            })
+        }
+
+        fn execute<'a>(
+            &'a self,
+            ctx: &'a mut Context<'_>,
+        ) -> BoxFuture<'a, Result<RESP, CmdError>> {
+            Box::pin(async move {
+                let removed = ctx.shard(&self.key).write().await.remove(&self.key);
+                if removed.is_none() {
+                    ctx.prevent_propagation();
+                    return Ok(RESP::Integer(0));
+                }
+                ctx.rewrite_propagation(RESP::Array(vec![
+                    RESP::new_bulk("DEL"),
+                    RESP::Bulk(self.key.clone()),
+                ]));
+                ctx.signal_key_ready(ctx.client.db, self.key.clone());
+                Ok(RESP::Integer(1))
+            })
+        }
+    }
+
+    fn frame(args: &[&'static str]) -> RESP {
+        RESP::Array(args.iter().map(|s| RESP::new_bulk(*s)).collect())
+    }
+
+    fn ok() -> RESP {
+        RESP::new_simple("OK".to_string())
+    }
+
+    fn bulks(items: &[&'static str]) -> RESP {
+        RESP::Array(items.iter().map(|s| RESP::new_bulk(*s)).collect())
+    }
+
+    // 执行一条命令, 返回回复和改写后的同步形式(None表示同步原命令)
+    async fn execute(
+        state: &ServerState,
+        client: &mut ClientState,
+        args: &[&'static str],
+    ) -> (RESP, Option<Vec<RESP>>) {
+        match state.parse_command(&frame(args)) {
+            Ok((command, _)) => {
+                let mut ctx = Context::new(state, client);
+                let response = run_command(command, &mut ctx).await;
+                (response, ctx.take_propagation())
+            }
+            Err(e) => (RESP::from(e), None),
+        }
+    }
+
+    // 与handle_client一样执行命令并同步
+    async fn handle(state: &ServerState, client: &mut ClientState, request: RESP) -> RESP {
+        match state.parse_command(&request) {
+            Ok((command, spec)) => {
+                let mut ctx = Context::new(state, client);
+                let response = run_command(command, &mut ctx).await;
+                propagate(ctx, spec, request, &response);
+                response
+            }
+            Err(e) => RESP::from(e),
+        }
+    }
+
+    // 一主一从: master上同步的命令(包括过期产生的DEL)按顺序在replica的master连接上执行
+    struct Replication {
+        master: Arc<ServerState>,
+        cmd_rx: CmdReceiver,
+        replica: ServerState,
+        client: ClientState,
+        link: ClientState,
+        // 已经在replica上执行的同步命令
+        propagated: Vec<RESP>,
+    }
+
+    impl Replication {
+        fn new() -> Self {
+            let Server { state, cmd_rx } = Server::new(Config::new());
+            let mut config = Config::new();
+            config.role = "slave".to_string();
+            let mut link = ClientState::new();
+            link.master_link = true;
+            Replication {
+                master: Arc::new(state),
+                cmd_rx,
+                replica: Server::new(config).state,
+                client: ClientState::new(),
+                link,
+                propagated: Vec::new(),
+            }
+        }
+
+        async fn master(&mut self, args: &[&'static str]) -> RESP {
+            let response = handle(&self.master, &mut self.client, frame(args)).await;
+            self.sync().await;
+            response
+        }
+
+        // 在新的连接上执行命令, 用于阻塞命令
+        fn spawn(&self, args: &[&'static str]) -> JoinHandle<RESP> {
+            let state = self.master.clone();
+            let request = frame(args);
+            tokio::spawn(async move { handle(&state, &mut ClientState::new(), request).await })
+        }
+
+        async fn sync(&mut self) {
+            while let Ok((db, frame)) = self.cmd_rx.try_recv() {
+                self.link.db = db;
+                let (command, _) = self.replica.parse_command(&frame).unwrap();
+                let mut ctx = Context::new(&self.replica, &mut self.link);
+                run_command(command, &mut ctx).await;
+                self.propagated.push(frame);
+            }
+        }
+
+        fn take_propagated(&mut self) -> Vec<RESP> {
+            std::mem::take(&mut self.propagated)
+        }
+
+        // 通过master连接读取replica上db中的数据, 已过期但未收到DEL的key仍然可见
+        async fn replica(&mut self, db: usize, args: &[&'static str]) -> RESP {
+            self.link.db = db;
+            let (command, _) = self.replica.parse_command(&frame(args)).unwrap();
+            let mut ctx = Context::new(&self.replica, &mut self.link);
+            run_command(command, &mut ctx).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_command() {
+        let mut server = Server::new(Config::new());
+        let spec = CommandSpec::new("lease", 3, CMD_WRITE, (1, 1, 1));
+        server.register_command::<Lease>(spec).unwrap();
+        assert!(server
+            .register_command::<Lease>(CommandSpec::new("get", 2, 0, (1, 1, 1)))
+            .is_err());
+
+        let state = server.state;
+        let mut client = ClientState::new();
+        assert_eq!(
+            state.parse_command(&frame(&["LEASE", "job"])).err(),
+            Some(CmdError::WrongArity("lease".to_string()))
+        );
+        for expected in [
+            RESP::Integer(1),
+            RESP::Error("ERR lease is held".to_string()),
+        ] {
+            let (command, spec) = state
+                .parse_command(&frame(&["LEASE", "job", "w1"]))
+                .unwrap();
+            assert!(spec.is_write());
+            let mut ctx = Context::new(&state, &mut client);
+            assert_eq!(run_command(command, &mut ctx).await, expected);
+        }
+        let (command, _) = state.parse_command(&frame(&["get", "job"])).unwrap();
+        let mut ctx = Context::new(&state, &mut client);
+        assert_eq!(run_command(command, &mut ctx).await, RESP::new_bulk("w1"));
+        let (command, _) = state
+            .parse_command(&frame(&["command", "info", "lease"]))
+            .unwrap();
+        let mut ctx = Context::new(&state, &mut client);
+        match run_command(command, &mut ctx).await {
+            RESP::Array(infos) => assert!(
+                matches!(&infos[0], RESP::Array(info) if info[0] == RESP::new_bulk("lease"))
+            ),
+            reply => panic!("unexpected reply: {:?}", reply),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_command_context() {
+        let mut server = Server::new(Config::new());
+        server
+            .register_command::<Lease>(CommandSpec::new("lease", 3, CMD_WRITE, (1, 1, 1)))
+            .unwrap();
+        server
+            .register_command::<Release>(CommandSpec::new("release", 2, CMD_WRITE, (1, 1, 1)))
+            .unwrap();
+        // 自定义命令之间也不能重名, 命令名须为小写
+        assert!(server
+            .register_command::<Release>(CommandSpec::new("lease", 2, CMD_WRITE, (1, 1, 1)))
+            .is_err());
+        assert!(server
+            .register_command::<Release>(CommandSpec::new("Unlock", 2, CMD_WRITE, (1, 1, 1)))
+            .is_err());
+
+        let state = server.state;
+        let mut client = ClientState::new();
+        let (command, _) = state
+            .parse_command(&frame(&["lease", "job", "w1"]))
+            .unwrap();
+        let mut ctx = Context::new(&state, &mut client);
+        assert_eq!(run_command(command, &mut ctx).await, RESP::Integer(1));
+        // 没有改写时同步原命令
+        assert_eq!(ctx.take_propagation(), None);
+
+        let (command, spec) = state.parse_command(&frame(&["RELEASE", "job"])).unwrap();
+        assert!(spec.is_write());
+        let mut ctx = Context::new(&state, &mut client);
+        assert_eq!(run_command(command, &mut ctx).await, RESP::Integer(1));
+        assert_eq!(ctx.take_propagation(), Some(vec![frame(&["DEL", "job"])]));
+        assert_eq!(ctx.take_ready_keys(), vec![(0, Bytes::from("job"))]);
+
+        let (command, _) = state.parse_command(&frame(&["release", "job"])).unwrap();
+        let mut ctx = Context::new(&state, &mut client);
+        assert_eq!(run_command(command, &mut ctx).await, RESP::Integer(0));
+        assert_eq!(ctx.take_propagation(), Some(vec![]));
+        assert!(ctx.take_ready_keys().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_expire_overflow() {
+        let state = Server::new(Config::new()).state;
+        let mut client = ClientState::new();
+        let invalid = |name: &str| RESP::from(CmdError::InvalidExpireTime(name.to_string()));
+        let max = "9223372036854775807";
+        for (args, name) in [
+            (&["set", "k", "v", "px", max][..], "set"),
+            (&["psetex", "k", max, "v"], "psetex"),
+        ] {
+            assert_eq!(execute(&state, &mut client, args).await.0, invalid(name));
+        }
+        assert_eq!(
+            execute(&state, &mut client, &["set", "k", "v"]).await.0,
+            ok()
+        );
+        assert_eq!(
+            execute(&state, &mut client, &["getex", "k", "px", max])
+                .await
+                .0,
+            invalid("getex")
+        );
+        assert_eq!(
+            execute(&state, &mut client, &["pexpire", "k", max]).await.0,
+            invalid("pexpire")
+        );
+        // 绝对时间只要求本身能表示为i64
+        assert_eq!(
+            execute(&state, &mut client, &["pexpireat", "k", max]).await,
+            (
+                RESP::Integer(1),
+                Some(vec![frame(&["PEXPIREAT", "k", max])])
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incr_execution() {
+        let state = Server::new(Config::new()).state;
+        let mut client = ClientState::new();
+        execute(&state, &mut client, &["set", "n", "10", "px", "100000"]).await;
+        assert_eq!(
+            execute(&state, &mut client, &["incrby", "n", "5"]).await,
+            (RESP::Integer(15), None)
+        );
+        // 浮点运算的结果以SET同步, 保留原来的过期时间
+        assert_eq!(
+            execute(&state, &mut client, &["incrbyfloat", "n", "1.5"]).await,
+            (
+                RESP::new_bulk("16.5"),
+                Some(vec![frame(&["SET", "n", "16.5", "KEEPTTL"])])
+            )
+        );
+        assert_eq!(
+            execute(&state, &mut client, &["incr", "n"]).await.0,
+            RESP::from(CmdError::NotInteger)
+        );
+        execute(&state, &mut client, &["set", "f", "1e308"]).await;
+        assert_eq!(
+            execute(&state, &mut client, &["incrbyfloat", "f", "1e308"])
+                .await
+                .0,
+            RESP::from(CmdError::NanOrInfinity)
+        );
+        assert_eq!(
+            execute(&state, &mut client, &["get", "f"]).await.0,
+            RESP::new_bulk("1e308")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expire_execution() {
+        let state = Server::new(Config::new()).state;
+        let mut client = ClientState::new();
+        execute(&state, &mut client, &["set", "k", "v"]).await;
+        // 相对时间以PEXPIREAT同步, replica上的过期时间与master相同
+        let (reply, propagation) = execute(&state, &mut client, &["expire", "k", "100"]).await;
+        assert_eq!(reply, RESP::Integer(1));
+        let deadline = match execute(&state, &mut client, &["pexpiretime", "k"]).await.0 {
+            RESP::Integer(deadline) => deadline.to_string(),
+            reply => panic!("unexpected reply: {:?}", reply),
+        };
+        assert_eq!(
+            propagation,
+            Some(vec![RESP::Array(vec![
+                RESP::new_bulk("PEXPIREAT"),
+                RESP::new_bulk("k"),
+                RESP::new_bulk(deadline),
+            ])])
+        );
+        assert_eq!(
+            execute(&state, &mut client, &["expire", "k", "200", "nx"]).await,
+            (RESP::Integer(0), Some(vec![]))
+        );
+        assert_eq!(
+            execute(&state, &mut client, &["pexpire", "k", "0"]).await,
+            (RESP::Integer(1), Some(vec![frame(&["DEL", "k"])]))
+        );
+        assert_eq!(
+            execute(&state, &mut client, &["exists", "k"]).await.0,
+            RESP::Integer(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_destination_replication() {
+        let mut repl = Replication::new();
+        for key in ["moved", "copied", "renamed", "setnx", "set", "msetnx"] {
+            repl.master(&["set", key, "stale", "px", "1"]).await;
+        }
+        repl.master(&["select", "1"]).await;
+        repl.master(&["set", "moved", "stale", "px", "1"]).await;
+        repl.master(&["select", "0"]).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        // master上已过期的目标key视为不存在, replica需要先收到DEL才能得到相同的结果
+        repl.master(&["set", "src", "v"]).await;
+        assert_eq!(repl.master(&["move", "moved", "1"]).await, RESP::Integer(0));
+        repl.master(&["set", "moved", "v"]).await;
+        assert_eq!(repl.master(&["move", "moved", "1"]).await, RESP::Integer(1));
+        assert_eq!(
+            repl.master(&["copy", "src", "copied"]).await,
+            RESP::Integer(1)
+        );
+        repl.master(&["set", "from", "v"]).await;
+        assert_eq!(
+            repl.master(&["renamenx", "from", "renamed"]).await,
+            RESP::Integer(1)
+        );
+        assert_eq!(
+            repl.master(&["setnx", "setnx", "v"]).await,
+            RESP::Integer(1)
+        );
+        assert_eq!(
+            repl.master(&["set", "set", "v", "nx"]).await,
+            RESP::new_simple("OK".to_string())
+        );
+        assert_eq!(
+            repl.master(&["msetnx", "msetnx", "v", "other", "v"]).await,
+            RESP::Integer(1)
+        );
+
+        assert_eq!(
+            repl.replica(1, &["get", "moved"]).await,
+            RESP::new_bulk("v")
+        );
+        assert_eq!(
+            repl.replica(0, &["exists", "moved", "from"]).await,
+            RESP::Integer(0)
+        );
+        for key in ["copied", "renamed", "setnx", "set", "msetnx"] {
+            assert_eq!(repl.replica(0, &["get", key]).await, RESP::new_bulk("v"));
+        }
+        // 7个过期的key都按过期处理计数
+        match repl.master(&["info", "stats"]).await {
+            RESP::Bulk(info) => assert!(info.ends_with(b"expired_keys:7\r\n")),
+            reply => panic!("unexpected reply: {:?}", reply),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_locks_all_shards() {
+        let state = Arc::new(Server::new(Config::new()).state);
+        let db = state.dbs[0].clone();
+        for shard in [0, db.len() - 1] {
+            db[shard]
+                .write()
+                .await
+                .insert(Bytes::from(shard.to_string()), (Value::Int(0), u128::MAX));
+        }
+        let last = db[db.len() - 1].write().await;
+        let flush = tokio::spawn({
+            let state = state.clone();
+            async move {
+                let (command, _) = state.parse_command(&frame(&["flushdb"])).unwrap();
+                let mut client = ClientState::new();
+                let mut ctx = Context::new(&state, &mut client);
+                run_command(command, &mut ctx).await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // 等待最后一个分片时已经锁住了前面的分片, 但还没有清空任何数据
+        assert!(db[0].try_read().is_err());
+        assert_eq!(last.len(), 1);
+        drop(last);
+        assert_eq!(flush.await.unwrap(), RESP::new_simple("OK".to_string()));
+        for shard in db.iter() {
+            assert!(shard.read().await.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_swapdb_move_replication() {
+        let mut repl = Replication::new();
+        repl.master(&["set", "a", "1"]).await;
+        assert_eq!(repl.master(&["move", "a", "1"]).await, RESP::Integer(1));
+        repl.master(&["select", "1"]).await;
+        repl.master(&["set", "b", "2"]).await;
+        assert_eq!(repl.master(&["swapdb", "0", "1"]).await, ok());
+        // 当前连接仍在db 1, 交换后为空
+        assert_eq!(repl.master(&["dbsize"]).await, RESP::Integer(0));
+        assert_eq!(
+            repl.replica(0, &["mget", "a", "b"]).await,
+            bulks(&["1", "2"])
+        );
+        assert_eq!(repl.replica(1, &["dbsize"]).await, RESP::Integer(0));
+        repl.master(&["select", "0"]).await;
+        assert_eq!(repl.master(&["flushdb"]).await, ok());
+        assert_eq!(repl.replica(0, &["dbsize"]).await, RESP::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_list_execution() {
+        let mut repl = Replication::new();
+        repl.master(&["set", "s", "v"]).await;
+        assert_eq!(
+            repl.master(&["lpush", "s", "a"]).await,
+            RESP::from(CmdError::WrongType)
+        );
+        assert_eq!(
+            repl.master(&["rpush", "l", "a", "b", "c"]).await,
+            RESP::Integer(3)
+        );
+        assert_eq!(repl.master(&["lpop", "l", "2"]).await, bulks(&["a", "b"]));
+        assert_eq!(repl.master(&["rpop", "l"]).await, RESP::new_bulk("c"));
+        // 弹出最后一个元素后删除key
+        assert_eq!(repl.master(&["exists", "l"]).await, RESP::Integer(0));
+        assert_eq!(repl.replica(0, &["exists", "l"]).await, RESP::Integer(0));
+        assert_eq!(
+            repl.take_propagated(),
+            vec![
+                frame(&["SET", "s", "v"]),
+                frame(&["rpush", "l", "a", "b", "c"]),
+                frame(&["lpop", "l", "2"]),
+                frame(&["rpop", "l"]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocking_wake_order() {
+        let mut repl = Replication::new();
+        let first = repl.spawn(&["blpop", "q", "1"]);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let second = repl.spawn(&["brpop", "q", "1"]);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // 先阻塞的客户端先被唤醒
+        repl.master(&["rpush", "q", "a"]).await;
+        assert_eq!(first.await.unwrap(), bulks(&["q", "a"]));
+        assert!(!second.is_finished());
+        repl.master(&["rpush", "q", "b", "c"]).await;
+        assert_eq!(second.await.unwrap(), bulks(&["q", "c"]));
+        repl.sync().await;
+        // 阻塞弹出以非阻塞的形式同步, 且在唤醒它的写入之后
+        assert_eq!(
+            repl.take_propagated(),
+            vec![
+                frame(&["rpush", "q", "a"]),
+                frame(&["LPOP", "q"]),
+                frame(&["rpush", "q", "b", "c"]),
+                frame(&["RPOP", "q"]),
+            ]
+        );
+        assert_eq!(
+            repl.replica(0, &["lrange", "q", "0", "-1"]).await,
+            bulks(&["b"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hash_execution() {
+        let mut repl = Replication::new();
+        repl.master(&["set", "s", "v"]).await;
+        assert_eq!(
+            repl.master(&["hset", "s", "f", "v"]).await,
+            RESP::from(CmdError::WrongType)
+        );
+        assert_eq!(
+            repl.master(&["hset", "h", "f", "1", "g", "2"]).await,
+            RESP::Integer(2)
+        );
+        assert_eq!(
+            repl.master(&["hincrbyfloat", "h", "f", "0.5"]).await,
+            RESP::new_bulk("1.5")
+        );
+        assert_eq!(
+            repl.replica(0, &["hget", "h", "f"]).await,
+            RESP::new_bulk("1.5")
+        );
+        // 删除最后一个字段后删除key
+        assert_eq!(
+            repl.master(&["hdel", "h", "f", "g"]).await,
+            RESP::Integer(2)
+        );
+        assert_eq!(repl.master(&["exists", "h"]).await, RESP::Integer(0));
+        assert_eq!(repl.replica(0, &["exists", "h"]).await, RESP::Integer(0));
+        assert_eq!(
+            repl.take_propagated(),
+            vec![
+                frame(&["SET", "s", "v"]),
+                frame(&["hset", "h", "f", "1", "g", "2"]),
+                frame(&["HSET", "h", "f", "1.5"]),
+                frame(&["hdel", "h", "f", "g"]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_execution() {
+        let mut repl = Replication::new();
+        repl.master(&["set", "s", "v"]).await;
+        assert_eq!(
+            repl.master(&["sadd", "s", "a"]).await,
+            RESP::from(CmdError::WrongType)
+        );
+        repl.master(&["sadd", "k", "a", "b"]).await;
+        // SPOP以SREM同步弹出的成员, 弹空后删除key
+        let popped = match repl.master(&["spop", "k", "2"]).await {
+            RESP::Array(popped) | RESP::Set(popped) => popped,
+            reply => panic!("unexpected reply: {:?}", reply),
+        };
+        assert_eq!(popped.len(), 2);
+        assert_eq!(repl.master(&["exists", "k"]).await, RESP::Integer(0));
+        assert_eq!(repl.replica(0, &["exists", "k"]).await, RESP::Integer(0));
+        let mut srem = vec![RESP::new_bulk("SREM"), RESP::new_bulk("k")];
+        srem.extend(popped);
+        assert_eq!(
+            repl.take_propagated(),
+            vec![
+                frame(&["SET", "s", "v"]),
+                frame(&["sadd", "k", "a", "b"]),
+                RESP::Array(srem),
+            ]
+        );
+        repl.master(&["sadd", "k", "a"]).await;
+        assert_eq!(repl.master(&["srem", "k", "a"]).await, RESP::Integer(1));
+        assert_eq!(repl.replica(0, &["exists", "k"]).await, RESP::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_set_store_execution() {
+        let mut repl = Replication::new();
+        repl.master(&["sadd", "a", "x"]).await;
+        repl.master(&["sadd", "b", "y"]).await;
+        repl.master(&["sadd", "dst", "z"]).await;
+        repl.take_propagated();
+        // 结果为空时删除目标key, 同步为DEL
+        assert_eq!(
+            repl.master(&["sinterstore", "dst", "a", "b"]).await,
+            RESP::Integer(0)
+        );
+        assert_eq!(
+            repl.master(&["sinterstore", "dst", "a", "b"]).await,
+            RESP::Integer(0)
+        );
+        assert_eq!(repl.take_propagated(), vec![frame(&["DEL", "dst"])]);
+        assert_eq!(repl.replica(0, &["exists", "dst"]).await, RESP::Integer(0));
+        repl.master(&["set", "s", "v"]).await;
+        assert_eq!(
+            repl.master(&["sunionstore", "dst", "a", "s"]).await,
+            RESP::from(CmdError::WrongType)
+        );
+        assert_eq!(
+            repl.master(&["sunionstore", "dst", "a", "b"]).await,
+            RESP::Integer(2)
+        );
+        assert_eq!(repl.replica(0, &["scard", "dst"]).await, RESP::Integer(2));
+    }
+
+    #[tokio::test]
+    async fn test_zset_execution() {
+        let mut repl = Replication::new();
+        repl.master(&["set", "s", "v"]).await;
+        assert_eq!(
+            repl.master(&["zadd", "s", "1", "a"]).await,
+            RESP::from(CmdError::WrongType)
+        );
+        repl.master(&["zadd", "z", "1", "a", "2", "b"]).await;
+        assert_eq!(
+            repl.master(&["zincrby", "z", "1.5", "a"]).await,
+            RESP::Double(2.5)
+        );
+        assert_eq!(
+            repl.replica(0, &["zscore", "z", "a"]).await,
+            RESP::Double(2.5)
+        );
+        // 删除最后一个成员后删除key
+        assert_eq!(
+            repl.master(&["zrem", "z", "a", "b"]).await,
+            RESP::Integer(2)
+        );
+        assert_eq!(repl.master(&["exists", "z"]).await, RESP::Integer(0));
+        assert_eq!(repl.replica(0, &["exists", "z"]).await, RESP::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_zset_pop_execution() {
+        let mut repl = Replication::new();
+        repl.master(&["zadd", "z", "1", "a", "2", "b", "3", "c"])
+            .await;
+        let blocked = repl.spawn(&["bzpopmax", "empty", "z", "1"]);
+        assert_eq!(
+            blocked.await.unwrap(),
+            RESP::Array(vec![
+                RESP::new_bulk("z"),
+                RESP::new_bulk("c"),
+                RESP::Double(3.0)
+            ])
+        );
+        repl.master(&["zpopmin", "z", "2"]).await;
+        repl.sync().await;
+        assert_eq!(
+            repl.take_propagated(),
+            vec![
+                frame(&["zadd", "z", "1", "a", "2", "b", "3", "c"]),
+                frame(&["ZPOPMAX", "z"]),
+                frame(&["zpopmin", "z", "2"]),
+            ]
+        );
+        assert_eq!(repl.replica(0, &["exists", "z"]).await, RESP::Integer(0));
+        // 与SINTERSTORE一样, 结果为空时删除目标key
+        repl.master(&["zadd", "dst", "1", "x"]).await;
+        assert_eq!(
+            repl.master(&["zunionstore", "dst", "1", "z"]).await,
+            RESP::Integer(0)
+        );
+        assert_eq!(repl.replica(0, &["exists", "dst"]).await, RESP::Integer(0));
+        repl.master(&["set", "s", "v"]).await;
+        assert_eq!(
+            repl.master(&["zunionstore", "dst", "1", "s"]).await,
+            RESP::from(CmdError::WrongType)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_execution() {
+        let mut repl = Replication::new();
+        repl.master(&["set", "s", "v"]).await;
+        assert_eq!(
+            repl.master(&["xadd", "s", "*", "f", "v"]).await,
+            RESP::from(CmdError::WrongType)
+        );
+        repl.take_propagated();
+        // 自动生成的ID以具体的ID同步, 近似裁剪改写为精确的MAXLEN
+        let id = match repl
+            .master(&["xadd", "x", "maxlen", "~", "0", "*", "f", "v"])
+            .await
+        {
+            RESP::Bulk(id) => id,
+            reply => panic!("unexpected reply: {:?}", reply),
+        };
+        let mut xadd = frame(&["XADD", "x", "MAXLEN", "=", "1"]);
+        if let RESP::Array(args) = &mut xadd {
+            args.push(RESP::Bulk(id.clone()));
+            args.extend([RESP::new_bulk("f"), RESP::new_bulk("v")]);
+        }
+        assert_eq!(repl.take_propagated(), vec![xadd]);
+        assert_eq!(repl.replica(0, &["xlen", "x"]).await, RESP::Integer(1));
+        match repl.replica(0, &["xrange", "x", "-", "+"]).await {
+            RESP::Array(entries) => {
+                assert!(matches!(&entries[0], RESP::Array(entry) if entry[0] == RESP::Bulk(id)))
+            }
+            reply => panic!("unexpected reply: {:?}", reply),
+        }
     }
-    Ok(())
 }
@@ -0,0 +1,211 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use crate::db::parse_i64_strict;
+
+/// 集合类型; 成员都是整数且个数较少时存为有序的整数数组(类似intset), 二分查找且更省内存,
+/// 出现非整数成员或者超过阈值后转换为HashSet, 之后不会再转换回来
+#[derive(Debug, Clone, PartialEq)]
+pub enum Set {
+    IntSet(Vec<i64>),
+    Table(HashSet<Bytes>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::IntSet(Vec::new())
+    }
+}
+
+impl Set {
+    pub fn len(&self) -> usize {
+        match self {
+            Set::IntSet(ints) => ints.len(),
+            Set::Table(set) => set.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// OBJECT ENCODING中的编码名
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Set::IntSet(_) => "intset",
+            Set::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => {
+                parse_i64_strict(member).is_some_and(|n| ints.binary_search(&n).is_ok())
+            }
+            Set::Table(set) => set.contains(member),
+        }
+    }
+
+    /// 添加成员, 返回是否为新成员; max_intset为整数编码的最大成员数
+    pub fn insert(&mut self, member: Bytes, max_intset: usize) -> bool {
+        if let Set::IntSet(ints) = self {
+            if let Some(n) = parse_i64_strict(&member) {
+                match ints.binary_search(&n) {
+                    Ok(_) => return false,
+                    Err(i) if ints.len() < max_intset => {
+                        ints.insert(i, n);
+                        return true;
+                    }
+                    Err(_) => {}
+                }
+            }
+            let members = ints.iter().map(|n| Bytes::from(n.to_string())).collect();
+            *self = Set::Table(members);
+        }
+        match self {
+            Set::Table(set) => set.insert(member),
+            Set::IntSet(_) => unreachable!(),
+        }
+    }
+
+    /// 删除成员, 返回成员是否存在
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => match parse_i64_strict(member).map(|n| ints.binary_search(&n)) {
+                Some(Ok(i)) => {
+                    ints.remove(i);
+                    true
+                }
+                _ => false,
+            },
+            Set::Table(set) => set.remove(member),
+        }
+    }
+
+    /// 所有成员, 整数编码的成员需要转换为字符串, 因此返回拥有所有权的Bytes
+    pub fn members(&self) -> Vec<Bytes> {
+        match self {
+            Set::IntSet(ints) => ints.iter().map(|n| Bytes::from(n.to_string())).collect(),
+            Set::Table(set) => set.iter().cloned().collect(),
+        }
+    }
+}
+
+/// SINTER/SUNION/SDIFF及其STORE变体的运算
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// 对多个集合做运算, 不存在的key为None, 视为空集合; limit限制结果个数, 0表示不限制
+pub fn combine(op: SetOp, sets: &[Option<&Set>], limit: usize) -> Vec<Bytes> {
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    match op {
+        SetOp::Inter => {
+            let Some(mut sets) = sets.iter().copied().collect::<Option<Vec<_>>>() else {
+                return vec![];
+            };
+            // 遍历最小的集合, 在其余集合中查找
+            sets.sort_by_key(|set| set.len());
+            match sets.split_first() {
+                Some((first, rest)) => first
+                    .members()
+                    .into_iter()
+                    .filter(|member| rest.iter().all(|set| set.contains(member)))
+                    .take(limit)
+                    .collect(),
+                None => vec![],
+            }
+        }
+        SetOp::Union => {
+            let mut seen = HashSet::new();
+            sets.iter()
+                .flatten()
+                .flat_map(|set| set.members())
+                .filter(|member| seen.insert(member.clone()))
+                .take(limit)
+                .collect()
+        }
+        SetOp::Diff => match sets.split_first() {
+            Some((Some(first), rest)) => first
+                .members()
+                .into_iter()
+                .filter(|member| !rest.iter().flatten().any(|set| set.contains(member)))
+                .take(limit)
+                .collect(),
+            _ => vec![],
+        },
+    }
+}
+
+#[cfg(test)]
+mod set_test {
+    use super::*;
+
+    #[test]
+    fn test_encoding_conversion() {
+        let b = |s: &'static str| Bytes::from(s);
+        let mut set = Set::default();
+        assert!(set.insert(b("3"), 3));
+        assert!(set.insert(b("-1"), 3));
+        assert!(!set.insert(b("3"), 3));
+        assert_eq!(set, Set::IntSet(vec![-1, 3]));
+        // "03"不是整数的规范形式
+        assert!(!set.contains(b"03") && set.contains(b"3"));
+        assert!(set.remove(b"-1") && !set.remove(b"x"));
+        // 非整数成员
+        assert!(set.insert(b("a"), 3));
+        assert_eq!(set.encoding(), "hashtable");
+        assert!(set.contains(b"3") && set.contains(b"a"));
+        // 删除后也不会转换回来
+        set.remove(b"a");
+        assert_eq!(set.encoding(), "hashtable");
+
+        // 超过成员个数
+        let mut set = Set::default();
+        for n in ["1", "2", "3"] {
+            set.insert(b(n), 2);
+        }
+        assert_eq!(set.encoding(), "hashtable");
+        let mut members = set.members();
+        members.sort();
+        assert_eq!(members, vec![b("1"), b("2"), b("3")]);
+    }
+
+    #[test]
+    fn test_combine() {
+        let set = |members: &[&'static str]| {
+            let mut set = Set::default();
+            for member in members {
+                set.insert(Bytes::from(*member), 512);
+            }
+            set
+        };
+        let sorted = |mut members: Vec<Bytes>| {
+            members.sort();
+            members
+        };
+        let (a, b, c) = (
+            set(&["1", "2", "3", "x"]),
+            set(&["2", "3", "y"]),
+            set(&["3"]),
+        );
+        let sets = [Some(&a), Some(&b), None];
+        assert!(combine(SetOp::Inter, &sets, 0).is_empty());
+        assert_eq!(combine(SetOp::Inter, &[Some(&a), Some(&b)], 0).len(), 2);
+        assert_eq!(combine(SetOp::Inter, &[Some(&a), Some(&b)], 1).len(), 1);
+        assert_eq!(
+            combine(SetOp::Inter, &[Some(&a), Some(&b), Some(&c)], 0),
+            vec![Bytes::from("3")]
+        );
+        assert_eq!(combine(SetOp::Union, &sets, 0).len(), 5);
+        assert_eq!(
+            sorted(combine(SetOp::Diff, &[Some(&a), None, Some(&b)], 0)),
+            vec![Bytes::from("1"), Bytes::from("x")]
+        );
+        assert!(combine(SetOp::Diff, &[None, Some(&a)], 0).is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{collections::BTreeMap, fmt};
+
+use bytes::Bytes;
+
+use crate::error::CmdError;
+
+/// 消息ID, 由毫秒时间戳和同一毫秒内的序号组成, 按(ms, seq)排序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+// 只允许数字, 不接受符号和空格
+fn parse_u64(src: &[u8]) -> Option<u64> {
+    if src.is_empty() || !src.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(src).ok()?.parse().ok()
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// 解析ms-seq格式的ID, 省略seq时使用missing_seq
+    pub fn parse(src: &[u8], missing_seq: u64) -> Result<Self, CmdError> {
+        let (ms, seq) = match src.iter().position(|c| *c == b'-') {
+            Some(i) => (parse_u64(&src[..i]), parse_u64(&src[i + 1..])),
+            None => (parse_u64(src), Some(missing_seq)),
+        };
+        match (ms, seq) {
+            (Some(ms), Some(seq)) => Ok(StreamId { ms, seq }),
+            _ => Err(CmdError::InvalidStreamId),
+        }
+    }
+
+    /// XRANGE等的区间端点, "-"和"+"表示最小和最大ID, "("表示不包含该ID;
+    /// 返回包含在区间内的端点, 起点省略的seq为0, 终点省略的seq为最大值
+    pub fn parse_bound(src: &[u8], start: bool) -> Result<Self, CmdError> {
+        let missing_seq = if start { 0 } else { u64::MAX };
+        match src {
+            b"-" => Ok(StreamId::MIN),
+            b"+" => Ok(StreamId::MAX),
+            [b'(', id @ ..] if !id.is_empty() => {
+                let id = StreamId::parse(id, missing_seq)?;
+                let (bound, next) = if start {
+                    ("start", id.next())
+                } else {
+                    ("end", id.prev())
+                };
+                next.ok_or_else(|| CmdError::InvalidStreamInterval(bound.to_string()))
+            }
+            _ => StreamId::parse(src, missing_seq),
+        }
+    }
+
+    pub fn next(self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self.ms.checked_add(1).map(|ms| StreamId { ms, seq: 0 }),
+        }
+    }
+
+    pub fn prev(self) -> Option<Self> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self
+                .ms
+                .checked_sub(1)
+                .map(|ms| StreamId { ms, seq: u64::MAX }),
+        }
+    }
+}
+
+/// XADD的ID参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XAddId {
+    // *, 由当前时间生成
+    Auto,
+    // ms-*, 只自动生成序号
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+impl XAddId {
+    pub fn parse(src: &[u8]) -> Result<Self, CmdError> {
+        match src {
+            b"*" => Ok(XAddId::Auto),
+            [ms @ .., b'-', b'*'] => parse_u64(ms)
+                .map(XAddId::AutoSeq)
+                .ok_or(CmdError::InvalidStreamId),
+            _ => match StreamId::parse(src, 0)? {
+                StreamId::MIN => Err(CmdError::XAddIdZero),
+                id => Ok(XAddId::Explicit(id)),
+            },
+        }
+    }
+}
+
+/// 裁剪策略: MAXLEN保留最新的若干条消息, MINID删除ID小于阈值的消息
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// MAXLEN | MINID [= | ~] threshold [LIMIT count]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trim {
+    pub strategy: TrimStrategy,
+    // ~, 只删除整个节点的消息
+    pub approx: bool,
+    // 近似裁剪最多删除的消息数, None为默认值, 0表示不限制
+    pub limit: Option<usize>,
+}
+
+pub type StreamEntry = Vec<(Bytes, Bytes)>;
+
+/// 消息流, 按ID有序存放消息的字段和值; 消息被删光后key依然存在
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, StreamEntry>,
+    // 最后添加的ID, 删除消息后也不会变小, 保证新的ID单调递增
+    last_id: StreamId,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// 为XADD确定新消息的ID, 必须大于最后添加的ID; now_millis为当前时间
+    pub fn next_id(&self, id: XAddId, now_millis: u64) -> Result<StreamId, CmdError> {
+        let last = self.last_id;
+        if last == StreamId::MAX {
+            return Err(CmdError::StreamExhausted);
+        }
+        match id {
+            XAddId::Auto if now_millis > last.ms => Ok(StreamId {
+                ms: now_millis,
+                seq: 0,
+            }),
+            // 时钟回拨时沿用最后的时间戳
+            XAddId::Auto => last.next().ok_or(CmdError::StreamExhausted),
+            XAddId::AutoSeq(ms) if ms > last.ms => Ok(StreamId { ms, seq: 0 }),
+            XAddId::AutoSeq(ms) if ms == last.ms && last.seq != u64::MAX => Ok(StreamId {
+                ms,
+                seq: last.seq + 1,
+            }),
+            XAddId::Explicit(id) if id > last => Ok(id),
+            _ => Err(CmdError::XAddIdTooSmall),
+        }
+    }
+
+    /// 添加消息, id须由next_id得到
+    pub fn add(&mut self, id: StreamId, fields: StreamEntry) {
+        self.entries.insert(id, fields);
+        self.last_id = id;
+    }
+
+    pub fn remove(&mut self, id: &StreamId) -> bool {
+        self.entries.remove(id).is_some()
+    }
+
+    /// [start, end]内的消息, 最多count条
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        rev: bool,
+        count: usize,
+    ) -> Vec<(StreamId, &StreamEntry)> {
+        if start > end {
+            return vec![];
+        }
+        let iter = self
+            .entries
+            .range(start..=end)
+            .map(|(id, entry)| (*id, entry));
+        if rev {
+            iter.rev().take(count).collect()
+        } else {
+            iter.take(count).collect()
+        }
+    }
+
+    /// 从最旧的消息开始裁剪, 返回删除的消息数; node_max_entries为每个节点的消息数,
+    /// 近似裁剪与redis按整个节点删除类似, 只删除节点大小整数倍的消息
+    pub fn trim(&mut self, trim: &Trim, node_max_entries: usize) -> usize {
+        let mut count = match trim.strategy {
+            TrimStrategy::MaxLen(maxlen) => self.len().saturating_sub(maxlen),
+            TrimStrategy::MinId(minid) => self.entries.range(..minid).count(),
+        };
+        if trim.approx {
+            // 节点大小为0时每个消息视为一个节点
+            let node = node_max_entries.max(1);
+            let limit = match trim.limit {
+                None => node.saturating_mul(100),
+                Some(0) => usize::MAX,
+                Some(limit) => limit,
+            };
+            count = count.min(limit);
+            count -= count % node;
+        }
+        for _ in 0..count {
+            self.entries.pop_first();
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod stream_test {
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(StreamId::parse(b"5-3", 0), Ok(id(5, 3)));
+        assert_eq!(StreamId::parse(b"5", 7), Ok(id(5, 7)));
+        for src in [&b"5-"[..], b"-1", b"+5", b"a-1", b"5-1-1", b""] {
+            assert_eq!(StreamId::parse(src, 0), Err(CmdError::InvalidStreamId));
+        }
+        assert_eq!(StreamId::parse_bound(b"-", false), Ok(StreamId::MIN));
+        assert_eq!(StreamId::parse_bound(b"5", false), Ok(id(5, u64::MAX)));
+        assert_eq!(StreamId::parse_bound(b"(5", true), Ok(id(5, 1)));
+        assert_eq!(StreamId::parse_bound(b"(5-0", false), Ok(id(4, u64::MAX)));
+        assert_eq!(
+            StreamId::parse_bound(b"(0-0", false),
+            Err(CmdError::InvalidStreamInterval("end".to_string()))
+        );
+        assert_eq!(
+            StreamId::parse_bound(b"(+", true),
+            Err(CmdError::InvalidStreamId)
+        );
+        assert_eq!(XAddId::parse(b"7-*"), Ok(XAddId::AutoSeq(7)));
+        assert_eq!(XAddId::parse(b"0"), Err(CmdError::XAddIdZero));
+        assert_eq!(XAddId::parse(b"0-1"), Ok(XAddId::Explicit(id(0, 1))));
+    }
+
+    #[test]
+    fn test_next_id() {
+        let mut stream = Stream::default();
+        assert_eq!(stream.next_id(XAddId::AutoSeq(0), 100), Ok(id(0, 1)));
+        assert_eq!(stream.next_id(XAddId::Auto, 100), Ok(id(100, 0)));
+        stream.add(id(100, 5), vec![]);
+        // 时钟回拨
+        assert_eq!(stream.next_id(XAddId::Auto, 50), Ok(id(100, 6)));
+        assert_eq!(stream.next_id(XAddId::AutoSeq(100), 0), Ok(id(100, 6)));
+        assert_eq!(
+            stream.next_id(XAddId::AutoSeq(99), 0),
+            Err(CmdError::XAddIdTooSmall)
+        );
+        assert_eq!(
+            stream.next_id(XAddId::Explicit(id(100, 5)), 0),
+            Err(CmdError::XAddIdTooSmall)
+        );
+        // 删除消息后ID依然单调递增
+        stream.remove(&id(100, 5));
+        assert!(stream.is_empty());
+        assert_eq!(
+            stream.next_id(XAddId::Explicit(id(100, 1)), 0),
+            Err(CmdError::XAddIdTooSmall)
+        );
+        stream.add(id(u64::MAX, u64::MAX - 1), vec![]);
+        assert_eq!(stream.next_id(XAddId::Auto, 0), Ok(StreamId::MAX));
+        stream.add(StreamId::MAX, vec![]);
+        assert_eq!(
+            stream.next_id(XAddId::Auto, 0),
+            Err(CmdError::StreamExhausted)
+        );
+    }
+
+    #[test]
+    fn test_range_and_trim() {
+        let mut stream = Stream::default();
+        for ms in 1..=10 {
+            stream.add(id(ms, 0), vec![]);
+        }
+        let ids = |items: Vec<(StreamId, &StreamEntry)>| -> Vec<u64> {
+            items.into_iter().map(|(id, _)| id.ms).collect()
+        };
+        assert_eq!(
+            ids(stream.range(id(3, 0), id(5, u64::MAX), false, usize::MAX)),
+            vec![3, 4, 5]
+        );
+        assert_eq!(
+            ids(stream.range(StreamId::MIN, StreamId::MAX, true, 2)),
+            vec![10, 9]
+        );
+        assert!(stream.range(id(5, 0), id(3, 0), false, 10).is_empty());
+
+        let trim = |strategy, approx, limit| Trim {
+            strategy,
+            approx,
+            limit,
+        };
+        // 近似裁剪只删除整个节点
+        assert_eq!(
+            stream.trim(&trim(TrimStrategy::MaxLen(5), true, None), 3),
+            3
+        );
+        assert_eq!(stream.len(), 7);
+        assert_eq!(
+            stream.trim(&trim(TrimStrategy::MaxLen(0), true, Some(2)), 1),
+            2
+        );
+        assert_eq!(
+            stream.trim(&trim(TrimStrategy::MinId(id(8, 0)), false, None), 3),
+            2
+        );
+        assert_eq!(
+            ids(stream.range(StreamId::MIN, StreamId::MAX, false, 10)),
+            vec![8, 9, 10]
+        );
+        assert_eq!(
+            stream.trim(&trim(TrimStrategy::MaxLen(0), false, None), 100),
+            3
+        );
+        assert!(stream.is_empty());
+        assert_eq!(stream.last_id(), id(10, 0));
+    }
+}
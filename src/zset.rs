@@ -0,0 +1,776 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use bytes::Bytes;
+
+use crate::{
+    db::{self, parse_float},
+    error::CmdError,
+    set::SetOp,
+};
+
+const MAX_LEVEL: usize = 32;
+// 空指针
+const NIL: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+struct Level {
+    forward: usize,
+    // 到forward跨过的节点数, 用于计算排名
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: usize,
+    levels: Vec<Level>,
+}
+
+// 按(score, member)排序
+fn cmp_entry(score: f64, member: &[u8], other_score: f64, other_member: &[u8]) -> Ordering {
+    score
+        .partial_cmp(&other_score)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| member.cmp(other_member))
+}
+
+/// 与redis相同的跳表, 按(score, member)升序排列, 每层记录跨度以支持按排名访问;
+/// 节点存放在数组中并用下标相连, 下标0为头节点, 删除的位置会被复用
+#[derive(Debug, Clone)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    level: usize,
+    len: usize,
+    // xorshift随机数状态
+    seed: u64,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: NIL,
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0
+                };
+                MAX_LEVEL
+            ],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            level: 1,
+            len: 0,
+            seed: db::random_u64() | 1,
+        }
+    }
+}
+
+impl SkipList {
+    // 每升一层的概率为1/4
+    fn random_level(&mut self) -> usize {
+        let mut level = 1;
+        while level < MAX_LEVEL {
+            self.seed ^= self.seed << 13;
+            self.seed ^= self.seed >> 7;
+            self.seed ^= self.seed << 17;
+            if self.seed & 3 != 0 {
+                break;
+            }
+            level += 1;
+        }
+        level
+    }
+
+    fn forward(&self, x: usize, i: usize) -> usize {
+        self.nodes[x].levels[i].forward
+    }
+
+    // 节点x是否排在(score, member)之前
+    fn before(&self, x: usize, score: f64, member: &[u8]) -> bool {
+        let node = &self.nodes[x];
+        cmp_entry(node.score, &node.member, score, member) == Ordering::Less
+    }
+
+    /// 插入一个不存在的成员
+    fn insert(&mut self, score: f64, member: Bytes) {
+        let mut update = [0; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = 0;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !self.before(next, score, &member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        let level = self.random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = 0;
+                self.nodes[0].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+        let node = Node {
+            member,
+            score,
+            backward: if update[0] == 0 { NIL } else { update[0] },
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0
+                };
+                level
+            ],
+        };
+        let x = match self.free.pop() {
+            Some(x) => {
+                self.nodes[x] = node;
+                x
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for i in 0..level {
+            let prev = update[i];
+            let prev_span = self.nodes[prev].levels[i].span;
+            self.nodes[x].levels[i] = Level {
+                forward: self.forward(prev, i),
+                span: prev_span - (rank[0] - rank[i]),
+            };
+            self.nodes[prev].levels[i] = Level {
+                forward: x,
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*prev].levels[i].span += 1;
+        }
+        let next = self.forward(x, 0);
+        if next != NIL {
+            self.nodes[next].backward = x;
+        }
+        self.len += 1;
+    }
+
+    /// 删除成员, 返回是否存在
+    fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [0; MAX_LEVEL];
+        let mut x = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !self.before(next, score, member) {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+        let x = self.forward(x, 0);
+        if x == NIL || self.nodes[x].score != score || self.nodes[x].member != member {
+            return false;
+        }
+        for (i, prev) in update.iter().enumerate().take(self.level) {
+            if self.forward(*prev, i) == x {
+                let Level { forward, span } = self.nodes[x].levels[i];
+                let level = &mut self.nodes[*prev].levels[i];
+                level.span = level.span + span - 1;
+                level.forward = forward;
+            } else {
+                self.nodes[*prev].levels[i].span -= 1;
+            }
+        }
+        let next = self.forward(x, 0);
+        if next != NIL {
+            self.nodes[next].backward = self.nodes[x].backward;
+        }
+        while self.level > 1 && self.forward(0, self.level - 1) == NIL {
+            self.level -= 1;
+        }
+        self.len -= 1;
+        // 释放成员和层数组的内存
+        self.nodes[x].member = Bytes::new();
+        self.nodes[x].levels = Vec::new();
+        self.free.push(x);
+        true
+    }
+
+    /// 从0开始的排名
+    fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL {
+                    break;
+                }
+                let node = &self.nodes[next];
+                if cmp_entry(node.score, &node.member, score, member) == Ordering::Greater {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if x != 0 && self.nodes[x].member == member {
+                return Some(rank - 1);
+            }
+        }
+        None
+    }
+
+    /// 排名为rank(从0开始)的节点
+    fn by_rank(&self, rank: usize) -> Option<usize> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || traversed + self.nodes[x].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    // 第一个满足after_min的节点, 节点按顺序排列时after_min是单调的
+    fn first_where(&self, after_min: impl Fn(&Node) -> bool) -> Option<usize> {
+        let mut x = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || after_min(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        Some(self.forward(x, 0)).filter(|x| *x != NIL)
+    }
+
+    // 最后一个满足before_max的节点
+    fn last_where(&self, before_max: impl Fn(&Node) -> bool) -> Option<usize> {
+        let mut x = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(x, i);
+                if next == NIL || !before_max(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        Some(x).filter(|x| *x != 0)
+    }
+
+    // 从start开始向一个方向遍历, 跳过offset个后取出满足in_range的节点直到取够count个
+    fn collect(
+        &self,
+        start: Option<usize>,
+        rev: bool,
+        offset: usize,
+        count: Option<usize>,
+        in_range: impl Fn(&Node) -> bool,
+    ) -> Vec<(Bytes, f64)> {
+        let step = |x: usize| {
+            let next = if rev {
+                self.nodes[x].backward
+            } else {
+                self.forward(x, 0)
+            };
+            Some(next).filter(|next| *next != NIL)
+        };
+        let mut x = start;
+        for _ in 0..offset {
+            x = x.and_then(step);
+        }
+        let mut items = Vec::new();
+        while let Some(i) = x {
+            let node = &self.nodes[i];
+            if count.is_some_and(|count| items.len() >= count) || !in_range(node) {
+                break;
+            }
+            items.push((node.member.clone(), node.score));
+            x = step(i);
+        }
+        items
+    }
+}
+
+/// ZRANGE BYSCORE等的分数区间, "("表示不包含端点
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    pub min: f64,
+    pub minex: bool,
+    pub max: f64,
+    pub maxex: bool,
+}
+
+impl ScoreRange {
+    pub fn parse(min: &[u8], max: &[u8]) -> Result<Self, CmdError> {
+        fn bound(src: &[u8]) -> Result<(f64, bool), CmdError> {
+            let (src, exclusive) = match src.strip_prefix(b"(") {
+                Some(src) => (src, true),
+                None => (src, false),
+            };
+            let value = parse_float(src).map_err(|_| CmdError::MinMaxNotFloat)?;
+            Ok((value, exclusive))
+        }
+        let ((min, minex), (max, maxex)) = (bound(min)?, bound(max)?);
+        Ok(ScoreRange {
+            min,
+            minex,
+            max,
+            maxex,
+        })
+    }
+
+    pub fn gte_min(&self, score: f64) -> bool {
+        if self.minex {
+            score > self.min
+        } else {
+            score >= self.min
+        }
+    }
+
+    pub fn lte_max(&self, score: f64) -> bool {
+        if self.maxex {
+            score < self.max
+        } else {
+            score <= self.max
+        }
+    }
+}
+
+/// BYLEX区间的端点: "-", "+", "[member"或"(member"
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    NegInf,
+    PosInf,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl LexRange {
+    pub fn parse(min: &Bytes, max: &Bytes) -> Result<Self, CmdError> {
+        fn bound(src: &Bytes) -> Result<LexBound, CmdError> {
+            match src.first() {
+                Some(b'-') if src.len() == 1 => Ok(LexBound::NegInf),
+                Some(b'+') if src.len() == 1 => Ok(LexBound::PosInf),
+                Some(b'[') => Ok(LexBound::Inclusive(src.slice(1..))),
+                Some(b'(') => Ok(LexBound::Exclusive(src.slice(1..))),
+                _ => Err(CmdError::MinMaxNotString),
+            }
+        }
+        Ok(LexRange {
+            min: bound(min)?,
+            max: bound(max)?,
+        })
+    }
+
+    pub fn gte_min(&self, member: &[u8]) -> bool {
+        match &self.min {
+            LexBound::NegInf => true,
+            LexBound::PosInf => false,
+            LexBound::Inclusive(min) => member >= &min[..],
+            LexBound::Exclusive(min) => member > &min[..],
+        }
+    }
+
+    pub fn lte_max(&self, member: &[u8]) -> bool {
+        match &self.max {
+            LexBound::NegInf => false,
+            LexBound::PosInf => true,
+            LexBound::Inclusive(max) => member <= &max[..],
+            LexBound::Exclusive(max) => member < &max[..],
+        }
+    }
+}
+
+/// 有序集合: 成员到分数的哈希表用于O(1)查分数, 跳表用于按排名和分数区间访问
+#[derive(Debug, Clone, Default)]
+pub struct ZSet {
+    dict: HashMap<Bytes, f64>,
+    list: SkipList,
+}
+
+impl PartialEq for ZSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.dict == other.dict
+    }
+}
+
+impl ZSet {
+    pub fn len(&self) -> usize {
+        self.dict.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dict.is_empty()
+    }
+
+    /// OBJECT ENCODING中的编码名
+    pub fn encoding(&self) -> &'static str {
+        "skiplist"
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.dict.get(member).copied()
+    }
+
+    /// 所有成员及分数, 顺序不确定
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> + '_ {
+        self.dict.iter().map(|(member, score)| (member, *score))
+    }
+
+    /// 写入成员的分数, 返回是否为新成员
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        match self.dict.insert(member.clone(), score) {
+            Some(old) if old.to_bits() == score.to_bits() => false,
+            Some(old) => {
+                self.list.remove(old, &member);
+                self.list.insert(score, member);
+                false
+            }
+            None => {
+                self.list.insert(score, member);
+                true
+            }
+        }
+    }
+
+    /// 删除成员, 返回成员是否存在
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.dict.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// 从0开始的排名, rev表示按分数从高到低
+    pub fn rank(&self, member: &[u8], rev: bool) -> Option<usize> {
+        let rank = self.list.rank(self.score(member)?, member)?;
+        Some(if rev { self.len() - 1 - rank } else { rank })
+    }
+
+    /// 弹出最多count个分数最低(max时为最高)的成员
+    pub fn pop(&mut self, max: bool, count: usize) -> Vec<(Bytes, f64)> {
+        let count = count.min(self.len());
+        if count == 0 {
+            return vec![];
+        }
+        let items = self.range_by_rank(0, count - 1, max);
+        for (member, _) in &items {
+            self.remove(member);
+        }
+        items
+    }
+
+    /// 排名在[start, end]之间的成员, 调用者保证end小于长度
+    pub fn range_by_rank(&self, start: usize, end: usize, rev: bool) -> Vec<(Bytes, f64)> {
+        let first = if rev { self.len() - 1 - start } else { start };
+        self.list.collect(
+            self.list.by_rank(first),
+            rev,
+            0,
+            Some(end + 1 - start),
+            |_| true,
+        )
+    }
+
+    /// 分数在区间内的成员, 跳过offset个后最多返回count个
+    pub fn range_by_score(
+        &self,
+        range: &ScoreRange,
+        rev: bool,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        if rev {
+            let start = self.list.last_where(|node| range.lte_max(node.score));
+            self.list
+                .collect(start, true, offset, count, |node| range.gte_min(node.score))
+        } else {
+            let start = self.list.first_where(|node| range.gte_min(node.score));
+            self.list.collect(start, false, offset, count, |node| {
+                range.lte_max(node.score)
+            })
+        }
+    }
+
+    /// 成员在字典序区间内的部分, 只在所有分数相同时有意义
+    pub fn range_by_lex(
+        &self,
+        range: &LexRange,
+        rev: bool,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        if rev {
+            let start = self.list.last_where(|node| range.lte_max(&node.member));
+            self.list.collect(start, true, offset, count, |node| {
+                range.gte_min(&node.member)
+            })
+        } else {
+            let start = self.list.first_where(|node| range.gte_min(&node.member));
+            self.list.collect(start, false, offset, count, |node| {
+                range.lte_max(&node.member)
+            })
+        }
+    }
+}
+
+/// ZUNIONSTORE等对同一成员在多个来源中的分数的合并方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+// 与redis一致, inf + -inf和0 * inf的结果视为0
+fn nan_to_zero(score: f64) -> f64 {
+    if score.is_nan() {
+        0.0
+    } else {
+        score
+    }
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            Aggregate::Sum => nan_to_zero(a + b),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+/// 成员及其分数的列表
+pub type ScoredMembers = Vec<(Bytes, f64)>;
+
+/// ZUNION/ZINTER/ZDIFF的运算, 不存在的key为None; weights为空时权重都为1,
+/// ZDIFF直接使用第一个来源中的分数; 结果按(score, member)排序
+pub fn combine(
+    op: SetOp,
+    sources: &[Option<ScoredMembers>],
+    weights: &[f64],
+    aggregate: Aggregate,
+) -> ScoredMembers {
+    let weighted = |i: usize, score: f64| nan_to_zero(score * weights.get(i).unwrap_or(&1.0));
+    let result: HashMap<Bytes, f64> = match op {
+        SetOp::Union => {
+            let mut result = HashMap::new();
+            for (i, source) in sources.iter().enumerate() {
+                for (member, score) in source.iter().flatten() {
+                    let score = weighted(i, *score);
+                    result
+                        .entry(member.clone())
+                        .and_modify(|s| *s = aggregate.apply(*s, score))
+                        .or_insert(score);
+                }
+            }
+            result
+        }
+        SetOp::Inter => {
+            let Some(sources) = sources
+                .iter()
+                .map(Option::as_ref)
+                .collect::<Option<Vec<_>>>()
+            else {
+                return vec![];
+            };
+            let Some((first, rest)) = sources.split_first() else {
+                return vec![];
+            };
+            let mut result: HashMap<_, _> = first
+                .iter()
+                .map(|(member, score)| (member.clone(), weighted(0, *score)))
+                .collect();
+            for (i, source) in rest.iter().enumerate() {
+                let scores: HashMap<_, _> = source.iter().map(|(m, s)| (m, *s)).collect();
+                result.retain(|member, s| match scores.get(member) {
+                    Some(score) => {
+                        *s = aggregate.apply(*s, weighted(i + 1, *score));
+                        true
+                    }
+                    None => false,
+                });
+            }
+            result
+        }
+        SetOp::Diff => match sources.split_first() {
+            Some((Some(first), rest)) => {
+                let others: HashSet<_> = rest.iter().flatten().flatten().map(|(m, _)| m).collect();
+                first
+                    .iter()
+                    .filter(|(member, _)| !others.contains(member))
+                    .cloned()
+                    .collect()
+            }
+            _ => HashMap::new(),
+        },
+    };
+    let mut items: Vec<_> = result.into_iter().collect();
+    items.sort_by(|a, b| cmp_entry(a.1, &a.0, b.1, &b.0));
+    items
+}
+
+#[cfg(test)]
+mod zset_test {
+    use super::*;
+
+    #[test]
+    fn test_skiplist() {
+        let mut zset = ZSet::default();
+        let mut expected = Vec::new();
+        // 分数有重复, 相同分数按成员排序
+        for i in 0..200u64 {
+            let member = Bytes::from(format!("m{:03}", (i * 37) % 200));
+            let score = ((i * 7) % 50) as f64;
+            assert!(zset.insert(member.clone(), score));
+            expected.push((member, score));
+        }
+        // 修改分数
+        assert!(!zset.insert(Bytes::from("m000"), -1.0));
+        expected.retain(|(m, _)| m != "m000");
+        expected.push((Bytes::from("m000"), -1.0));
+        for i in (0..200).step_by(3) {
+            let member = format!("m{:03}", i);
+            assert!(zset.remove(member.as_bytes()));
+            expected.retain(|(m, _)| *m != member);
+        }
+        assert!(!zset.remove(b"m003"));
+        expected.sort_by(|a, b| cmp_entry(a.1, &a.0, b.1, &b.0));
+        assert_eq!(zset.len(), expected.len());
+        for (rank, (member, _)) in expected.iter().enumerate() {
+            assert_eq!(zset.rank(member, false), Some(rank));
+            assert_eq!(zset.rank(member, true), Some(expected.len() - 1 - rank));
+        }
+        let len = expected.len();
+        assert_eq!(zset.range_by_rank(0, len - 1, false), expected);
+        assert_eq!(zset.range_by_rank(5, 7, true), {
+            let mut rev = expected.clone();
+            rev.reverse();
+            rev[5..=7].to_vec()
+        });
+
+        let range = ScoreRange::parse(b"(10", b"20").unwrap();
+        let in_range: Vec<_> = expected
+            .iter()
+            .filter(|(_, s)| *s > 10.0 && *s <= 20.0)
+            .cloned()
+            .collect();
+        assert_eq!(zset.range_by_score(&range, false, 0, None), in_range);
+        assert_eq!(
+            zset.range_by_score(&range, false, 2, Some(3)),
+            in_range[2..5].to_vec()
+        );
+        let mut rev = in_range.clone();
+        rev.reverse();
+        assert_eq!(zset.range_by_score(&range, true, 0, None), rev);
+        let empty = ScoreRange::parse(b"5", b"(5").unwrap();
+        assert!(zset.range_by_score(&empty, false, 0, None).is_empty());
+        assert_eq!(
+            ScoreRange::parse(b"(x", b"1"),
+            Err(CmdError::MinMaxNotFloat)
+        );
+    }
+
+    #[test]
+    fn test_range_by_lex() {
+        let mut zset = ZSet::default();
+        for member in ["a", "b", "c", "d", "e"] {
+            zset.insert(Bytes::from(member), 0.0);
+        }
+        let members = |items: Vec<(Bytes, f64)>| -> Vec<Bytes> {
+            items.into_iter().map(|(m, _)| m).collect()
+        };
+        let range = LexRange::parse(&Bytes::from("(b"), &Bytes::from("[d")).unwrap();
+        assert_eq!(
+            members(zset.range_by_lex(&range, false, 0, None)),
+            vec![Bytes::from("c"), Bytes::from("d")]
+        );
+        let range = LexRange::parse(&Bytes::from("-"), &Bytes::from("+")).unwrap();
+        assert_eq!(
+            members(zset.range_by_lex(&range, true, 1, Some(2))),
+            vec![Bytes::from("d"), Bytes::from("c")]
+        );
+        assert_eq!(
+            LexRange::parse(&Bytes::from("a"), &Bytes::from("+")),
+            Err(CmdError::MinMaxNotString)
+        );
+    }
+
+    #[test]
+    fn test_pop_and_combine() {
+        let b = |s: &'static str| Bytes::from(s);
+        let mut zset = ZSet::default();
+        for (score, member) in [(1.0, "a"), (2.0, "b"), (3.0, "c")] {
+            zset.insert(b(member), score);
+        }
+        assert_eq!(zset.pop(true, 2), vec![(b("c"), 3.0), (b("b"), 2.0)]);
+        assert_eq!(zset.pop(false, 5), vec![(b("a"), 1.0)]);
+        assert!(zset.is_empty() && zset.pop(false, 1).is_empty());
+
+        let x = Some(vec![(b("a"), 1.0), (b("b"), 2.0)]);
+        let y = Some(vec![(b("b"), 3.0), (b("c"), f64::INFINITY)]);
+        let sources = [x.clone(), y.clone()];
+        assert_eq!(
+            combine(SetOp::Union, &sources, &[2.0, 1.0], Aggregate::Sum),
+            vec![(b("a"), 2.0), (b("b"), 7.0), (b("c"), f64::INFINITY)]
+        );
+        assert_eq!(
+            combine(SetOp::Inter, &sources, &[], Aggregate::Max),
+            vec![(b("b"), 3.0)]
+        );
+        // 0 * inf视为0
+        assert_eq!(
+            combine(
+                SetOp::Union,
+                std::slice::from_ref(&y),
+                &[0.0],
+                Aggregate::Sum
+            ),
+            vec![(b("b"), 0.0), (b("c"), 0.0)]
+        );
+        assert!(combine(SetOp::Inter, &[x.clone(), None], &[], Aggregate::Sum).is_empty());
+        assert_eq!(
+            combine(SetOp::Diff, &[x, None, y], &[], Aggregate::Sum),
+            vec![(b("a"), 1.0)]
+        );
+    }
+}